@@ -0,0 +1,10 @@
+//! Passes the linked FFmpeg's version flags from ffmpeg-sys-the-third on as
+//! cfgs, so shear can follow ffmpeg-the-third's version-specific APIs.
+
+fn main() {
+    println!("cargo::rustc-check-cfg=cfg(ffmpeg_5_1)");
+    // Only set with the ffmpeg feature, when FFmpeg is 5.1 or newer
+    if std::env::var_os("DEP_FFMPEG_FFMPEG_5_1").is_some() {
+        println!("cargo::rustc-cfg=ffmpeg_5_1");
+    }
+}
@@ -0,0 +1,211 @@
+//! Chunk byte ranges for byte-range HLS packaging (`--byte-ranges`).
+//!
+//! HLS can address segments of a single file with `#EXT-X-BYTERANGE`, but
+//! a segment's bytes have to start at a keyframe. For every chunk boundary
+//! this finds the last keyframe at or before it, from the packet
+//! positions the demuxer reports, so a packager can write the playlist
+//! without parsing the container itself.
+
+use crate::timecode;
+use anyhow::{bail, Result};
+use serde::Serialize;
+
+/// A video packet as the demuxer reports it, in decode order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VideoPacket {
+    /// Presentation timestamp, in the stream's time base
+    pub pts: i64,
+    /// Byte offset in the file, if the demuxer knows it
+    pub offset: Option<u64>,
+    pub key: bool,
+}
+
+/// A keyframe's presentation-order frame number and byte offset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Keyframe {
+    pub frame: usize,
+    pub offset: u64,
+}
+
+/// Keyframes with a known position, in presentation order. Frames are
+/// numbered by sorting the packets' timestamps, like the decoder's output.
+pub fn keyframes(packets: &[VideoPacket]) -> Vec<Keyframe> {
+    let mut order: Vec<&VideoPacket> = packets.iter().collect();
+    order.sort_by_key(|packet| packet.pts);
+    order
+        .into_iter()
+        .enumerate()
+        .filter(|(_, packet)| packet.key)
+        .filter_map(|(frame, packet)| packet.offset.map(|offset| Keyframe { frame, offset }))
+        .collect()
+}
+
+/// Everything written to a `--byte-ranges` file.
+#[derive(Serialize, Debug)]
+pub struct ByteRanges {
+    pub fps_num: u32,
+    pub fps_den: u32,
+    pub total_frames: usize,
+    pub file_size: u64,
+    /// Bytes before the first segment, such as container headers
+    pub header_length: u64,
+    pub segments: Vec<Segment>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Segment {
+    pub index: usize,
+    /// First chunk boundary in the segment
+    pub start: usize,
+    /// Exclusive end frame: the next segment's start
+    pub end: usize,
+    /// Keyframe the segment's bytes start at: the last one at or before
+    /// `start`, or the first if `start` precedes it
+    pub keyframe: usize,
+    /// True if the keyframe is the boundary itself
+    pub aligned: bool,
+    /// Byte offset of the keyframe's packet
+    pub offset: u64,
+    /// Bytes up to the next segment's offset, or the end of the file
+    pub length: u64,
+    /// Time from this segment's keyframe to the next one's, in seconds,
+    /// for `#EXTINF`
+    pub duration: f64,
+}
+
+/// Byte ranges for each chunk of `chunk_starts`. Chunks that start within
+/// the same GOP share a segment, since the file can only be split at
+/// keyframes.
+pub fn byte_ranges(
+    chunk_starts: &[usize],
+    total_frames: usize,
+    keyframes: &[Keyframe],
+    file_size: u64,
+    fps_num: u32,
+    fps_den: u32,
+) -> Result<ByteRanges> {
+    let Some(first) = keyframes.first() else {
+        bail!("The video stream has no keyframes with known byte offsets");
+    };
+    let seconds = |frame: usize| timecode::frame_to_nanos(frame, fps_num, fps_den) as f64 / 1e9;
+
+    // (chunk start, keyframe) for the first chunk of each GOP
+    let mut starts: Vec<(usize, Keyframe)> = Vec::new();
+    for &start in chunk_starts {
+        let at = keyframes.partition_point(|keyframe| keyframe.frame <= start);
+        let keyframe = keyframes[at.saturating_sub(1)];
+        if starts
+            .last()
+            .is_none_or(|(_, last)| last.frame != keyframe.frame)
+        {
+            starts.push((start, keyframe));
+        }
+    }
+
+    let segments = starts
+        .iter()
+        .enumerate()
+        .map(|(index, &(start, keyframe))| {
+            let next = starts.get(index + 1);
+            let next_offset = next.map_or(file_size, |(_, next)| next.offset);
+            let next_keyframe = next.map_or(total_frames, |(_, next)| next.frame);
+            Segment {
+                index,
+                start,
+                end: next.map_or(total_frames, |&(start, _)| start),
+                keyframe: keyframe.frame,
+                aligned: keyframe.frame == start,
+                offset: keyframe.offset,
+                length: next_offset.saturating_sub(keyframe.offset),
+                duration: seconds(next_keyframe) - seconds(keyframe.frame),
+            }
+        })
+        .collect();
+
+    Ok(ByteRanges {
+        fps_num,
+        fps_den,
+        total_frames,
+        file_size,
+        header_length: first.offset,
+        segments,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packet(pts: i64, offset: u64, key: bool) -> VideoPacket {
+        VideoPacket {
+            pts,
+            offset: Some(offset),
+            key,
+        }
+    }
+
+    #[test]
+    fn test_keyframes() {
+        // I0 P2 B1 | I4 B3: the B-frame shown before the second keyframe
+        // follows it in the file
+        let packets = [
+            packet(0, 100, true),
+            packet(2, 200, false),
+            packet(1, 300, false),
+            packet(4, 400, true),
+            packet(3, 500, false),
+            VideoPacket {
+                pts: 5,
+                offset: None,
+                key: true,
+            },
+        ];
+        assert_eq!(
+            keyframes(&packets),
+            vec![
+                Keyframe {
+                    frame: 0,
+                    offset: 100
+                },
+                Keyframe {
+                    frame: 4,
+                    offset: 400
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_byte_ranges() {
+        let keyframes: Vec<Keyframe> = [(0, 50), (24, 1000), (48, 2000), (72, 3000)]
+            .iter()
+            .map(|&(frame, offset)| Keyframe { frame, offset })
+            .collect();
+        // 30 and 40 fall in the same GOP as 24
+        let ranges = byte_ranges(&[0, 24, 30, 40, 72], 96, &keyframes, 4000, 24, 1).unwrap();
+        assert_eq!(ranges.header_length, 50);
+        let segments: Vec<(usize, usize, usize, bool, u64, u64)> = ranges
+            .segments
+            .iter()
+            .map(|s| (s.start, s.end, s.keyframe, s.aligned, s.offset, s.length))
+            .collect();
+        assert_eq!(
+            segments,
+            vec![
+                (0, 24, 0, true, 50, 950),
+                (24, 72, 24, true, 1000, 2000),
+                (72, 96, 72, true, 3000, 1000),
+            ]
+        );
+        assert_eq!(ranges.segments[1].duration, 2.0);
+
+        // A boundary between keyframes starts at the one before it
+        let ranges = byte_ranges(&[0, 60], 96, &keyframes, 4000, 24, 1).unwrap();
+        let second = &ranges.segments[1];
+        assert_eq!((second.keyframe, second.aligned), (48, false));
+        assert_eq!((second.offset, second.length), (2000, 2000));
+        assert_eq!(second.duration, 2.0);
+
+        assert!(byte_ranges(&[0], 96, &[], 4000, 24, 1).is_err());
+    }
+}
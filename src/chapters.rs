@@ -0,0 +1,276 @@
+//! Automatic chapter generation from scene boundaries, and reading chapter
+//! files back as cut points for `--hints`.
+
+use crate::timecode;
+use anyhow::{bail, Context, Result};
+use clap::ValueEnum;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// Chapter file format
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChapterFormat {
+    /// OGM simple chapters (CHAPTER01=00:00:00.000)
+    Ogm,
+    /// Matroska XML chapters, for mkvmerge
+    Matroska,
+    /// FFmpeg metadata file, for ffmpeg -i meta.txt -map_metadata 1
+    Ffmetadata,
+}
+
+/// Group scenes into chapters of at least `min_frames`.
+///
+/// Each chapter ends at a scene boundary. Once a chapter reaches min_frames,
+/// the strongest boundary within the next min_frames starts the next
+/// chapter, so chapters land on the clearest transitions and stay between
+/// one and two times the minimum where the scene layout allows. A trailing
+/// chapter shorter than min_frames is absorbed into the one before it.
+///
+/// `boundaries` holds (start frame, strength) pairs for every scene start,
+/// sorted by frame. Returns chapter start frames, always beginning at 0.
+pub fn group_chapters(
+    boundaries: &[(usize, f64)],
+    total_frames: usize,
+    min_frames: usize,
+) -> Vec<usize> {
+    let min_frames = min_frames.max(1);
+    let mut chapters = vec![0];
+
+    loop {
+        let current = *chapters.last().expect("chapters start with frame 0");
+        let earliest = current + min_frames;
+        let latest = earliest + min_frames;
+
+        // Candidates must leave room for a full-length final chapter
+        let eligible =
+            |&&(frame, _): &&(usize, f64)| frame >= earliest && frame + min_frames <= total_frames;
+
+        let in_window = boundaries
+            .iter()
+            .filter(eligible)
+            .filter(|&&(frame, _)| frame <= latest)
+            .max_by(|a, b| a.1.total_cmp(&b.1).then(b.0.cmp(&a.0)));
+        let next = in_window.or_else(|| boundaries.iter().find(eligible));
+
+        match next {
+            Some(&(frame, _)) => chapters.push(frame),
+            None => break,
+        }
+    }
+
+    chapters
+}
+
+/// Write chapter start frames in the given format.
+pub fn write_chapters(
+    path: &Path,
+    format: ChapterFormat,
+    chapters: &[usize],
+    total_frames: usize,
+    fps_num: u32,
+    fps_den: u32,
+) -> Result<()> {
+    let file =
+        File::create(path).with_context(|| format!("Failed to create chapter file {:?}", path))?;
+    let mut w = BufWriter::new(file);
+
+    match format {
+        ChapterFormat::Ogm => {
+            for (i, &start) in chapters.iter().enumerate() {
+                let n = i + 1;
+                writeln!(
+                    w,
+                    "CHAPTER{:02}={}",
+                    n,
+                    timecode::format_millis(start, fps_num, fps_den)
+                )?;
+                writeln!(w, "CHAPTER{:02}NAME=Chapter {:02}", n, n)?;
+            }
+        }
+        ChapterFormat::Matroska => {
+            writeln!(w, "<?xml version=\"1.0\"?>")?;
+            writeln!(w, "<!DOCTYPE Chapters SYSTEM \"matroskachapters.dtd\">")?;
+            writeln!(w, "<Chapters>")?;
+            writeln!(w, "  <EditionEntry>")?;
+            for (i, &start) in chapters.iter().enumerate() {
+                let end = chapters.get(i + 1).copied().unwrap_or(total_frames);
+                writeln!(w, "    <ChapterAtom>")?;
+                writeln!(
+                    w,
+                    "      <ChapterTimeStart>{}</ChapterTimeStart>",
+                    timecode::format_nanos(start, fps_num, fps_den)
+                )?;
+                writeln!(
+                    w,
+                    "      <ChapterTimeEnd>{}</ChapterTimeEnd>",
+                    timecode::format_nanos(end, fps_num, fps_den)
+                )?;
+                writeln!(w, "      <ChapterDisplay>")?;
+                writeln!(
+                    w,
+                    "        <ChapterString>Chapter {:02}</ChapterString>",
+                    i + 1
+                )?;
+                writeln!(w, "        <ChapterLanguage>und</ChapterLanguage>")?;
+                writeln!(w, "      </ChapterDisplay>")?;
+                writeln!(w, "    </ChapterAtom>")?;
+            }
+            writeln!(w, "  </EditionEntry>")?;
+            writeln!(w, "</Chapters>")?;
+        }
+        ChapterFormat::Ffmetadata => {
+            writeln!(w, ";FFMETADATA1")?;
+            for (i, &start) in chapters.iter().enumerate() {
+                let end = chapters.get(i + 1).copied().unwrap_or(total_frames);
+                // Frame-exact timebase, so no rounding is involved
+                writeln!(w)?;
+                writeln!(w, "[CHAPTER]")?;
+                writeln!(w, "TIMEBASE={}/{}", fps_den, fps_num)?;
+                writeln!(w, "START={}", start)?;
+                writeln!(w, "END={}", end)?;
+                writeln!(w, "title=Chapter {:02}", i + 1)?;
+            }
+        }
+    }
+
+    w.flush()?;
+    Ok(())
+}
+
+/// Chapter start frames from a file in any `ChapterFormat`, or None if
+/// `contents` isn't a chapter file.
+pub fn parse_chapters(contents: &str, fps_num: u32, fps_den: u32) -> Result<Option<Vec<usize>>> {
+    let frame = |line: usize, time: &str| match timecode::parse_seconds(time) {
+        Some(seconds) => Ok(timecode::seconds_to_frame(seconds, fps_num, fps_den)),
+        None => bail!("line {}: invalid chapter time {:?}", line, time),
+    };
+
+    let first = contents.trim_start();
+    if first.starts_with(";FFMETADATA1") {
+        return parse_ffmetadata(contents, fps_num, fps_den).map(Some);
+    }
+    if first.starts_with("<?xml") || first.starts_with("<Chapters") {
+        let mut chapters = Vec::new();
+        for (i, line) in contents.lines().enumerate() {
+            if let Some(rest) = line.trim().strip_prefix("<ChapterTimeStart>") {
+                let time = rest.split('<').next().unwrap_or(rest);
+                chapters.push(frame(i + 1, time)?);
+            }
+        }
+        return Ok(Some(chapters));
+    }
+    if first.starts_with("CHAPTER") {
+        let mut chapters = Vec::new();
+        for (i, line) in contents.lines().enumerate() {
+            // CHAPTER01=..., skipping CHAPTER01NAME=...
+            let Some((key, time)) = line.trim().split_once('=') else {
+                continue;
+            };
+            let number = key.strip_prefix("CHAPTER").unwrap_or("");
+            if !number.is_empty() && number.bytes().all(|b| b.is_ascii_digit()) {
+                chapters.push(frame(i + 1, time)?);
+            }
+        }
+        return Ok(Some(chapters));
+    }
+    Ok(None)
+}
+
+/// `[CHAPTER]` starts, converting each from its own `TIMEBASE` (FFmpeg's
+/// default is nanoseconds).
+fn parse_ffmetadata(contents: &str, fps_num: u32, fps_den: u32) -> Result<Vec<usize>> {
+    let mut chapters = Vec::new();
+    let mut in_chapter = false;
+    let mut timebase = (1u128, 1_000_000_000u128);
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.starts_with('[') {
+            in_chapter = line == "[CHAPTER]";
+            timebase = (1, 1_000_000_000);
+            continue;
+        }
+        if !in_chapter {
+            continue;
+        }
+        if let Some(value) = line.strip_prefix("TIMEBASE=") {
+            let parsed = value
+                .split_once('/')
+                .and_then(|(num, den)| Some((num.parse().ok()?, den.parse().ok()?)));
+            match parsed {
+                Some((num, den)) if den > 0 => timebase = (num, den),
+                _ => bail!("line {}: invalid TIMEBASE {:?}", i + 1, value),
+            }
+        } else if let Some(value) = line.strip_prefix("START=") {
+            let Ok(start) = value.parse::<u128>() else {
+                bail!("line {}: invalid START {:?}", i + 1, value);
+            };
+            // start * timebase seconds, in frames, rounded to the nearest
+            let num = start * timebase.0 * fps_num as u128;
+            let den = timebase.1 * fps_den.max(1) as u128;
+            chapters.push(((num + den / 2) / den) as usize);
+        }
+    }
+    Ok(chapters)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempPath;
+
+    #[test]
+    fn test_group_chapters_prefers_strong_boundaries() {
+        let boundaries = [
+            (0, 0.0),
+            (80, 5.0),
+            (120, 1.5),
+            (150, 9.0),
+            (190, 2.0),
+            (260, 1.2),
+            (330, 3.0),
+        ];
+        // Min 100: first window is 100..=200, strongest there is 150.
+        // Next window is 250..=350, but 330 would leave a 70 frame tail.
+        assert_eq!(group_chapters(&boundaries, 400, 100), vec![0, 150, 260]);
+    }
+
+    #[test]
+    fn test_group_chapters_falls_back_past_window() {
+        let boundaries = [(0, 0.0), (50, 4.0), (500, 1.0)];
+        // Nothing between 100 and 200, so the next boundary after is used
+        assert_eq!(group_chapters(&boundaries, 1000, 100), vec![0, 500]);
+    }
+
+    #[test]
+    fn test_parse_written_chapters() {
+        let path = TempPath::new("chapters");
+        let chapters = [0, 1439, 7200];
+        for format in [
+            ChapterFormat::Ogm,
+            ChapterFormat::Matroska,
+            ChapterFormat::Ffmetadata,
+        ] {
+            write_chapters(&path, format, &chapters, 9000, 24000, 1001).unwrap();
+            let contents = std::fs::read_to_string(&path).unwrap();
+            assert_eq!(
+                parse_chapters(&contents, 24000, 1001).unwrap(),
+                Some(chapters.to_vec()),
+                "{:?}",
+                format
+            );
+        }
+
+        // FFmpeg's default timebase is nanoseconds
+        let contents = ";FFMETADATA1\n[CHAPTER]\nSTART=2000000000\n";
+        assert_eq!(parse_chapters(contents, 25, 1).unwrap(), Some(vec![50]));
+        assert!(parse_chapters("CHAPTER01=soon\n", 25, 1).is_err());
+        assert_eq!(parse_chapters("0\n100\n", 25, 1).unwrap(), None);
+    }
+
+    #[test]
+    fn test_group_chapters_short_video() {
+        let boundaries = [(0, 0.0), (40, 4.0)];
+        assert_eq!(group_chapters(&boundaries, 90, 100), vec![0]);
+    }
+}
@@ -0,0 +1,195 @@
+//! Checksums tying a scene file to its source, so an encode farm can
+//! confirm it is encoding the video a scene file was made from.
+//!
+//! Hashing a whole multi-gigabyte source would take longer than detection
+//! on some inputs, so the input checksum covers the file length and evenly
+//! spaced samples of its bytes. That catches a different or re-encoded
+//! file, not a single changed byte in the middle of a large one.
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// Name written alongside the checksums, so the scheme can change later
+pub const ALGORITHM: &str = "fnv1a64-sampled";
+
+/// Number of blocks sampled from inputs too large to hash in full
+const SAMPLES: u64 = 16;
+
+/// Size of each sampled block
+const SAMPLE_BYTES: u64 = 64 * 1024;
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// Checksums embedded in a JSON scene file or manifest.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Checksum {
+    pub algorithm: String,
+    /// Sampled bytes of the input file
+    pub input: String,
+    /// Total frame count and scene or chunk start frames
+    pub boundaries: String,
+}
+
+impl Checksum {
+    /// Checksums of `input` and of the boundaries written to a scene file.
+    pub fn new(input: &Path, starts: &[usize], total_frames: usize) -> Result<Self> {
+        Ok(Checksum {
+            algorithm: ALGORITHM.to_string(),
+            input: input_checksum(input)?,
+            boundaries: boundaries_checksum(starts, total_frames),
+        })
+    }
+}
+
+fn fnv1a(mut hash: u64, bytes: &[u8]) -> u64 {
+    for &byte in bytes {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(FNV_PRIME);
+    }
+    hash
+}
+
+/// Checksum of the input file's length and sampled contents; files of at
+/// most `SAMPLES` blocks are hashed in full.
+pub fn input_checksum(path: &Path) -> Result<String> {
+    let mut file = File::open(path).with_context(|| format!("Failed to open input {:?}", path))?;
+    let len = file.metadata()?.len();
+
+    let mut hash = fnv1a(FNV_OFFSET, &len.to_le_bytes());
+    if len <= SAMPLES * SAMPLE_BYTES {
+        let mut contents = Vec::new();
+        file.read_to_end(&mut contents)?;
+        hash = fnv1a(hash, &contents);
+    } else {
+        // First and last blocks included, the rest spread evenly between
+        let mut block = vec![0; SAMPLE_BYTES as usize];
+        for sample in 0..SAMPLES {
+            let offset = (len - SAMPLE_BYTES) * sample / (SAMPLES - 1);
+            file.seek(SeekFrom::Start(offset))?;
+            file.read_exact(&mut block)
+                .with_context(|| format!("Failed to read input {:?}", path))?;
+            hash = fnv1a(hash, &block);
+        }
+    }
+    Ok(format!("{:016x}", hash))
+}
+
+/// Checksum of a boundary list and the frame count it ends at.
+pub fn boundaries_checksum(starts: &[usize], total_frames: usize) -> String {
+    let mut hash = fnv1a(FNV_OFFSET, &(total_frames as u64).to_le_bytes());
+    for &start in starts {
+        hash = fnv1a(hash, &(start as u64).to_le_bytes());
+    }
+    format!("{:016x}", hash)
+}
+
+/// Checksum of arbitrary bytes, in the same form as the others.
+pub fn bytes_checksum(bytes: &[u8]) -> String {
+    format!("{:016x}", fnv1a(FNV_OFFSET, bytes))
+}
+
+/// The parts of a JSON scene file or manifest the checksums cover.
+#[derive(Deserialize)]
+struct SignedFile {
+    total_frames: usize,
+    #[serde(alias = "chunks")]
+    scenes: Vec<Start>,
+    checksum: Option<Checksum>,
+}
+
+#[derive(Deserialize)]
+struct Start {
+    start: usize,
+}
+
+/// Result of checking a scene file against a source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Verification {
+    /// The input checksum matches the source
+    pub input: bool,
+    /// The boundaries still match their checksum, i.e. the file's scene
+    /// list hasn't been edited since it was written
+    pub boundaries: bool,
+}
+
+impl Verification {
+    pub fn ok(&self) -> bool {
+        self.input && self.boundaries
+    }
+}
+
+/// Check the checksums embedded in JSON scene file `scenes` against
+/// `input`.
+pub fn verify(scenes: &Path, input: &Path) -> Result<Verification> {
+    let contents = fs::read_to_string(scenes)
+        .with_context(|| format!("Failed to read scene file {:?}", scenes))?;
+    let file: SignedFile = serde_json::from_str(&contents)
+        .with_context(|| format!("Invalid JSON scene file {:?}", scenes))?;
+    let Some(checksum) = file.checksum else {
+        bail!("{:?} has no checksum; write it with --checksum", scenes);
+    };
+    if checksum.algorithm != ALGORITHM {
+        bail!("Unknown checksum algorithm {:?}", checksum.algorithm);
+    }
+
+    let starts: Vec<usize> = file.scenes.iter().map(|s| s.start).collect();
+    Ok(Verification {
+        input: input_checksum(input)? == checksum.input,
+        boundaries: boundaries_checksum(&starts, file.total_frames) == checksum.boundaries,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempPath;
+
+    #[test]
+    fn test_input_checksum() {
+        let small = TempPath::new("checksum-small");
+        fs::write(&small, b"frame data").unwrap();
+        let first = input_checksum(&small).unwrap();
+        assert_eq!(first.len(), 16);
+        fs::write(&small, b"frame datA").unwrap();
+        assert_ne!(input_checksum(&small).unwrap(), first);
+
+        // Large files are sampled: a change in a sampled block is seen
+        let large = TempPath::new("checksum-large");
+        let mut contents = vec![7u8; (SAMPLES * SAMPLE_BYTES * 2) as usize];
+        fs::write(&large, &contents).unwrap();
+        let before = input_checksum(&large).unwrap();
+        *contents.last_mut().unwrap() = 8;
+        fs::write(&large, &contents).unwrap();
+        assert_ne!(input_checksum(&large).unwrap(), before);
+    }
+
+    #[test]
+    fn test_verify() {
+        let input = TempPath::new("checksum-input");
+        let scenes = TempPath::new("checksum-scenes.json");
+        fs::write(&input, b"source video").unwrap();
+        let checksum = Checksum::new(&input, &[0, 100], 300).unwrap();
+        let doc = serde_json::json!({
+            "total_frames": 300,
+            "scenes": [{"start": 0}, {"start": 100}],
+            "checksum": checksum,
+        });
+        fs::write(&scenes, doc.to_string()).unwrap();
+        assert!(verify(&scenes, &input).unwrap().ok());
+
+        // Edited boundaries
+        let edited = doc.to_string().replace(r#""start":100"#, r#""start":120"#);
+        fs::write(&scenes, edited).unwrap();
+        let result = verify(&scenes, &input).unwrap();
+        assert!(result.input && !result.boundaries);
+
+        // A different source
+        fs::write(&scenes, doc.to_string()).unwrap();
+        fs::write(&input, b"other video").unwrap();
+        assert!(!verify(&scenes, &input).unwrap().input);
+    }
+}
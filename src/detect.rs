@@ -0,0 +1,1634 @@
+//! Scene detection pass.
+//!
+//! Drives av-scenechange's `SceneChangeDetector` directly rather than going
+//! through `detect_scene_changes`, so per-frame statistics can be gathered
+//! from the same decoded frames without a second decode.
+
+use crate::ecr::{self, EdgeMap};
+use crate::filters::{downscale_half, half_size, PreFilters};
+use crate::histogram::{self, FrameHistogram};
+use crate::segments::FrameRange;
+use crate::source::{FrameSource, RangeSource};
+use anyhow::Result;
+use av_scenechange::av_decoders::v_frame::{frame::Frame, pixel::Pixel, plane::Plane};
+use av_scenechange::{DetectionOptions, SceneChangeDetector, SceneDetectionSpeed, ScenecutResult};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, sync_channel};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Number of decoded frames buffered ahead of the detector
+const FRAME_PREFETCH_DEPTH: usize = 8;
+
+/// Luma value (8-bit units) at or below which a pixel counts as black.
+/// Limited-range black is 16; this leaves headroom for noise and
+/// soft fades.
+const BLACK_PIXEL_MAX: u32 = 32;
+
+/// Results from a detection pass.
+#[derive(Debug, Clone)]
+pub struct DetectionResults {
+    /// The 0-indexed frame numbers where scene changes were detected
+    pub scene_changes: Vec<usize>,
+    /// Detector scores for each frame. Some frames may not have a score.
+    pub scores: BTreeMap<usize, ScenecutResult>,
+    /// The total number of frames decoded
+    pub frame_count: usize,
+    /// Per-frame statistics, indexed by frame number (empty unless requested)
+    pub frame_stats: Vec<FrameStats>,
+    /// Time spent decoding and pre-filtering frames
+    pub decode_time: Duration,
+    /// Time the detection thread spent analyzing frames. Decoding runs in
+    /// parallel, so this overlaps `decode_time`.
+    pub analysis_time: Duration,
+}
+
+/// Cheap pixel statistics for a single frame.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FrameStats {
+    /// Mean absolute luma difference to the previous frame, in 8-bit units
+    pub luma_diff: f64,
+    /// Fraction of luma pixels that are black
+    pub black_ratio: f64,
+    /// Mean luma, in 8-bit units
+    pub mean_luma: f64,
+    /// Brightest pixel's light level in nits, reading luma as PQ
+    pub max_nits: f64,
+    /// Mean light level in nits, reading luma as PQ
+    pub mean_nits: f64,
+}
+
+/// Stand-in statistics for frames skipped by `PreFilters::exclude`: never
+/// part of a freeze or black segment.
+const EXCLUDED_FRAME_STATS: FrameStats = FrameStats {
+    luma_diff: f64::MAX,
+    black_ratio: 0.0,
+    mean_luma: 0.0,
+    max_nits: 0.0,
+    mean_nits: 0.0,
+};
+
+/// Progress of a detection pass, reported after each analyzed frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PassProgress {
+    /// Frames analyzed so far
+    pub frames: usize,
+    /// Cuts found so far by the first detector (0 for detectors that
+    /// decide cuts at the end)
+    pub cuts: usize,
+    /// Set when the first detector found a cut since the last report.
+    /// Cuts are provisional until the pass ends: ensemble fusion and
+    /// voting can still drop or move them.
+    pub new_cut: Option<usize>,
+}
+
+/// Handle for stopping a detection pass from another thread. Clones share
+/// the same flag, so one can be kept while another is passed to the pass.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    flag: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ask the pass to stop before the next frame.
+    pub fn cancel(&self) {
+        self.flag.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.flag.load(Ordering::Relaxed)
+    }
+}
+
+/// Error from a cancelled pass, holding the results for the frames
+/// analyzed before it stopped. Recover it with `anyhow::Error::downcast`.
+#[derive(Debug)]
+pub struct Cancelled {
+    /// Cuts, scores and statistics up to the last analyzed frame;
+    /// `frame_count` is the number of frames decoded
+    pub partial: DetectionResults,
+}
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Detection cancelled after {} frames",
+            self.partial.frame_count
+        )
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+/// Run scene detection over every frame from the source with the built-in
+/// backends.
+///
+/// Every backend in `backends` sees every frame of the same pass; see
+/// `detect_with` for the rest.
+#[allow(clippy::too_many_arguments)]
+pub fn detect<T: Pixel>(
+    source: &mut dyn FrameSource<T>,
+    opts: DetectionOptions,
+    filters: &PreFilters,
+    collect_stats: bool,
+    backends: &[Backend],
+    min_votes: usize,
+    cancel: Option<&CancellationToken>,
+    progress_callback: Option<&dyn Fn(&PassProgress)>,
+) -> Result<DetectionResults> {
+    assert!(opts.lookahead_distance >= 1);
+
+    let details = source.video_details();
+    filters.validate(&details)?;
+
+    let (width, height) = filters.output_size(details.width, details.height);
+    let new_detector = |size: (usize, usize)| {
+        SceneChangeDetector::<T>::new(
+            size,
+            details.bit_depth,
+            details.frame_rate.recip(),
+            details.chroma_sampling,
+            if opts.detect_flashes {
+                opts.lookahead_distance
+            } else {
+                1
+            },
+            opts.analysis_speed,
+            opts.min_scenecut_distance.unwrap_or(0),
+            opts.max_scenecut_distance.unwrap_or(u32::MAX as usize),
+        )
+    };
+    let new_backend =
+        |backend: Backend, size: (usize, usize)| -> Box<dyn SceneDetectorBackend<T>> {
+            match backend {
+                Backend::Cost { .. } => Box::new(CostDetector {
+                    scale: Scale::new(new_detector(size)),
+                    lookahead_distance: opts.lookahead_distance,
+                    next_frameno: 0,
+                }),
+                Backend::Histogram { threshold, .. } => Box::new(HistogramDetector {
+                    threshold,
+                    prev: None,
+                    distances: Vec::new(),
+                }),
+                Backend::Ecr { threshold, .. } => Box::new(EcrDetector {
+                    threshold,
+                    prev: None,
+                    ratios: Vec::new(),
+                }),
+            }
+        };
+    let detectors = backends
+        .iter()
+        .map(|&backend| {
+            let full = new_backend(backend, (width, height));
+            if backend.ensemble() {
+                Box::new(EnsembleDetector {
+                    full,
+                    coarse: new_backend(backend, half_size(width, height)),
+                })
+            } else {
+                full
+            }
+        })
+        .collect();
+
+    detect_with(
+        source,
+        filters,
+        collect_stats,
+        detectors,
+        min_votes,
+        cancel,
+        progress_callback,
+    )
+}
+
+/// Run scene detection over every frame from the source with any backends,
+/// including ones implemented outside shear.
+///
+/// Each frame goes through `filters` before analysis; frames in its
+/// excluded ranges are decoded but never analyzed, and detectors see the
+/// frames either side of a range as consecutive. Cuts and scores are
+/// numbered by source frame regardless. When `collect_stats` is set,
+/// `FrameStats` are computed for each frame alongside detection.
+/// Every detector sees every frame of the same pass; with more than one, a
+/// cut needs `min_votes` of them to agree (see `vote_cuts`). Scores come
+/// from the first detector that has any. Cancelling `cancel` stops the pass
+/// at the next frame with a `Cancelled` error holding the partial results;
+/// the source is no longer borrowed once this returns, so it can be
+/// dropped straight away.
+///
+/// The progress callback receives a `PassProgress` for every analyzed
+/// frame, in order.
+pub fn detect_with<T: Pixel>(
+    source: &mut dyn FrameSource<T>,
+    filters: &PreFilters,
+    collect_stats: bool,
+    mut detectors: Vec<Box<dyn SceneDetectorBackend<T>>>,
+    min_votes: usize,
+    cancel: Option<&CancellationToken>,
+    progress_callback: Option<&dyn Fn(&PassProgress)>,
+) -> Result<DetectionResults> {
+    assert!(!detectors.is_empty());
+
+    let details = source.video_details();
+    filters.validate(&details)?;
+
+    let bit_depth = details.bit_depth;
+    let (frame_tx, frame_rx) = sync_channel::<(usize, Arc<Frame<T>>)>(FRAME_PREFETCH_DEPTH);
+    let (progress_tx, progress_rx) = channel();
+
+    let detection_handle = thread::spawn(move || -> Result<DetectionResults> {
+        let mut frame_stats = Vec::new();
+        let mut prev_frame: Option<Arc<Frame<T>>> = None;
+        let nits = if collect_stats {
+            pq_nits_table(bit_depth)
+        } else {
+            Vec::new()
+        };
+
+        // Source frame number of each analyzed frame; detectors number
+        // frames in the order they see them, which differs once excluded
+        // ranges are skipped
+        let mut frame_numbers = Vec::new();
+        let mut analysis_time = Duration::ZERO;
+        let mut reported_cuts = detectors[0].cut_count();
+        for (number, frame) in frame_rx {
+            let started = Instant::now();
+            if collect_stats {
+                frame_stats.resize(number, EXCLUDED_FRAME_STATS);
+                frame_stats.push(frame_stats_for(
+                    &frame,
+                    prev_frame.as_deref(),
+                    bit_depth,
+                    &nits,
+                ));
+                prev_frame = Some(Arc::clone(&frame));
+            }
+            for detector in &mut detectors {
+                detector.push(Arc::clone(&frame))?;
+            }
+            frame_numbers.push(number);
+            analysis_time += started.elapsed();
+
+            let cuts = detectors[0].cut_count();
+            let new_cut = if cuts > reported_cuts {
+                detectors[0]
+                    .last_cut()
+                    .and_then(|cut| frame_numbers.get(cut).copied())
+                    .filter(|&cut| cut != 0)
+            } else {
+                None
+            };
+            reported_cuts = cuts;
+            let _ = progress_tx.send(PassProgress {
+                frames: number + 1,
+                cuts,
+                new_cut,
+            });
+        }
+
+        let started = Instant::now();
+        let to_source = |frame: usize| frame_numbers.get(frame).copied().unwrap_or(frame);
+        let mut cut_lists: Vec<Vec<usize>> = Vec::new();
+        let mut scores = BTreeMap::new();
+        for detector in detectors {
+            let output = detector.finish()?;
+            if scores.is_empty() {
+                scores = output
+                    .scores
+                    .into_iter()
+                    .map(|(frame, score)| (to_source(frame), score))
+                    .collect();
+            }
+            cut_lists.push(output.cuts.into_iter().map(to_source).collect());
+        }
+        let scene_changes = match cut_lists.as_slice() {
+            [cuts] => cuts.clone(),
+            _ => vote_cuts(&cut_lists, min_votes),
+        };
+
+        Ok(DetectionResults {
+            scene_changes,
+            scores,
+            frame_count: 0,
+            frame_stats,
+            decode_time: Duration::ZERO,
+            analysis_time: analysis_time + started.elapsed(),
+        })
+    });
+
+    let report_progress = || {
+        if let Some(progress_fn) = progress_callback {
+            while let Ok(progress) = progress_rx.try_recv() {
+                progress_fn(&progress);
+            }
+        }
+    };
+
+    let mut decode_time = Duration::ZERO;
+    let mut frame_count = 0;
+    let mut cancelled = false;
+    loop {
+        let started = Instant::now();
+        let Some(frame) = source.read_frame()? else {
+            break;
+        };
+        if cancel.is_some_and(CancellationToken::is_cancelled) {
+            cancelled = true;
+            break;
+        }
+        let number = frame_count;
+        frame_count += 1;
+        if filters.excludes(number) {
+            decode_time += started.elapsed();
+            continue;
+        }
+        let frame = filters.apply(frame)?;
+        decode_time += started.elapsed();
+        if frame_tx.send((number, Arc::new(frame))).is_err() {
+            break;
+        }
+        report_progress();
+    }
+
+    drop(frame_tx);
+
+    let mut results = detection_handle
+        .join()
+        .map_err(|_| anyhow::anyhow!("scene detection thread panicked"))??;
+    report_progress();
+
+    results.frame_count = frame_count;
+    if collect_stats {
+        results
+            .frame_stats
+            .resize(frame_count, EXCLUDED_FRAME_STATS);
+    }
+    results.decode_time = decode_time;
+    if cancelled {
+        return Err(Cancelled { partial: results }.into());
+    }
+    Ok(results)
+}
+
+/// Cut detection algorithm and its settings.
+///
+/// With `ensemble`, a second detector of the same kind analyzes the frames
+/// at half width and height, and the two scales' cuts are merged with
+/// `fuse_cuts`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Backend {
+    /// av-scenechange's inter/intra cost comparison
+    Cost { ensemble: bool },
+    /// Histogram differences with an adaptive threshold; see `histogram`
+    Histogram { threshold: f64, ensemble: bool },
+    /// Edge change ratio; see `ecr`
+    Ecr { threshold: f64, ensemble: bool },
+}
+
+impl Backend {
+    fn ensemble(self) -> bool {
+        match self {
+            Backend::Cost { ensemble }
+            | Backend::Histogram { ensemble, .. }
+            | Backend::Ecr { ensemble, .. } => ensemble,
+        }
+    }
+}
+
+/// A cut detection algorithm, fed the decoded and filtered frames of a
+/// pass in order.
+///
+/// Implement this to run your own algorithm in shear's decode pipeline;
+/// its cuts then go through the same voting, splitting and output as the
+/// built-in backends.
+pub trait SceneDetectorBackend<T: Pixel>: Send {
+    fn push(&mut self, frame: Arc<Frame<T>>) -> Result<()>;
+
+    /// Cuts found so far, for progress
+    fn cut_count(&self) -> usize {
+        0
+    }
+
+    /// The most recent cut found so far, for progress
+    fn last_cut(&self) -> Option<usize> {
+        None
+    }
+
+    /// Called once after the last frame.
+    fn finish(self: Box<Self>) -> Result<BackendOutput>;
+}
+
+/// What a backend found in a pass.
+#[derive(Debug, Clone, Default)]
+pub struct BackendOutput {
+    /// Cut frames in order, including frame 0
+    pub cuts: Vec<usize>,
+    /// Per-frame scores in av-scenechange's cost units. These feed
+    /// complexity balancing, chunk reports and threshold re-decisions;
+    /// leave empty if the backend has no such costs.
+    pub scores: BTreeMap<usize, ScenecutResult>,
+    /// How far each frame cleared the backend's own cut threshold, 1.0
+    /// exactly at it (see `cut_strength`). `--ensemble` fuses scales on
+    /// these; a frame without one counts as 0.
+    pub strengths: BTreeMap<usize, f64>,
+}
+
+/// av-scenechange's own detector.
+struct CostDetector<T: Pixel> {
+    scale: Scale<T>,
+    lookahead_distance: usize,
+    /// Next frame to analyze, once its lookahead frames are in
+    next_frameno: usize,
+}
+
+impl<T: Pixel> CostDetector<T> {
+    fn analyze_next(&mut self) -> bool {
+        if !self
+            .scale
+            .analyze(self.next_frameno, self.lookahead_distance)
+        {
+            return false;
+        }
+        self.next_frameno += 1;
+        true
+    }
+}
+
+impl<T: Pixel> SceneDetectorBackend<T> for CostDetector<T> {
+    fn push(&mut self, frame: Arc<Frame<T>>) -> Result<()> {
+        let frameno = self.scale.frames.keys().last().map_or(0, |key| key + 1);
+        self.scale.frames.insert(frameno, frame);
+
+        if self.next_frameno + self.lookahead_distance <= frameno {
+            self.analyze_next();
+        }
+        Ok(())
+    }
+
+    fn cut_count(&self) -> usize {
+        self.scale.keyframes.len()
+    }
+
+    fn last_cut(&self) -> Option<usize> {
+        self.scale.keyframes.last().copied()
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<BackendOutput> {
+        // The last frames are analyzed with a shrinking lookahead
+        while self.analyze_next() {}
+
+        let strengths = self
+            .scale
+            .scores
+            .iter()
+            .map(|(&frame, score)| (frame, cut_strength(score)))
+            .collect();
+        Ok(BackendOutput {
+            cuts: self.scale.keyframes.into_iter().collect(),
+            scores: self.scale.scores,
+            strengths,
+        })
+    }
+}
+
+/// A backend at full scale alongside a copy fed half-scale frames
+/// (`--ensemble`). Scores and progress come from the full scale.
+struct EnsembleDetector<T: Pixel> {
+    full: Box<dyn SceneDetectorBackend<T>>,
+    coarse: Box<dyn SceneDetectorBackend<T>>,
+}
+
+impl<T: Pixel> SceneDetectorBackend<T> for EnsembleDetector<T> {
+    fn push(&mut self, frame: Arc<Frame<T>>) -> Result<()> {
+        self.coarse.push(Arc::new(downscale_half(&frame)?))?;
+        self.full.push(frame)
+    }
+
+    fn cut_count(&self) -> usize {
+        self.full.cut_count()
+    }
+
+    fn last_cut(&self) -> Option<usize> {
+        self.full.last_cut()
+    }
+
+    fn finish(self: Box<Self>) -> Result<BackendOutput> {
+        let full = self.full.finish()?;
+        let coarse = self.coarse.finish()?;
+        Ok(BackendOutput {
+            cuts: fuse_cuts(&full.cuts, &full.strengths, &coarse.cuts, &coarse.strengths),
+            ..full
+        })
+    }
+}
+
+/// Cuts are decided once all distances are known, since the adaptive
+/// threshold looks at frames on both sides.
+struct HistogramDetector {
+    threshold: f64,
+    prev: Option<FrameHistogram>,
+    distances: Vec<f64>,
+}
+
+impl<T: Pixel> SceneDetectorBackend<T> for HistogramDetector {
+    fn push(&mut self, frame: Arc<Frame<T>>) -> Result<()> {
+        let histogram = FrameHistogram::new(&frame);
+        let distance = self
+            .prev
+            .as_ref()
+            .map_or(0.0, |prev| prev.distance(&histogram));
+        self.distances.push(distance);
+        self.prev = Some(histogram);
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> Result<BackendOutput> {
+        Ok(BackendOutput {
+            cuts: histogram::adaptive_cuts(&self.distances, self.threshold),
+            strengths: histogram::strengths(&self.distances, self.threshold),
+            ..Default::default()
+        })
+    }
+}
+
+struct EcrDetector {
+    threshold: f64,
+    prev: Option<EdgeMap>,
+    ratios: Vec<f64>,
+}
+
+impl<T: Pixel> SceneDetectorBackend<T> for EcrDetector {
+    fn push(&mut self, frame: Arc<Frame<T>>) -> Result<()> {
+        let edges = EdgeMap::new(&downscale_half(&frame)?);
+        let ratio = self
+            .prev
+            .as_ref()
+            .map_or(0.0, |prev| prev.change_ratio(&edges));
+        self.ratios.push(ratio);
+        self.prev = Some(edges);
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> Result<BackendOutput> {
+        Ok(BackendOutput {
+            cuts: ecr::cuts(&self.ratios, self.threshold),
+            strengths: ecr::strengths(&self.ratios, self.threshold),
+            ..Default::default()
+        })
+    }
+}
+
+/// Frames apart that cuts from different detectors still count as the
+/// same cut
+const VOTE_TOLERANCE: usize = 2;
+
+/// Combine several detectors' cuts: cuts within `VOTE_TOLERANCE` frames of
+/// each other form one candidate, kept when at least `min_votes` detectors
+/// contributed to it. A kept cut sits where the earliest-listed detector
+/// put it. Frame 0 is always a cut.
+fn vote_cuts(cut_lists: &[Vec<usize>], min_votes: usize) -> Vec<usize> {
+    let mut candidates: Vec<(usize, usize)> = cut_lists
+        .iter()
+        .enumerate()
+        .flat_map(|(detector, cuts)| cuts.iter().map(move |&frame| (frame, detector)))
+        .filter(|&(frame, _)| frame != 0)
+        .collect();
+    candidates.sort();
+
+    let mut cuts = vec![0];
+    let mut group: Vec<(usize, usize)> = Vec::new();
+    let mut close_group = |group: &mut Vec<(usize, usize)>| {
+        let mut voters: Vec<usize> = group.iter().map(|&(_, detector)| detector).collect();
+        voters.sort();
+        voters.dedup();
+        if voters.len() >= min_votes {
+            let &(frame, _) = group
+                .iter()
+                .min_by_key(|&&(_, detector)| detector)
+                .expect("groups are not empty");
+            cuts.push(frame);
+        }
+        group.clear();
+    };
+    for candidate in candidates {
+        if group
+            .first()
+            .is_some_and(|&(first, _)| candidate.0 > first + VOTE_TOLERANCE)
+        {
+            close_group(&mut group);
+        }
+        group.push(candidate);
+    }
+    if !group.is_empty() {
+        close_group(&mut group);
+    }
+    cuts
+}
+
+/// One detector with its own frame window and decisions.
+struct Scale<T: Pixel> {
+    detector: SceneChangeDetector<T>,
+    frames: BTreeMap<usize, Arc<Frame<T>>>,
+    keyframes: BTreeSet<usize>,
+    scores: BTreeMap<usize, ScenecutResult>,
+}
+
+impl<T: Pixel> Scale<T> {
+    fn new(detector: SceneChangeDetector<T>) -> Self {
+        Scale {
+            detector,
+            frames: BTreeMap::new(),
+            keyframes: BTreeSet::from([0]),
+            scores: BTreeMap::new(),
+        }
+    }
+
+    /// Analyze `frameno` with the frames buffered after it. Returns false
+    /// once fewer than two frames are left.
+    fn analyze(&mut self, frameno: usize, lookahead_distance: usize) -> bool {
+        let frame_set = self
+            .frames
+            .values()
+            .take(lookahead_distance + 2)
+            .collect::<Vec<_>>();
+        if frame_set.len() < 2 {
+            return false;
+        }
+
+        if frameno > 0 {
+            let previous_keyframe = *self
+                .keyframes
+                .iter()
+                .last()
+                .expect("at least 1 keyframe should exist");
+            let (cut, score) =
+                self.detector
+                    .analyze_next_frame(&frame_set, frameno, previous_keyframe);
+            if let Some(score) = score {
+                self.scores.insert(frameno, score);
+            }
+            if cut {
+                self.keyframes.insert(frameno);
+            }
+            self.frames.remove(&(frameno - 1));
+        }
+        true
+    }
+}
+
+/// Merge the cuts of full and coarse scale detection.
+///
+/// Strengths are relative to the detector's own threshold, so the same
+/// rule holds for every backend. A frame cut at both scales is kept. A
+/// frame cut at only one is kept when the mean of its strengths at both
+/// scales still clears the threshold, so a cut one scale narrowly missed is
+/// recovered while one the other scale doesn't see at all is dropped. Cuts
+/// on consecutive frames collapse to the stronger one.
+fn fuse_cuts(
+    full_cuts: &[usize],
+    full_strengths: &BTreeMap<usize, f64>,
+    coarse_cuts: &[usize],
+    coarse_strengths: &BTreeMap<usize, f64>,
+) -> Vec<usize> {
+    let strength = |frame: &usize| {
+        let full = full_strengths.get(frame).copied().unwrap_or(0.0);
+        let coarse = coarse_strengths.get(frame).copied().unwrap_or(0.0);
+        (full + coarse) / 2.0
+    };
+    let (full_cuts, coarse_cuts): (BTreeSet<usize>, BTreeSet<usize>) = (
+        full_cuts.iter().copied().collect(),
+        coarse_cuts.iter().copied().collect(),
+    );
+
+    let mut cuts: Vec<usize> = Vec::new();
+    for frame in full_cuts.union(&coarse_cuts) {
+        let agreed = full_cuts.contains(frame) && coarse_cuts.contains(frame);
+        if *frame != 0 && !agreed && strength(frame) < 1.0 {
+            continue;
+        }
+        match cuts.last_mut() {
+            Some(last) if *last != 0 && *last + 1 == *frame => {
+                if strength(frame) > strength(last) {
+                    *last = *frame;
+                }
+            }
+            _ => cuts.push(*frame),
+        }
+    }
+    cuts
+}
+
+/// The detector's own cut threshold, as a percentage of a frame's intra
+/// cost that its inter cost must reach.
+pub const DEFAULT_THRESHOLD: f64 = 30.0;
+
+/// Frames whose score clears `threshold` (same units as
+/// `DEFAULT_THRESHOLD`), re-deciding cuts from scores without a new decode.
+///
+/// Flash compensation is already folded into the scores, so at the default
+/// threshold this closely matches the detector's own cuts.
+pub fn cuts_at_threshold(scores: &BTreeMap<usize, ScenecutResult>, threshold: f64) -> Vec<usize> {
+    let min_strength = threshold / DEFAULT_THRESHOLD;
+    scores
+        .iter()
+        .filter(|(_, score)| cut_strength(score) >= min_strength)
+        .map(|(&frame, _)| frame)
+        .collect()
+}
+
+/// Dual-threshold cut decision.
+///
+/// A transition starts when a frame clears `confirm` and lasts while
+/// frames keep clearing `extend`; each transition yields one cut, at its
+/// strongest frame. Frames that only clear `extend` never start a cut, so
+/// noise hovering around a single threshold can't flap.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Hysteresis {
+    pub confirm: f64,
+    pub extend: f64,
+}
+
+impl FromStr for Hysteresis {
+    type Err = String;
+
+    /// Parse `confirm:extend`, e.g. `40:20`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((confirm, extend)) = s.split_once(':') else {
+            return Err("expected confirm:extend, e.g. 40:20".to_string());
+        };
+        let parse = |v: &str| {
+            v.trim()
+                .parse::<f64>()
+                .map_err(|_| format!("invalid threshold {:?}", v))
+        };
+        let hysteresis = Hysteresis {
+            confirm: parse(confirm)?,
+            extend: parse(extend)?,
+        };
+        if hysteresis.extend > hysteresis.confirm {
+            return Err("extend threshold must not exceed confirm threshold".to_string());
+        }
+        Ok(hysteresis)
+    }
+}
+
+impl Hysteresis {
+    /// Re-decide cuts from the detector's scores.
+    pub fn cuts(&self, scores: &BTreeMap<usize, ScenecutResult>) -> Vec<usize> {
+        let confirm = self.confirm / DEFAULT_THRESHOLD;
+        let extend = self.extend / DEFAULT_THRESHOLD;
+
+        let mut cuts = Vec::new();
+        // Strongest frame of the current run above `extend`, and whether
+        // any frame in the run cleared `confirm`
+        let mut run: Option<(usize, f64, bool)> = None;
+        let mut prev_frame = None;
+
+        for (&frame, score) in scores {
+            let strength = cut_strength(score);
+            // A frame without a score breaks the run
+            let contiguous = prev_frame.is_some_and(|prev| prev + 1 == frame);
+            prev_frame = Some(frame);
+
+            if !contiguous || strength < extend {
+                if let Some((peak, _, true)) = run.take() {
+                    cuts.push(peak);
+                }
+            }
+            if strength >= extend {
+                let (peak, peak_strength, confirmed) = run.get_or_insert((frame, strength, false));
+                if strength > *peak_strength {
+                    *peak = frame;
+                    *peak_strength = strength;
+                }
+                *confirmed |= strength >= confirm;
+            }
+        }
+        if let Some((peak, _, true)) = run {
+            cuts.push(peak);
+        }
+
+        cuts
+    }
+}
+
+/// How clearly a frame's score cleared the detector's threshold.
+///
+/// 1.0 is exactly at the threshold; scene cuts usually score well above it.
+pub fn cut_strength(score: &ScenecutResult) -> f64 {
+    if score.threshold > 0.0 {
+        score.forward_adjusted_cost / score.threshold
+    } else {
+        0.0
+    }
+}
+
+/// A cut's score mapped to 0-1: 0 exactly at the detector's threshold,
+/// 0.5 at twice it, approaching 1 for unmistakable cuts.
+pub fn confidence(score: &ScenecutResult) -> f64 {
+    let strength = cut_strength(score);
+    if strength > 0.0 {
+        (1.0 - 1.0 / strength).max(0.0)
+    } else {
+        0.0
+    }
+}
+
+/// A detected cut dropped for scoring below `--min-confidence`.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct Rejected {
+    pub frame: usize,
+    pub confidence: f64,
+}
+
+/// Split `cuts` into those whose confidence reaches `min_confidence` and
+/// those dropped. Frame 0 is always kept.
+pub fn filter_by_confidence(
+    cuts: &[usize],
+    scores: &BTreeMap<usize, ScenecutResult>,
+    min_confidence: f64,
+) -> (Vec<usize>, Vec<Rejected>) {
+    let mut kept = Vec::new();
+    let mut rejected = Vec::new();
+    for &frame in cuts {
+        let confidence = scores.get(&frame).map_or(0.0, confidence);
+        if frame == 0 || confidence >= min_confidence {
+            kept.push(frame);
+        } else {
+            rejected.push(Rejected { frame, confidence });
+        }
+    }
+    (kept, rejected)
+}
+
+/// Cuts below this confidence are re-analyzed by `refine_cuts`
+pub const REFINE_CONFIDENCE: f64 = 0.5;
+
+/// Frames either side of a borderline cut that `refine_cuts` re-analyzes
+pub const REFINE_WINDOW: usize = 12;
+
+/// Detected cuts other than frame 0 whose confidence is below
+/// `REFINE_CONFIDENCE`.
+pub fn borderline_cuts(cuts: &[usize], scores: &BTreeMap<usize, ScenecutResult>) -> Vec<usize> {
+    cuts.iter()
+        .copied()
+        .filter(|&frame| frame != 0)
+        .filter(|frame| scores.get(frame).map_or(0.0, confidence) < REFINE_CONFIDENCE)
+        .collect()
+}
+
+/// Frame ranges to re-analyze around sorted `borderline` cuts: each cut's
+/// window, plus `lookahead` frames for the detector to look ahead into,
+/// with overlapping windows merged.
+pub fn refine_windows(borderline: &[usize], lookahead: usize) -> Vec<FrameRange> {
+    let mut windows: Vec<FrameRange> = Vec::new();
+    for &cut in borderline {
+        let window = FrameRange {
+            start: cut.saturating_sub(REFINE_WINDOW),
+            end: cut + REFINE_WINDOW + 1 + lookahead,
+        };
+        match windows.last_mut() {
+            Some(last) if window.start <= last.end => last.end = last.end.max(window.end),
+            _ => windows.push(window),
+        }
+    }
+    windows
+}
+
+/// Replace each `borderline` cut with the nearest of `refined` within
+/// `REFINE_WINDOW` frames, or drop it if there is none. Returns the new
+/// cut list and the dropped cuts.
+pub fn apply_refinement(
+    cuts: &[usize],
+    borderline: &[usize],
+    refined: &[usize],
+) -> (Vec<usize>, Vec<usize>) {
+    let mut result = BTreeSet::new();
+    let mut dropped = Vec::new();
+    for &cut in cuts {
+        if !borderline.contains(&cut) {
+            result.insert(cut);
+            continue;
+        }
+        let nearest = refined
+            .iter()
+            .copied()
+            .filter(|frame| frame.abs_diff(cut) <= REFINE_WINDOW)
+            .min_by_key(|frame| frame.abs_diff(cut));
+        match nearest {
+            Some(frame) => {
+                result.insert(frame);
+            }
+            None => dropped.push(cut),
+        }
+    }
+    (result.into_iter().collect(), dropped)
+}
+
+/// Re-analyze the frames around borderline cuts at full resolution with
+/// av-scenechange's Standard speed, for a main pass that ran at Fast speed
+/// or at half scale, and move or drop those cuts to match.
+///
+/// `source` must be freshly opened: the windows are read in one forward
+/// pass, and frames between them are decoded but not analyzed. Cuts
+/// whose window would reach into a range `filters` excludes are left as
+/// they are. Returns the new cut list and the dropped cuts.
+pub fn refine_cuts<T: Pixel>(
+    source: &mut dyn FrameSource<T>,
+    cuts: &[usize],
+    scores: &BTreeMap<usize, ScenecutResult>,
+    opts: DetectionOptions,
+    filters: &PreFilters,
+    cancel: Option<&CancellationToken>,
+) -> Result<(Vec<usize>, Vec<usize>)> {
+    let lookahead = opts.lookahead_distance;
+    let borderline: Vec<usize> = borderline_cuts(cuts, scores)
+        .into_iter()
+        .filter(|&cut| {
+            let window = FrameRange {
+                start: cut.saturating_sub(REFINE_WINDOW),
+                end: cut + REFINE_WINDOW + 1 + lookahead,
+            };
+            !filters
+                .exclude
+                .iter()
+                .any(|range| range.start < window.end && window.start < range.end)
+        })
+        .collect();
+    if borderline.is_empty() {
+        return Ok((cuts.to_vec(), Vec::new()));
+    }
+
+    let opts = DetectionOptions {
+        analysis_speed: SceneDetectionSpeed::Standard,
+        ..opts
+    };
+    // Window frames are numbered from the window start
+    let window_filters = PreFilters {
+        exclude: Vec::new(),
+        ..filters.clone()
+    };
+    let backends = [Backend::Cost { ensemble: false }];
+    let mut refined = Vec::new();
+    let mut position = 0;
+    for window in refine_windows(&borderline, lookahead) {
+        let mut ranged = RangeSource::resuming(source, window, position);
+        let results = detect(
+            &mut ranged,
+            opts,
+            &window_filters,
+            false,
+            &backends,
+            1,
+            cancel,
+            None,
+        )?;
+        position = ranged.position();
+        // The window's first frame has nothing before it to be cut from
+        refined.extend(
+            results
+                .scene_changes
+                .into_iter()
+                .filter(|&frame| frame != 0)
+                .map(|frame| frame + window.start),
+        );
+    }
+    Ok(apply_refinement(cuts, &borderline, &refined))
+}
+
+/// Strength a frame near a hint needs to become a cut: half the
+/// detector's threshold, since the hint already suggests a cut there
+const HINT_MIN_STRENGTH: f64 = 0.5;
+
+/// Merge externally known cut points into detected `cuts`.
+///
+/// A hint with detected cuts within `window` frames is confirmed by the
+/// nearest of them, and the others are dropped as duplicates. A hint with
+/// none becomes a cut at the strongest frame in the window, if that clears
+/// `HINT_MIN_STRENGTH`. Hints with no scored frames nearby, as with
+/// backends that keep no scores, are taken as they are.
+pub fn apply_hints(
+    cuts: &[usize],
+    hints: &[usize],
+    scores: &BTreeMap<usize, ScenecutResult>,
+    window: usize,
+) -> Vec<usize> {
+    let mut result: BTreeSet<usize> = cuts.iter().copied().collect();
+    for &hint in hints {
+        let (lo, hi) = (hint.saturating_sub(window), hint + window);
+        let nearby: Vec<usize> = result.range(lo..=hi).copied().collect();
+        if let Some(&nearest) = nearby.iter().min_by_key(|&&cut| cut.abs_diff(hint)) {
+            for cut in nearby {
+                if cut != nearest && cut != 0 {
+                    result.remove(&cut);
+                }
+            }
+            continue;
+        }
+
+        let strongest = scores
+            .range(lo..=hi)
+            .map(|(&frame, score)| (frame, cut_strength(score)))
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+        match strongest {
+            None => {
+                result.insert(hint);
+            }
+            Some((frame, strength)) if strength >= HINT_MIN_STRENGTH => {
+                result.insert(frame);
+            }
+            Some(_) => {}
+        }
+    }
+    result.into_iter().collect()
+}
+
+/// Compute statistics for a frame, given the frame before it and the
+/// light level of each luma code value.
+fn frame_stats_for<T: Pixel>(
+    frame: &Frame<T>,
+    prev: Option<&Frame<T>>,
+    bit_depth: usize,
+    nits: &[f64],
+) -> FrameStats {
+    let (max_nits, mean_nits) = light_level(&frame.y_plane, nits);
+    FrameStats {
+        luma_diff: prev.map_or(f64::MAX, |prev| {
+            mean_abs_diff(&frame.y_plane, &prev.y_plane, bit_depth)
+        }),
+        black_ratio: black_ratio(&frame.y_plane, bit_depth),
+        mean_luma: mean_value(&frame.y_plane, bit_depth),
+        max_nits,
+        mean_nits,
+    }
+}
+
+/// Light level in nits of every luma code value at `bit_depth`, reading
+/// them as limited-range SMPTE ST 2084 (PQ), as HDR10 sources are.
+fn pq_nits_table(bit_depth: usize) -> Vec<f64> {
+    const M1: f64 = 2610.0 / 16384.0;
+    const M2: f64 = 2523.0 / 4096.0 * 128.0;
+    const C1: f64 = 3424.0 / 4096.0;
+    const C2: f64 = 2413.0 / 4096.0 * 32.0;
+    const C3: f64 = 2392.0 / 4096.0 * 32.0;
+
+    let scale = (1u32 << (bit_depth - 8)) as f64;
+    (0..1u32 << bit_depth)
+        .map(|code| {
+            let signal = ((code as f64 / scale - 16.0) / 219.0).clamp(0.0, 1.0);
+            let p = signal.powf(1.0 / M2);
+            10000.0 * ((p - C1).max(0.0) / (C2 - C3 * p)).powf(1.0 / M1)
+        })
+        .collect()
+}
+
+/// Brightest and mean light level of a plane, looking each pixel up in
+/// `nits`.
+fn light_level<T: Pixel>(plane: &Plane<T>, nits: &[f64]) -> (f64, f64) {
+    let mut max = 0.0f64;
+    let mut sum = 0.0;
+    let mut count = 0u64;
+    for row in plane.rows() {
+        for &p in row {
+            let level = nits
+                .get(p.to_usize().unwrap_or(0))
+                .copied()
+                .unwrap_or_default();
+            max = max.max(level);
+            sum += level;
+        }
+        count += row.len() as u64;
+    }
+
+    if count == 0 {
+        return (0.0, 0.0);
+    }
+    (max, sum / count as f64)
+}
+
+/// Fraction of pixels in a plane at or below BLACK_PIXEL_MAX.
+fn black_ratio<T: Pixel>(plane: &Plane<T>, bit_depth: usize) -> f64 {
+    let max = BLACK_PIXEL_MAX << (bit_depth - 8);
+    let mut black = 0u64;
+    let mut count = 0u64;
+    for row in plane.rows() {
+        black += row
+            .iter()
+            .filter(|&&p| p.to_u32().unwrap_or(0) <= max)
+            .count() as u64;
+        count += row.len() as u64;
+    }
+
+    if count == 0 {
+        return 0.0;
+    }
+    black as f64 / count as f64
+}
+
+/// Mean pixel value of a plane, scaled to 8-bit units.
+fn mean_value<T: Pixel>(plane: &Plane<T>, bit_depth: usize) -> f64 {
+    let mut sum = 0u64;
+    let mut count = 0u64;
+    for row in plane.rows() {
+        sum += row.iter().map(|p| p.to_u64().unwrap_or(0)).sum::<u64>();
+        count += row.len() as u64;
+    }
+
+    if count == 0 {
+        return 0.0;
+    }
+    sum as f64 / count as f64 / (1u64 << (bit_depth - 8)) as f64
+}
+
+/// Mean absolute difference between two planes, scaled to 8-bit units.
+fn mean_abs_diff<T: Pixel>(a: &Plane<T>, b: &Plane<T>, bit_depth: usize) -> f64 {
+    let mut sum = 0u64;
+    let mut count = 0u64;
+    for (row_a, row_b) in a.rows().zip(b.rows()) {
+        for (&pa, &pb) in row_a.iter().zip(row_b) {
+            let pa = pa.to_i32().unwrap_or(0);
+            let pb = pb.to_i32().unwrap_or(0);
+            sum += pa.abs_diff(pb) as u64;
+        }
+        count += row_a.len() as u64;
+    }
+
+    if count == 0 {
+        return 0.0;
+    }
+    sum as f64 / count as f64 / (1u64 << (bit_depth - 8)) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filters::new_frame;
+    use av_scenechange::av_decoders::v_frame::chroma::ChromaSubsampling;
+    use av_scenechange::av_decoders::VideoDetails;
+    use av_scenechange::Rational32;
+    use std::num::NonZeroU8;
+
+    fn scores(strengths: &[(usize, f64)]) -> BTreeMap<usize, ScenecutResult> {
+        strengths
+            .iter()
+            .map(|&(frame, strength)| {
+                let score = ScenecutResult {
+                    inter_cost: 0.0,
+                    imp_block_cost: 0.0,
+                    backward_adjusted_cost: 0.0,
+                    forward_adjusted_cost: strength * 10.0,
+                    threshold: 10.0,
+                };
+                (frame, score)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_hysteresis_single_cut_per_transition() {
+        // Noise hovering around the default threshold, then a real cut
+        let s = scores(&[
+            (1, 0.9),
+            (2, 1.1),
+            (3, 0.8),
+            (4, 1.2),
+            (5, 0.1),
+            (6, 0.7),
+            (7, 2.0),
+            (8, 1.5),
+            (9, 0.1),
+        ]);
+        // Default threshold alone would cut at 2, 4, 7 and 8
+        assert_eq!(cuts_at_threshold(&s, DEFAULT_THRESHOLD), vec![2, 4, 7, 8]);
+
+        let h = Hysteresis {
+            confirm: 45.0,
+            extend: 15.0,
+        };
+        assert_eq!(h.cuts(&s), vec![7]);
+    }
+
+    #[test]
+    fn test_hysteresis_gap_breaks_run() {
+        let s = scores(&[(10, 2.0), (11, 1.0), (13, 3.0)]);
+        let h = Hysteresis {
+            confirm: 45.0,
+            extend: 15.0,
+        };
+        assert_eq!(h.cuts(&s), vec![10, 13]);
+    }
+
+    #[test]
+    fn test_pq_nits_table() {
+        let nits = pq_nits_table(10);
+        assert_eq!(nits.len(), 1024);
+        // Limited-range black and peak white
+        assert_eq!(nits[64], 0.0);
+        assert!((nits[940] - 10000.0).abs() < 1e-6);
+        // HDR10 reference white is around code 509
+        assert!((nits[509] - 100.0).abs() < 3.0);
+    }
+
+    #[test]
+    fn test_filter_by_confidence() {
+        // Strengths 1.25, 4 and 2: confidences 0.2, 0.75 and 0.5
+        let scores = scores(&[(40, 1.25), (90, 4.0), (150, 2.0)]);
+        let (kept, rejected) = filter_by_confidence(&[0, 40, 90, 150], &scores, 0.5);
+        assert_eq!(kept, vec![0, 90, 150]);
+        assert_eq!(rejected.len(), 1);
+        assert_eq!(rejected[0].frame, 40);
+        assert!((rejected[0].confidence - 0.2).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_refinement() {
+        // Strength 3 is confidence 0.67, 1.5 is 0.33
+        let scores = scores(&[(40, 3.0), (100, 1.5), (110, 1.2)]);
+        let borderline = borderline_cuts(&[0, 40, 100, 110], &scores);
+        assert_eq!(borderline, vec![100, 110]);
+        assert_eq!(
+            refine_windows(&borderline, 5),
+            vec![FrameRange {
+                start: 88,
+                end: 128
+            }]
+        );
+
+        // 100 moves to the refined cut at 96, 110 has none near it
+        let (cuts, dropped) = apply_refinement(&[0, 40, 100, 110], &borderline, &[96, 140]);
+        assert_eq!(cuts, vec![0, 40, 96]);
+        assert_eq!(dropped, vec![110]);
+    }
+
+    #[test]
+    fn test_refine_cuts() {
+        // Blank frames hold no cuts, so the borderline one is dropped;
+        // the confident one is never re-analyzed
+        let opts = DetectionOptions {
+            lookahead_distance: 5,
+            ..Default::default()
+        };
+        let (cuts, dropped) = refine_cuts(
+            &mut BlankSource { remaining: 80 },
+            &[0, 20, 50],
+            &scores(&[(20, 4.0), (50, 1.1)]),
+            opts,
+            &PreFilters::default(),
+            None,
+        )
+        .unwrap();
+        assert_eq!(cuts, vec![0, 20]);
+        assert_eq!(dropped, vec![50]);
+    }
+
+    #[test]
+    fn test_apply_hints() {
+        let scores = scores(&[(98, 0.2), (101, 0.7), (199, 0.3), (200, 1.5), (202, 1.1)]);
+        // 200 confirms the hint at 201 and 202 goes as a duplicate; the
+        // hint at 100 moves onto 101; the one at 300 has no scores to check
+        assert_eq!(
+            apply_hints(&[0, 200, 202], &[100, 201, 300], &scores, 3),
+            vec![0, 101, 200, 300]
+        );
+        // Too weak to confirm a hint
+        assert_eq!(apply_hints(&[0], &[198], &scores, 1), vec![0]);
+    }
+
+    #[test]
+    fn test_fuse_cuts() {
+        let strengths = |scores: BTreeMap<usize, ScenecutResult>| -> BTreeMap<usize, f64> {
+            scores.iter().map(|(&f, s)| (f, cut_strength(s))).collect()
+        };
+        let full = scores(&[(10, 3.0), (20, 0.8), (30, 1.5), (31, 0.2), (40, 0.1)]);
+        let coarse = scores(&[(10, 2.0), (20, 1.4), (30, 0.9), (31, 2.5), (40, 1.6)]);
+        // 20 is recovered with support from the full scale, 40 lacks it,
+        // and 30/31 collapse to the stronger frame
+        assert_eq!(
+            fuse_cuts(
+                &[0, 10, 30],
+                &strengths(full),
+                &[0, 10, 20, 31, 40],
+                &strengths(coarse)
+            ),
+            vec![0, 10, 20, 31]
+        );
+    }
+
+    #[test]
+    fn test_fuse_histogram_cuts() {
+        let threshold = histogram::DEFAULT_ADAPTIVE_THRESHOLD;
+        let (mut full, mut coarse) = (vec![0.05; 60], vec![0.05; 60]);
+        // The full scale narrowly misses 20, which the coarse scale cuts;
+        // only the coarse scale sees 40
+        full[20] = 0.14;
+        coarse[20] = 0.2;
+        coarse[40] = 0.25;
+        let full_cuts = histogram::adaptive_cuts(&full, threshold);
+        let coarse_cuts = histogram::adaptive_cuts(&coarse, threshold);
+        assert_eq!(
+            (full_cuts.as_slice(), coarse_cuts.as_slice()),
+            (&[0][..], &[0, 20, 40][..])
+        );
+        assert_eq!(
+            fuse_cuts(
+                &full_cuts,
+                &histogram::strengths(&full, threshold),
+                &coarse_cuts,
+                &histogram::strengths(&coarse, threshold)
+            ),
+            vec![0, 20]
+        );
+    }
+
+    #[test]
+    fn test_vote_cuts() {
+        let cut_lists = [
+            vec![0, 50, 120, 300],
+            vec![0, 52, 200, 301],
+            vec![0, 120, 200, 400],
+        ];
+        // 50/52 and 300/301 count as one cut each; positions follow the
+        // first detector
+        assert_eq!(vote_cuts(&cut_lists, 2), vec![0, 50, 120, 200, 300]);
+        assert_eq!(vote_cuts(&cut_lists, 1), vec![0, 50, 120, 200, 300, 400]);
+        assert_eq!(vote_cuts(&cut_lists, 3), vec![0]);
+    }
+
+    /// Blank 16x16 frames.
+    struct BlankSource {
+        remaining: usize,
+    }
+
+    impl FrameSource<u8> for BlankSource {
+        fn video_details(&self) -> VideoDetails {
+            VideoDetails {
+                width: 16,
+                height: 16,
+                bit_depth: 8,
+                chroma_sampling: ChromaSubsampling::Yuv420,
+                frame_rate: Rational32::new(24, 1),
+                total_frames: None,
+            }
+        }
+
+        fn read_frame(&mut self) -> Result<Option<Frame<u8>>> {
+            if self.remaining == 0 {
+                return Ok(None);
+            }
+            self.remaining -= 1;
+            let bit_depth = NonZeroU8::new(8).unwrap();
+            Ok(Some(new_frame(
+                16,
+                16,
+                ChromaSubsampling::Yuv420,
+                bit_depth,
+            )?))
+        }
+    }
+
+    /// Cuts every `every` frames, regardless of content.
+    struct Periodic {
+        every: usize,
+        frames: usize,
+    }
+
+    impl SceneDetectorBackend<u8> for Periodic {
+        fn push(&mut self, _frame: Arc<Frame<u8>>) -> Result<()> {
+            self.frames += 1;
+            Ok(())
+        }
+
+        fn cut_count(&self) -> usize {
+            self.frames.div_ceil(self.every)
+        }
+
+        fn last_cut(&self) -> Option<usize> {
+            self.frames
+                .checked_sub(1)
+                .map(|last| last / self.every * self.every)
+        }
+
+        fn finish(self: Box<Self>) -> Result<BackendOutput> {
+            Ok(BackendOutput {
+                cuts: (0..self.frames).step_by(self.every).collect(),
+                ..Default::default()
+            })
+        }
+    }
+
+    #[test]
+    fn test_detect_with_custom_backends() {
+        let backends = |every: &[usize]| -> Vec<Box<dyn SceneDetectorBackend<u8>>> {
+            every
+                .iter()
+                .map(|&every| Box::new(Periodic { every, frames: 0 }) as _)
+                .collect()
+        };
+        let run = |every: &[usize], min_votes| {
+            let mut source = BlankSource { remaining: 25 };
+            detect_with(
+                &mut source,
+                &PreFilters::default(),
+                true,
+                backends(every),
+                min_votes,
+                None,
+                None,
+            )
+            .unwrap()
+        };
+
+        let results = run(&[10], 1);
+        assert_eq!(results.scene_changes, vec![0, 10, 20]);
+        assert_eq!(results.frame_count, 25);
+        assert_eq!(results.frame_stats.len(), 25);
+        assert!(results.scores.is_empty());
+
+        // Of the two cadences, only 20 and 21 are close enough to agree
+        assert_eq!(run(&[10, 7], 2).scene_changes, vec![0, 20]);
+
+        // Cuts are reported as they are found, frame 0 excepted
+        let new_cuts = std::cell::RefCell::new(Vec::new());
+        let progress = |progress: &PassProgress| {
+            new_cuts.borrow_mut().extend(progress.new_cut);
+        };
+        detect_with(
+            &mut BlankSource { remaining: 25 },
+            &PreFilters::default(),
+            false,
+            backends(&[10]),
+            1,
+            None,
+            Some(&progress),
+        )
+        .unwrap();
+        assert_eq!(new_cuts.into_inner(), vec![10, 20]);
+
+        // Excluded frames aren't analyzed, but cuts keep source numbering
+        let filters = PreFilters {
+            exclude: vec![FrameRange { start: 5, end: 15 }],
+            ..Default::default()
+        };
+        let results = detect_with(
+            &mut BlankSource { remaining: 25 },
+            &filters,
+            true,
+            backends(&[10]),
+            1,
+            None,
+            None,
+        )
+        .unwrap();
+        assert_eq!(results.scene_changes, vec![0, 20]);
+        assert_eq!(results.frame_count, 25);
+        assert_eq!(results.frame_stats.len(), 25);
+        assert_eq!(results.frame_stats[10].luma_diff, f64::MAX);
+    }
+
+    #[test]
+    fn test_cancel_returns_partial_results() {
+        let token = CancellationToken::new();
+        let progress = |progress: &PassProgress| {
+            if progress.frames == 12 {
+                token.cancel();
+            }
+        };
+        let err = detect_with(
+            &mut BlankSource { remaining: 1000 },
+            &PreFilters::default(),
+            false,
+            vec![Box::new(Periodic {
+                every: 10,
+                frames: 0,
+            })],
+            1,
+            Some(&token.clone()),
+            Some(&progress),
+        )
+        .unwrap_err();
+        let partial = err.downcast::<Cancelled>().unwrap().partial;
+        // Frames already queued for analysis may still be counted
+        assert!((12..1000).contains(&partial.frame_count));
+        assert_eq!(&partial.scene_changes[..2], &[0, 10]);
+    }
+
+    /// Dark frames with a cut to a bright pattern at `cut`.
+    struct CutSource {
+        cut: usize,
+        frames: usize,
+        next: usize,
+    }
+
+    impl FrameSource<u8> for CutSource {
+        fn video_details(&self) -> VideoDetails {
+            VideoDetails {
+                width: 64,
+                height: 64,
+                bit_depth: 8,
+                chroma_sampling: ChromaSubsampling::Yuv420,
+                frame_rate: Rational32::new(24, 1),
+                total_frames: None,
+            }
+        }
+
+        fn read_frame(&mut self) -> Result<Option<Frame<u8>>> {
+            if self.next == self.frames {
+                return Ok(None);
+            }
+            let bright = self.next >= self.cut;
+            self.next += 1;
+            let bit_depth = NonZeroU8::new(8).unwrap();
+            let mut frame = new_frame(64, 64, ChromaSubsampling::Yuv420, bit_depth)?;
+            let luma: Vec<u8> = (0..64 * 64)
+                .map(|n| {
+                    if bright {
+                        (n * 37 % 200 + 40) as u8
+                    } else {
+                        16
+                    }
+                })
+                .collect();
+            frame.y_plane.copy_from_u8_slice(&luma)?;
+            Ok(Some(frame))
+        }
+    }
+
+    #[test]
+    fn test_thread_safety() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<DetectionResults>();
+        assert_send_sync::<CancellationToken>();
+        assert_send_sync::<PreFilters>();
+        assert_send_sync::<Backend>();
+        fn assert_send<T: Send>() {}
+        assert_send::<crate::push::PushDetector<u8>>();
+    }
+
+    #[test]
+    fn test_concurrent_detections() {
+        let run = |cut: usize| {
+            let opts = DetectionOptions {
+                lookahead_distance: 5,
+                ..Default::default()
+            };
+            let mut source = CutSource {
+                cut,
+                frames: 60,
+                next: 0,
+            };
+            detect(
+                &mut source,
+                opts,
+                &PreFilters::default(),
+                true,
+                &[Backend::Cost { ensemble: false }],
+                1,
+                None,
+                None,
+            )
+            .unwrap()
+        };
+
+        // Independent passes on many threads match the same passes run alone
+        let cuts: Vec<usize> = (0..8).map(|i| 20 + i % 4 * 5).collect();
+        let alone: Vec<Vec<usize>> = cuts.iter().map(|&cut| run(cut).scene_changes).collect();
+        let concurrent: Vec<Vec<usize>> = thread::scope(|s| {
+            let runs: Vec<_> = cuts
+                .iter()
+                .map(|&cut| s.spawn(move || run(cut).scene_changes))
+                .collect();
+            runs.into_iter().map(|run| run.join().unwrap()).collect()
+        });
+        assert_eq!(concurrent, alone);
+        for (scenes, &cut) in alone.iter().zip(&cuts) {
+            assert!(scenes.contains(&cut), "no cut at {} in {:?}", cut, scenes);
+        }
+    }
+
+    #[test]
+    fn test_parse_hysteresis() {
+        let h: Hysteresis = "40:20".parse().unwrap();
+        assert_eq!(
+            h,
+            Hysteresis {
+                confirm: 40.0,
+                extend: 20.0
+            }
+        );
+        assert!("20:40".parse::<Hysteresis>().is_err());
+        assert!("40".parse::<Hysteresis>().is_err());
+    }
+}
@@ -0,0 +1,200 @@
+//! Edge change ratio cut detection (Zabih, Miller and Mai).
+//!
+//! Compares where edges are rather than how bright pixels are: a cut
+//! replaces most edges at once, while fades, exposure changes and flashes
+//! leave edge positions largely intact. The ratio for a frame is the
+//! larger of the fraction of its edges that are new (not near an edge of
+//! the previous frame) and the fraction of the previous frame's edges
+//! that disappeared.
+
+use av_scenechange::av_decoders::v_frame::{frame::Frame, pixel::Pixel};
+use std::collections::BTreeMap;
+
+/// Default edge change ratio a frame must reach to be a cut
+pub const DEFAULT_ECR_THRESHOLD: f64 = 0.6;
+
+/// Sobel gradient magnitude (|gx| + |gy|, 8-bit units) marking an edge
+const EDGE_MIN: u32 = 128;
+
+/// How far edges may move between frames and still match, in pixels
+const DILATION: usize = 2;
+
+/// Frames with fewer edge pixels than this fraction are treated as
+/// featureless
+const MIN_EDGE_FRACTION: f64 = 0.001;
+
+/// Minimum frames between cuts, as for the histogram detector
+const MIN_SCENE_LEN: usize = 15;
+
+/// Edge pixels of a frame's luma plane, plus the same map dilated by
+/// `DILATION`.
+#[derive(Debug, Clone)]
+pub struct EdgeMap {
+    edges: Vec<bool>,
+    dilated: Vec<bool>,
+    count: usize,
+}
+
+impl EdgeMap {
+    pub fn new<T: Pixel>(frame: &Frame<T>) -> Self {
+        let shift = frame.bit_depth.get() as u32 - 8;
+        let rows: Vec<Vec<i32>> = frame
+            .y_plane
+            .rows()
+            .map(|row| {
+                row.iter()
+                    .map(|px| (px.to_u32().unwrap_or(0) >> shift) as i32)
+                    .collect()
+            })
+            .collect();
+        let height = rows.len();
+        let width = rows.first().map_or(0, Vec::len);
+
+        let mut edges = vec![false; width * height];
+        for y in 1..height.saturating_sub(1) {
+            for x in 1..width.saturating_sub(1) {
+                let p = |dx: usize, dy: usize| rows[y + dy - 1][x + dx - 1];
+                let gx = p(2, 0) + 2 * p(2, 1) + p(2, 2) - p(0, 0) - 2 * p(0, 1) - p(0, 2);
+                let gy = p(0, 2) + 2 * p(1, 2) + p(2, 2) - p(0, 0) - 2 * p(1, 0) - p(2, 0);
+                edges[y * width + x] = gx.unsigned_abs() + gy.unsigned_abs() >= EDGE_MIN;
+            }
+        }
+
+        let count = edges.iter().filter(|&&e| e).count();
+        let dilated = dilate(&edges, width, height);
+        EdgeMap {
+            edges,
+            dilated,
+            count,
+        }
+    }
+
+    /// Edge change ratio from `prev` to this frame, from 0 (same edges) to
+    /// 1 (all edges replaced).
+    pub fn change_ratio(&self, prev: &EdgeMap) -> f64 {
+        let min_edges = (self.edges.len() as f64 * MIN_EDGE_FRACTION).max(1.0) as usize;
+        match (self.count >= min_edges, prev.count >= min_edges) {
+            (false, false) => return 0.0,
+            // Detail appearing from, or vanishing into, a blank frame
+            (true, false) | (false, true) => return 1.0,
+            (true, true) => {}
+        }
+        if self.edges.len() != prev.edges.len() {
+            return 1.0;
+        }
+
+        let entering = self
+            .edges
+            .iter()
+            .zip(&prev.dilated)
+            .filter(|&(&edge, &near)| edge && !near)
+            .count();
+        let exiting = prev
+            .edges
+            .iter()
+            .zip(&self.dilated)
+            .filter(|&(&edge, &near)| edge && !near)
+            .count();
+        (entering as f64 / self.count as f64).max(exiting as f64 / prev.count as f64)
+    }
+}
+
+/// Grow edges by `DILATION` pixels in each direction (a square window, done
+/// as a row pass then a column pass).
+fn dilate(edges: &[bool], width: usize, height: usize) -> Vec<bool> {
+    let mut rows = vec![false; edges.len()];
+    for y in 0..height {
+        for x in 0..width {
+            let (lo, hi) = (x.saturating_sub(DILATION), (x + DILATION).min(width - 1));
+            rows[y * width + x] = (lo..=hi).any(|x| edges[y * width + x]);
+        }
+    }
+    let mut out = vec![false; edges.len()];
+    for y in 0..height {
+        let (lo, hi) = (y.saturating_sub(DILATION), (y + DILATION).min(height - 1));
+        for x in 0..width {
+            out[y * width + x] = (lo..=hi).any(|y| rows[y * width + x]);
+        }
+    }
+    out
+}
+
+/// Cuts from per-frame ratios, where `ratios[i]` compares frame `i` with
+/// frame `i - 1`: frames reaching `threshold` at least `MIN_SCENE_LEN`
+/// frames after the previous cut. Frame 0 is always a cut.
+pub fn cuts(ratios: &[f64], threshold: f64) -> Vec<usize> {
+    let mut cuts = vec![0];
+    for (frame, &ratio) in ratios.iter().enumerate().skip(1) {
+        if ratio >= threshold && frame - cuts[cuts.len() - 1] >= MIN_SCENE_LEN {
+            cuts.push(frame);
+        }
+    }
+    cuts
+}
+
+/// Each frame's ratio over `threshold`, 1.0 exactly at it, for fusing with
+/// other detectors' strengths.
+pub fn strengths(ratios: &[f64], threshold: f64) -> BTreeMap<usize, f64> {
+    ratios
+        .iter()
+        .enumerate()
+        .skip(1)
+        .map(|(frame, &ratio)| {
+            let strength = if threshold > 0.0 {
+                ratio / threshold
+            } else {
+                0.0
+            };
+            (frame, strength)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filters::new_frame;
+    use av_scenechange::av_decoders::v_frame::chroma::ChromaSubsampling;
+    use std::num::NonZeroU8;
+
+    /// A 32x32 frame with a bright square at (x, y), scaled by `gain`.
+    fn square_frame(x: usize, y: usize, gain: f64) -> Frame<u8> {
+        let mut frame = new_frame(
+            32,
+            32,
+            ChromaSubsampling::Monochrome,
+            NonZeroU8::new(8).unwrap(),
+        )
+        .unwrap();
+        for (py, row) in frame.y_plane.rows_mut().enumerate() {
+            for (px, value) in row.iter_mut().enumerate() {
+                let inside = (x..x + 8).contains(&px) && (y..y + 8).contains(&py);
+                *value = ((if inside { 200.0 } else { 40.0 }) * gain) as u8;
+            }
+        }
+        frame
+    }
+
+    #[test]
+    fn test_change_ratio() {
+        let base = EdgeMap::new(&square_frame(4, 4, 1.0));
+        // Brightness change and a small move keep the edges
+        assert!(EdgeMap::new(&square_frame(4, 4, 1.2)).change_ratio(&base) < 0.01);
+        assert!(EdgeMap::new(&square_frame(5, 4, 1.0)).change_ratio(&base) < 0.01);
+        // The square jumping across the frame replaces every edge
+        assert_eq!(
+            EdgeMap::new(&square_frame(20, 20, 1.0)).change_ratio(&base),
+            1.0
+        );
+    }
+
+    #[test]
+    fn test_cuts() {
+        let mut ratios = vec![0.1; 40];
+        ratios[10] = 0.9;
+        ratios[20] = 0.9;
+        ratios[24] = 0.9;
+        assert_eq!(cuts(&ratios, DEFAULT_ECR_THRESHOLD), vec![0, 20]);
+        assert!((strengths(&ratios, DEFAULT_ECR_THRESHOLD)[&10] - 1.5).abs() < 1e-9);
+    }
+}
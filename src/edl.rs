@@ -0,0 +1,111 @@
+//! CMX3600 edit decision lists read as cut points for `--hints`.
+//!
+//! Each video event's record-in timecode is a cut. Frames count from the
+//! earliest record-in, since EDLs usually start the program at a timecode
+//! such as 01:00:00:00 rather than at zero.
+
+use anyhow::{bail, Result};
+
+/// Cut frames from an EDL, in file order, or None if `contents` isn't one.
+pub fn parse_edl(contents: &str, fps_num: u32, fps_den: u32) -> Result<Option<Vec<usize>>> {
+    let is_header = |line: &str| line.starts_with("TITLE:") || line.starts_with("FCM:");
+    let events: Vec<(usize, Vec<&str>)> = contents
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.split_whitespace().collect::<Vec<_>>()))
+        .filter(|(_, fields)| is_event(fields))
+        .collect();
+    if events.is_empty() && !contents.lines().any(|line| is_header(line.trim())) {
+        return Ok(None);
+    }
+
+    // Timecode frames tick at the nominal rate: 30 for 29.97 fps
+    let rate = (fps_num as f64 / fps_den.max(1) as f64).round().max(1.0) as usize;
+    let mut record_ins = Vec::new();
+    for (line, fields) in events {
+        let track = fields[2];
+        if !(track.starts_with('V') || track == "B") {
+            continue;
+        }
+        // Source in/out and record in/out are always the last four fields,
+        // after a dissolve or wipe's duration if there is one
+        let record_in = fields[fields.len() - 2];
+        let Some(frame) = timecode_frames(record_in, rate) else {
+            bail!("line {}: invalid record-in timecode {:?}", line, record_in);
+        };
+        record_ins.push(frame);
+    }
+
+    let origin = record_ins.iter().min().copied().unwrap_or(0);
+    Ok(Some(
+        record_ins.iter().map(|frame| frame - origin).collect(),
+    ))
+}
+
+/// An event line: an event number, then at least reel, track and edit type
+/// before the four timecodes.
+fn is_event(fields: &[&str]) -> bool {
+    fields.len() >= 8
+        && fields[0].bytes().all(|b| b.is_ascii_digit())
+        && fields[fields.len() - 4..]
+            .iter()
+            .all(|tc| tc.len() == 11 && tc.contains(':'))
+}
+
+/// Frames in `HH:MM:SS:FF`, or `HH:MM:SS;FF` for drop-frame timecode, which
+/// skips two frame numbers per 30 (four per 60) every minute except every
+/// tenth.
+fn timecode_frames(tc: &str, rate: usize) -> Option<usize> {
+    let drop_frame = tc.contains(';');
+    let fields: Vec<usize> = tc
+        .split([':', ';'])
+        .map(|field| field.parse().ok())
+        .collect::<Option<_>>()?;
+    let &[hours, minutes, seconds, frames] = fields.as_slice() else {
+        return None;
+    };
+    if minutes >= 60 || seconds >= 60 || frames >= rate {
+        return None;
+    }
+    let total_minutes = hours * 60 + minutes;
+    let nominal = (total_minutes * 60 + seconds) * rate + frames;
+    if !drop_frame || !rate.is_multiple_of(30) {
+        return Some(nominal);
+    }
+    let dropped = rate / 15 * (total_minutes - total_minutes / 10);
+    nominal.checked_sub(dropped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_edl() {
+        let edl = "TITLE: Reel cut
+FCM: NON-DROP FRAME
+
+001  A001     V     C        00:00:10:00 00:00:14:00 01:00:00:00 01:00:04:00
+* FROM CLIP NAME: a.mov
+002  A001     AA    C        00:00:10:00 00:00:14:00 01:00:00:00 01:00:04:00
+003  A002     V     C        00:01:00:00 00:01:02:12 01:00:04:00 01:00:06:12
+004  A003     V     D    012 00:02:00:00 00:02:05:00 01:00:06:12 01:00:11:12
+";
+        // The audio event is skipped; the dissolve starts at its record-in
+        assert_eq!(parse_edl(edl, 25, 1).unwrap(), Some(vec![0, 100, 162]));
+
+        assert_eq!(parse_edl("0\n100\n", 25, 1).unwrap(), None);
+        let bad = "001  A001     V     C        00:00:10:00 00:00:14:00 01:00:00:99 01:00:04:00";
+        assert!(parse_edl(bad, 25, 1).is_err());
+    }
+
+    #[test]
+    fn test_drop_frame_timecode() {
+        // 00:01:00;02 is the first frame number after the two skipped at
+        // the minute, frame 1800
+        assert_eq!(timecode_frames("00:01:00;02", 30), Some(1800));
+        // Every tenth minute keeps its numbers: 10 minutes at 29.97 fps
+        assert_eq!(timecode_frames("00:10:00;00", 30), Some(17982));
+        assert_eq!(timecode_frames("00:01:00:02", 30), Some(1802));
+    }
+}
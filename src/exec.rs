@@ -0,0 +1,186 @@
+//! Per-chunk command templating, optionally running the commands.
+
+use crate::script::shell_quote;
+use crate::timecode;
+use anyhow::{bail, Result};
+use std::path::Path;
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+/// Placeholders available in an exec template
+pub const PLACEHOLDERS: &[&str] = &[
+    "input", "stream", "index", "start", "end", "frames", "start_ts", "end_ts", "duration",
+];
+
+/// Expand `template` once per chunk.
+///
+/// `{input}` is shell-quoted, `{stream}` is a `-map` stream specifier
+/// (`v:0` for the main stream), `{start}`/`{end}`/`{frames}` are frame
+/// numbers (end exclusive), and `{start_ts}`/`{end_ts}`/`{duration}` are
+/// seconds. Other braces, such as shell `${VAR}`, are left alone.
+pub fn expand_template(
+    template: &str,
+    input: &Path,
+    stream: Option<usize>,
+    scene_starts: &[usize],
+    total_frames: usize,
+    fps_num: u32,
+    fps_den: u32,
+) -> Vec<String> {
+    let input = shell_quote(&input.to_string_lossy());
+    let stream = stream.map_or("v:0".to_string(), |index| index.to_string());
+
+    scene_starts
+        .iter()
+        .enumerate()
+        .map(|(index, &start)| {
+            let end = scene_starts.get(index + 1).copied().unwrap_or(total_frames);
+            let frames = end.saturating_sub(start);
+            let values = [
+                input.clone(),
+                stream.clone(),
+                index.to_string(),
+                start.to_string(),
+                end.to_string(),
+                frames.to_string(),
+                timecode::format_seconds(start, fps_num, fps_den),
+                timecode::format_seconds(end, fps_num, fps_den),
+                timecode::format_seconds(frames, fps_num, fps_den),
+            ];
+
+            expand(template, &values)
+        })
+        .collect()
+}
+
+/// Substitute placeholders in a single pass, so substituted values are
+/// never expanded again.
+fn expand(template: &str, values: &[String]) -> String {
+    let mut command = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        command.push_str(&rest[..open]);
+        rest = &rest[open..];
+        let placeholder = PLACEHOLDERS.iter().zip(values).find(|(name, _)| {
+            rest[1..].starts_with(*name) && rest[1 + name.len()..].starts_with('}')
+        });
+        match placeholder {
+            Some((name, value)) => {
+                command.push_str(value);
+                rest = &rest[name.len() + 2..];
+            }
+            None => {
+                command.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    command.push_str(rest);
+    command
+}
+
+/// Run each command with `sh -c`, at most `jobs` at a time.
+///
+/// Every command runs even if some fail; the error lists the failed chunk
+/// indices.
+pub fn run_commands(commands: &[String], jobs: usize, progress: bool) -> Result<()> {
+    let next = AtomicUsize::new(0);
+    let failed = Mutex::new(Vec::new());
+
+    thread::scope(|s| {
+        for _ in 0..jobs.clamp(1, commands.len().max(1)) {
+            s.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let Some(command) = commands.get(index) else {
+                    break;
+                };
+                let ok = Command::new("sh")
+                    .arg("-c")
+                    .arg(command)
+                    .status()
+                    .is_ok_and(|status| status.success());
+                if !ok {
+                    failed.lock().expect("failure list lock").push(index);
+                }
+                if progress {
+                    eprintln!("Chunk {} {}", index, if ok { "finished" } else { "failed" });
+                }
+            });
+        }
+    });
+
+    let mut failed = failed.into_inner().expect("failure list lock");
+    if !failed.is_empty() {
+        failed.sort();
+        bail!(
+            "{} of {} chunk commands failed (chunks {:?})",
+            failed.len(),
+            commands.len(),
+            failed
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand_template() {
+        let commands = expand_template(
+            "ffmpeg -i {input} -ss {start_ts} -to {end_ts} -frames:v {frames} chunk_{index}.mkv",
+            Path::new("in file.mkv"),
+            None,
+            &[0, 48],
+            72,
+            24,
+            1,
+        );
+        assert_eq!(
+            commands,
+            vec![
+                "ffmpeg -i 'in file.mkv' -ss 0.000000 -to 2.000000 -frames:v 48 chunk_0.mkv",
+                "ffmpeg -i 'in file.mkv' -ss 2.000000 -to 3.000000 -frames:v 24 chunk_1.mkv",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_expand_template_leaves_shell_braces() {
+        let commands = expand_template(
+            "${OUT}/{stream}-{start}-{end}-{duration}",
+            Path::new("in.mkv"),
+            Some(2),
+            &[0],
+            30,
+            30,
+            1,
+        );
+        assert_eq!(commands, vec!["${OUT}/2-0-30-1.000000"]);
+    }
+
+    #[test]
+    fn test_expand_template_values_not_reexpanded() {
+        let commands = expand_template(
+            "{input} {index}",
+            Path::new("{index}.mkv"),
+            None,
+            &[0],
+            10,
+            24,
+            1,
+        );
+        assert_eq!(commands, vec!["'{index}.mkv' 0"]);
+    }
+
+    #[test]
+    fn test_run_commands_reports_failures() {
+        let commands = ["exit 0", "exit 1", "exit 0", "exit 3"].map(String::from);
+        let err = run_commands(&commands, 2, false).unwrap_err();
+        assert!(err.to_string().contains("chunks [1, 3]"));
+        assert!(run_commands(&commands[..1], 4, false).is_ok());
+    }
+}
@@ -0,0 +1,522 @@
+//! Direct FFmpeg access for what av-decoders doesn't expose.
+//!
+//! av-decoders always decodes the "best" video stream. `StreamDecoder`
+//! decodes any video stream by index, producing the same frame layout,
+//! and can pin decoding to one thread for `--deterministic`.
+//! av-decoders also can't seek, which `Seeker` does for `shear verify`,
+//! or report packet order, which `packet_timestamps` reads for
+//! `--numbering decode`, and `video_packets` with byte offsets for
+//! `--byte-ranges`. `audio_levels` decodes the main audio stream for
+//! the silence map.
+//!
+//! Every type here owns its FFmpeg contexts outright and is `Send`, so
+//! independent detections can decode on separate threads. FFmpeg's
+//! process-wide setup runs once, behind `init`.
+
+use crate::byte_ranges::VideoPacket;
+use crate::silence::LevelMeter;
+use crate::source::FrameSource;
+use anyhow::{bail, Context, Result};
+use av_scenechange::av_decoders::v_frame::{chroma::ChromaSubsampling, frame::Frame, pixel::Pixel};
+use av_scenechange::av_decoders::{Rational32, VideoDetails};
+use ffmpeg_the_third::{
+    codec, format, format::context, format::stream::Disposition, frame, media, threading,
+    Dictionary, Rational,
+};
+use std::num::{NonZeroU8, NonZeroUsize};
+use std::path::Path;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+/// Initialize FFmpeg once per process. `ffmpeg_the_third::init` fills
+/// global error string tables, which must not be rewritten while another
+/// thread is opening a file.
+fn init() -> Result<()> {
+    static INIT: OnceLock<Result<(), ffmpeg_the_third::Error>> = OnceLock::new();
+    (*INIT.get_or_init(ffmpeg_the_third::init)).context("Failed to initialize FFmpeg")
+}
+
+/// Indices of all video streams in a file, skipping attached pictures
+/// such as cover art.
+pub fn video_streams(path: &Path) -> Result<Vec<usize>> {
+    init()?;
+    let input_ctx =
+        format::input(path).with_context(|| format!("Failed to open input {:?}", path))?;
+
+    Ok(input_ctx
+        .streams()
+        .filter(|stream| {
+            stream.parameters().medium() == media::Type::Video
+                && !stream.disposition().contains(Disposition::ATTACHED_PIC)
+        })
+        .map(|stream| stream.index())
+        .collect())
+}
+
+/// Index of the video stream av-decoders decodes.
+pub fn best_video_stream(path: &Path) -> Result<usize> {
+    init()?;
+    let input_ctx =
+        format::input(path).with_context(|| format!("Failed to open input {:?}", path))?;
+    let stream = input_ctx
+        .streams()
+        .best(media::Type::Video)
+        .with_context(|| format!("No video stream in {:?}", path))?;
+    Ok(stream.index())
+}
+
+/// Presentation timestamps of a video stream's packets, in decode order.
+/// With no `stream_index`, the stream av-decoders picks is used. Only
+/// demuxes; nothing is decoded.
+pub fn packet_timestamps(path: &Path, stream_index: Option<usize>) -> Result<Vec<i64>> {
+    Ok(video_packets(path, stream_index)?
+        .iter()
+        .map(|packet| packet.pts)
+        .collect())
+}
+
+/// Timestamp, byte offset and keyframe flag of a video stream's packets,
+/// in decode order, for `--byte-ranges`. Stream selection as for
+/// `packet_timestamps`.
+pub fn video_packets(path: &Path, stream_index: Option<usize>) -> Result<Vec<VideoPacket>> {
+    init()?;
+    let mut input_ctx =
+        format::input(path).with_context(|| format!("Failed to open input {:?}", path))?;
+    let stream_index = match stream_index {
+        Some(index) => index,
+        None => input_ctx
+            .streams()
+            .best(media::Type::Video)
+            .with_context(|| format!("No video stream in {:?}", path))?
+            .index(),
+    };
+
+    let mut packets = Vec::new();
+    for result in input_ctx.packets() {
+        let (stream, packet) = result?;
+        if stream.index() != stream_index {
+            continue;
+        }
+        let Some(pts) = packet.pts() else {
+            bail!(
+                "Stream {} has packets without presentation timestamps",
+                stream_index
+            );
+        };
+        packets.push(VideoPacket {
+            pts,
+            // -1 when unknown
+            offset: u64::try_from(packet.position()).ok(),
+            key: packet.is_key(),
+        });
+    }
+    Ok(packets)
+}
+
+/// Per-video-frame levels of the file's main audio stream, in dBFS, for
+/// the silence map.
+pub fn audio_levels(path: &Path, fps_num: u32, fps_den: u32) -> Result<Vec<f64>> {
+    init()?;
+    let mut input_ctx =
+        format::input(path).with_context(|| format!("Failed to open input {:?}", path))?;
+    let (mut decoder, stream_index) = {
+        let stream = input_ctx
+            .streams()
+            .best(media::Type::Audio)
+            .with_context(|| format!("No audio stream in {:?}", path))?;
+        let context = codec::context::Context::from_parameters(stream.parameters())?;
+        (context.decoder().audio()?, stream.index())
+    };
+
+    let mut meter = LevelMeter::new(decoder.rate(), fps_num, fps_den);
+    let mut decoded = frame::Audio::empty();
+    let mut drain = |decoder: &mut codec::decoder::Audio, meter: &mut LevelMeter| {
+        while decoder.receive_frame(&mut decoded).is_ok() {
+            meter.push(decoded.samples(), mean_square(&decoded));
+        }
+    };
+    for result in input_ctx.packets() {
+        let (stream, packet) = result?;
+        if stream.index() == stream_index {
+            // Decode errors on individual packets are not fatal
+            let _ = decoder.send_packet(&packet);
+            drain(&mut decoder, &mut meter);
+        }
+    }
+    let _ = decoder.send_eof();
+    drain(&mut decoder, &mut meter);
+    Ok(meter.levels_db())
+}
+
+/// Mean square sample value of a decoded audio frame, full scale = 1.0.
+///
+/// Planar frames hold `samples()` values per plane; a packed frame's one
+/// plane interleaves every channel, `samples()` times the channel count.
+fn mean_square(decoded: &frame::Audio) -> f64 {
+    let values = if decoded.is_packed() {
+        decoded.samples() * channels(decoded)
+    } else {
+        decoded.samples()
+    };
+    let sum = |width: usize, scale: fn(&[u8]) -> f64| {
+        let mut sum = 0.0;
+        let mut count = 0;
+        for plane in 0..decoded.planes() {
+            // Planes are padded past their last value
+            let data = decoded.data(plane);
+            let len = (values * width).min(data.len());
+            for sample in data[..len].chunks_exact(width) {
+                let value = scale(sample);
+                sum += value * value;
+                count += 1;
+            }
+        }
+        (sum, count)
+    };
+
+    use format::Sample as S;
+    let (sum, count) = match decoded.format() {
+        S::U8(_) => sum(1, |b| (b[0] as f64 - 128.0) / 128.0),
+        S::I16(_) => sum(2, |b| i16::from_ne_bytes([b[0], b[1]]) as f64 / 32768.0),
+        S::I32(_) => sum(4, |b| {
+            i32::from_ne_bytes(b.try_into().unwrap()) as f64 / 2147483648.0
+        }),
+        S::F32(_) => sum(4, |b| f32::from_ne_bytes(b.try_into().unwrap()) as f64),
+        S::F64(_) => sum(8, |b| f64::from_ne_bytes(b.try_into().unwrap())),
+        // 64-bit integer audio has no decoders in practice
+        S::I64(_) | S::None => (0.0, 0),
+    };
+    sum / count.max(1) as f64
+}
+
+/// Channels in a decoded audio frame, from whichever API the linked FFmpeg
+/// has, as ffmpeg-the-third itself does.
+#[cfg(ffmpeg_5_1)]
+fn channels(decoded: &frame::Audio) -> usize {
+    decoded.ch_layout().channels() as usize
+}
+
+#[cfg(not(ffmpeg_5_1))]
+fn channels(decoded: &frame::Audio) -> usize {
+    decoded.channels() as usize
+}
+
+/// Frame threading with FFmpeg's default thread count.
+fn frame_threads() -> threading::Config {
+    threading::Config::kind(threading::Type::Frame)
+}
+
+/// Decoder for a single, explicitly chosen video stream.
+pub struct StreamDecoder {
+    input_ctx: context::Input,
+    decoder: codec::decoder::Video,
+    stream_index: usize,
+    details: VideoDetails,
+    eof_sent: bool,
+    /// Treat read errors as the end of the stream, since a followed file
+    /// ends with a read timeout
+    following: bool,
+}
+
+impl StreamDecoder {
+    pub fn open(path: &Path, stream_index: usize) -> Result<Self> {
+        init()?;
+        let input_ctx =
+            format::input(path).with_context(|| format!("Failed to open input {:?}", path))?;
+        Self::from_input(input_ctx, path, stream_index, false, frame_threads())
+    }
+
+    /// Open a stream decoded on a single thread, for `--deterministic`.
+    /// Threaded decoding is bit-exact on valid streams, but how damaged
+    /// ones are concealed can depend on thread scheduling.
+    pub fn open_single_threaded(path: &Path, stream_index: usize) -> Result<Self> {
+        init()?;
+        let input_ctx =
+            format::input(path).with_context(|| format!("Failed to open input {:?}", path))?;
+        Self::from_input(
+            input_ctx,
+            path,
+            stream_index,
+            false,
+            threading::Config::count(1),
+        )
+    }
+
+    /// Open a stream of a file that is still being written. Reads at the
+    /// end of the file wait for more data, and the stream ends once none
+    /// has arrived for `idle`.
+    pub fn open_following(path: &Path, stream_index: usize, idle: Duration) -> Result<Self> {
+        init()?;
+        let mut options = Dictionary::new();
+        options.set("follow", "1");
+        options.set("rw_timeout", &idle.as_micros().to_string());
+        let input_ctx = format::input_with_dictionary(path, options)
+            .with_context(|| format!("Failed to open input {:?}", path))?;
+        Self::from_input(input_ctx, path, stream_index, true, frame_threads())
+    }
+
+    fn from_input(
+        input_ctx: context::Input,
+        path: &Path,
+        stream_index: usize,
+        following: bool,
+        threads: threading::Config,
+    ) -> Result<Self> {
+        let (decoder, details) = {
+            let stream = input_ctx
+                .stream(stream_index)
+                .with_context(|| format!("No stream {} in {:?}", stream_index, path))?;
+
+            let mut context = codec::context::Context::from_parameters(stream.parameters())?;
+            context.set_threading(threads);
+            let decoder = context.decoder().video().with_context(|| {
+                format!("Stream {} is not a decodable video stream", stream_index)
+            })?;
+
+            let (bit_depth, chroma_sampling) = pixel_layout(decoder.format())?;
+            let rate = stream.rate();
+            let details = VideoDetails {
+                width: decoder.width() as usize,
+                height: decoder.height() as usize,
+                bit_depth,
+                chroma_sampling,
+                frame_rate: Rational32::new(rate.numerator(), rate.denominator().max(1)),
+                total_frames: usize::try_from(stream.frames()).ok().filter(|&n| n > 0),
+            };
+            (decoder, details)
+        };
+
+        Ok(Self {
+            input_ctx,
+            decoder,
+            stream_index,
+            details,
+            eof_sent: false,
+            following,
+        })
+    }
+
+    fn convert<T: Pixel>(&self, decoded: &frame::Video) -> Result<Frame<T>> {
+        let bit_depth = NonZeroU8::new(self.details.bit_depth as u8).expect("bit depth is nonzero");
+        let mut frame = crate::filters::new_frame::<T>(
+            self.details.width,
+            self.details.height,
+            self.details.chroma_sampling,
+            bit_depth,
+        )?;
+
+        let planes = [
+            Some(&mut frame.y_plane),
+            frame.u_plane.as_mut(),
+            frame.v_plane.as_mut(),
+        ];
+        for (index, plane) in planes.into_iter().enumerate() {
+            if let Some(plane) = plane {
+                let stride = NonZeroUsize::new(decoded.stride(index))
+                    .context("Decoded frame has zero stride")?;
+                plane.copy_from_u8_slice_with_stride(decoded.data(index), stride)?;
+            }
+        }
+
+        Ok(frame)
+    }
+}
+
+impl<T: Pixel> FrameSource<T> for StreamDecoder {
+    fn video_details(&self) -> VideoDetails {
+        self.details
+    }
+
+    fn read_frame(&mut self) -> Result<Option<Frame<T>>> {
+        let mut decoded = frame::Video::empty();
+        loop {
+            if self.decoder.receive_frame(&mut decoded).is_ok() {
+                return self.convert(&decoded).map(Some);
+            }
+            if self.eof_sent {
+                return Ok(None);
+            }
+
+            let packet = match self.input_ctx.packets().next().transpose() {
+                Ok(packet) => packet.map(|(_, packet)| packet),
+                Err(_) if self.following => None,
+                Err(e) => return Err(e.into()),
+            };
+            match packet {
+                // Decode errors on individual packets are not fatal
+                Some(packet) if packet.stream() == self.stream_index => {
+                    let _ = self.decoder.send_packet(&packet);
+                }
+                Some(_) => {}
+                None => {
+                    let _ = self.decoder.send_eof();
+                    self.eof_sent = true;
+                }
+            }
+        }
+    }
+}
+
+/// Where a seek to a boundary landed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SeekProbe {
+    /// First frame decoded after the seek
+    pub landed: Option<usize>,
+    /// True if the boundary frame itself was decoded
+    pub reached: bool,
+    /// Packets the decoder rejected between the seek and the boundary
+    pub decode_errors: usize,
+}
+
+/// Seeks the main video stream to frame numbers.
+pub struct Seeker {
+    input_ctx: context::Input,
+    decoder: codec::decoder::Video,
+    stream_index: usize,
+    time_base: Rational,
+    /// Stream start time in `time_base` units
+    start_time: i64,
+    frame_rate: Rational,
+}
+
+impl Seeker {
+    pub fn open(path: &Path) -> Result<Self> {
+        init()?;
+        let input_ctx =
+            format::input(path).with_context(|| format!("Failed to open input {:?}", path))?;
+        let (decoder, stream_index, time_base, start_time, frame_rate) = {
+            let stream = input_ctx
+                .streams()
+                .best(media::Type::Video)
+                .with_context(|| format!("No video stream in {:?}", path))?;
+
+            let context = codec::context::Context::from_parameters(stream.parameters())?;
+            let decoder = context.decoder().video()?;
+
+            let frame_rate = match stream.avg_frame_rate() {
+                rate if rate.numerator() > 0 && rate.denominator() > 0 => rate,
+                _ => stream.rate(),
+            };
+            if frame_rate.numerator() <= 0 || frame_rate.denominator() <= 0 {
+                bail!("Video stream in {:?} has no frame rate", path);
+            }
+            // AV_NOPTS_VALUE when unknown
+            let start_time = match stream.start_time() {
+                i64::MIN => 0,
+                start => start,
+            };
+            (
+                decoder,
+                stream.index(),
+                stream.time_base(),
+                start_time,
+                frame_rate,
+            )
+        };
+
+        Ok(Self {
+            input_ctx,
+            decoder,
+            stream_index,
+            time_base,
+            start_time,
+            frame_rate,
+        })
+    }
+
+    /// Seek to the keyframe at or before `frame`, then decode forward until
+    /// `frame` or the first frame after it.
+    pub fn probe(&mut self, frame: usize) -> Result<SeekProbe> {
+        let target_secs = self.frame_secs(frame) + self.start_secs();
+        // Seeking without a stream index uses AV_TIME_BASE (microseconds)
+        let ts = (target_secs * 1_000_000.0).round() as i64;
+        self.input_ctx
+            .seek(ts, ..=ts)
+            .with_context(|| format!("Seek to frame {} failed", frame))?;
+        self.decoder.flush();
+
+        let mut probe = SeekProbe::default();
+        let mut decoded = frame::Video::empty();
+        let mut eof_sent = false;
+        loop {
+            while self.decoder.receive_frame(&mut decoded).is_ok() {
+                let Some(timestamp) = decoded.timestamp() else {
+                    continue;
+                };
+                let number = self.frame_number(timestamp);
+                probe.landed.get_or_insert(number);
+                if number >= frame {
+                    probe.reached = number == frame;
+                    return Ok(probe);
+                }
+            }
+            if eof_sent {
+                return Ok(probe);
+            }
+
+            let packet = self
+                .input_ctx
+                .packets()
+                .next()
+                .transpose()?
+                .map(|(_, packet)| packet);
+            match packet {
+                Some(packet) if packet.stream() == self.stream_index => {
+                    if self.decoder.send_packet(&packet).is_err() {
+                        probe.decode_errors += 1;
+                    }
+                }
+                Some(_) => {}
+                None => {
+                    let _ = self.decoder.send_eof();
+                    eof_sent = true;
+                }
+            }
+        }
+    }
+
+    fn start_secs(&self) -> f64 {
+        self.start_time as f64 * f64::from(self.time_base)
+    }
+
+    fn frame_secs(&self, frame: usize) -> f64 {
+        frame as f64 / f64::from(self.frame_rate)
+    }
+
+    /// Frame number of a decoded frame's timestamp.
+    fn frame_number(&self, timestamp: i64) -> usize {
+        let secs = timestamp as f64 * f64::from(self.time_base) - self.start_secs();
+        (secs * f64::from(self.frame_rate)).round().max(0.0) as usize
+    }
+}
+
+/// Bit depth and chroma subsampling for the planar YUV formats the
+/// detector supports.
+fn pixel_layout(fmt: format::Pixel) -> Result<(usize, ChromaSubsampling)> {
+    use format::Pixel as P;
+    Ok(match fmt {
+        P::YUV420P | P::YUVJ420P => (8, ChromaSubsampling::Yuv420),
+        P::YUV422P | P::YUVJ422P => (8, ChromaSubsampling::Yuv422),
+        P::YUV444P | P::YUVJ444P => (8, ChromaSubsampling::Yuv444),
+        P::GRAY8 => (8, ChromaSubsampling::Monochrome),
+        P::YUV420P10LE => (10, ChromaSubsampling::Yuv420),
+        P::YUV422P10LE => (10, ChromaSubsampling::Yuv422),
+        P::YUV444P10LE => (10, ChromaSubsampling::Yuv444),
+        P::YUV420P12LE => (12, ChromaSubsampling::Yuv420),
+        P::YUV422P12LE => (12, ChromaSubsampling::Yuv422),
+        P::YUV444P12LE => (12, ChromaSubsampling::Yuv444),
+        other => bail!("Unsupported pixel format {:?}", other),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decoders_are_send() {
+        fn assert_send<T: Send>() {}
+        assert_send::<StreamDecoder>();
+        assert_send::<Seeker>();
+    }
+}
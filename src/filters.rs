@@ -0,0 +1,493 @@
+//! Frame pre-filters applied between decoding and analysis.
+//!
+//! Filters change what the detector and frame statistics see, never the
+//! frame numbering, so boundaries stay valid for the original source.
+
+use crate::segments::FrameRange;
+use anyhow::{bail, Result};
+use av_scenechange::av_decoders::v_frame::{
+    chroma::ChromaSubsampling,
+    frame::{Frame, FrameBuilder},
+    pixel::Pixel,
+    plane::Plane,
+};
+use av_scenechange::av_decoders::VideoDetails;
+use clap::ValueEnum;
+use std::num::{NonZeroU8, NonZeroUsize};
+use std::str::FromStr;
+
+/// Luma padding used by av-decoders; the detector expects the same layout.
+const LUMA_PADDING: usize = 64 + 16 + 8;
+
+/// Clockwise rotation applied to each frame
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Rotation {
+    #[default]
+    #[value(name = "0")]
+    None,
+    #[value(name = "90")]
+    Cw90,
+    #[value(name = "180")]
+    Cw180,
+    #[value(name = "270")]
+    Cw270,
+}
+
+impl Rotation {
+    fn swaps_dimensions(self) -> bool {
+        matches!(self, Rotation::Cw90 | Rotation::Cw270)
+    }
+}
+
+/// A rectangle in source frame coordinates
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl FromStr for Region {
+    type Err = String;
+
+    /// Parse `x:y:w:h`, e.g. `0:0:1920:960`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts = s
+            .split(':')
+            .map(|v| {
+                v.trim()
+                    .parse::<usize>()
+                    .map_err(|_| format!("invalid region value {:?}", v))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let [x, y, width, height] = parts[..] else {
+            return Err("expected x:y:w:h, e.g. 0:0:1920:960".to_string());
+        };
+        if width == 0 || height == 0 {
+            return Err("region width and height must be greater than zero".to_string());
+        }
+        Ok(Region {
+            x,
+            y,
+            width,
+            height,
+        })
+    }
+}
+
+impl Region {
+    /// Check the region lies inside the frame and on chroma sample
+    /// boundaries, so every plane can be cut at the same place.
+    fn validate(&self, name: &str, details: &VideoDetails) -> Result<()> {
+        let outside = |offset: usize, size: usize, limit: usize| {
+            offset.checked_add(size).is_none_or(|end| end > limit)
+        };
+        if outside(self.x, self.width, details.width)
+            || outside(self.y, self.height, details.height)
+        {
+            bail!(
+                "{} {}:{}:{}:{} is outside the {}x{} frame",
+                name,
+                self.x,
+                self.y,
+                self.width,
+                self.height,
+                details.width,
+                details.height
+            );
+        }
+        if let Some((ss_x, ss_y)) = details.chroma_sampling.subsample_ratio() {
+            let (ss_x, ss_y) = (ss_x.get() as usize, ss_y.get() as usize);
+            let aligned = self.x.is_multiple_of(ss_x)
+                && self.width.is_multiple_of(ss_x)
+                && self.y.is_multiple_of(ss_y)
+                && self.height.is_multiple_of(ss_y);
+            if !aligned {
+                bail!(
+                    "{} offsets and sizes must be multiples of {}x{} for this chroma subsampling",
+                    name,
+                    ss_x,
+                    ss_y
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The set of pre-filters for a detection pass.
+///
+/// The frame is rotated first, so ignored regions and the region of
+/// interest are given in displayed coordinates; then ignored regions are
+/// blanked and the region of interest is cut out. Frames in excluded
+/// ranges skip all of them and are not analyzed.
+#[derive(Debug, Clone, Default)]
+pub struct PreFilters {
+    pub rotate: Rotation,
+    /// Parts of the rotated frame zeroed out before analysis, such as
+    /// overlays
+    pub ignore: Vec<Region>,
+    /// Only analyze this part of the rotated frame
+    pub roi: Option<Region>,
+    /// Frame ranges skipped entirely during analysis
+    pub exclude: Vec<FrameRange>,
+}
+
+impl PreFilters {
+    /// Check the filters can be applied to frames of the given layout.
+    pub fn validate(&self, details: &VideoDetails) -> Result<()> {
+        if self.rotate.swaps_dimensions() && details.chroma_sampling == ChromaSubsampling::Yuv422 {
+            bail!("Rotating 4:2:2 video by 90 or 270 degrees is not supported");
+        }
+        let (width, height) = self.rotated_size(details.width, details.height);
+        let rotated = VideoDetails {
+            width,
+            height,
+            ..*details
+        };
+        for region in &self.ignore {
+            region.validate("Ignore region", &rotated)?;
+        }
+        if let Some(roi) = &self.roi {
+            roi.validate("Region of interest", &rotated)?;
+        }
+        Ok(())
+    }
+
+    /// Frame size after rotation, which regions are given in.
+    fn rotated_size(&self, width: usize, height: usize) -> (usize, usize) {
+        if self.rotate.swaps_dimensions() {
+            (height, width)
+        } else {
+            (width, height)
+        }
+    }
+
+    /// Frame size after filtering.
+    pub fn output_size(&self, width: usize, height: usize) -> (usize, usize) {
+        self.roi
+            .map_or_else(|| self.rotated_size(width, height), |r| (r.width, r.height))
+    }
+
+    /// True if frame `frame` is in an excluded range.
+    pub fn excludes(&self, frame: usize) -> bool {
+        self.exclude
+            .iter()
+            .any(|range| (range.start..range.end).contains(&frame))
+    }
+
+    /// Apply all filters to a decoded frame.
+    pub fn apply<T: Pixel>(&self, frame: Frame<T>) -> Result<Frame<T>> {
+        let mut frame = match self.rotate {
+            Rotation::None => frame,
+            rotate => rotate_frame(&frame, rotate)?,
+        };
+        for region in &self.ignore {
+            blank_region(&mut frame, region);
+        }
+        match &self.roi {
+            Some(roi) => crop_frame(&frame, roi),
+            None => Ok(frame),
+        }
+    }
+}
+
+/// Allocate a frame with the same padding the decoders use.
+pub fn new_frame<T: Pixel>(
+    width: usize,
+    height: usize,
+    subsampling: ChromaSubsampling,
+    bit_depth: NonZeroU8,
+) -> Result<Frame<T>> {
+    let (Some(width), Some(height)) = (NonZeroUsize::new(width), NonZeroUsize::new(height)) else {
+        bail!("Filtered frame has zero size");
+    };
+    Ok(FrameBuilder::new(width, height, subsampling, bit_depth)
+        .luma_padding_left(LUMA_PADDING)
+        .luma_padding_right(LUMA_PADDING)
+        .luma_padding_top(LUMA_PADDING)
+        .luma_padding_bottom(LUMA_PADDING)
+        .build()?)
+}
+
+/// Frame size after `downscale_half`.
+pub fn half_size(width: usize, height: usize) -> (usize, usize) {
+    (width.div_ceil(2), height.div_ceil(2))
+}
+
+/// Halve a frame's width and height by averaging 2x2 blocks (clamped at the
+/// edges), for coarse-scale analysis.
+pub fn downscale_half<T: Pixel>(frame: &Frame<T>) -> Result<Frame<T>> {
+    let (width, height) = half_size(frame.y_plane.width().get(), frame.y_plane.height().get());
+    let mut out = new_frame(width, height, frame.subsampling, frame.bit_depth)?;
+    downscale_plane(&frame.y_plane, &mut out.y_plane);
+    if let (Some(src), Some(dst)) = (&frame.u_plane, &mut out.u_plane) {
+        downscale_plane(src, dst);
+    }
+    if let (Some(src), Some(dst)) = (&frame.v_plane, &mut out.v_plane) {
+        downscale_plane(src, dst);
+    }
+    Ok(out)
+}
+
+fn downscale_plane<T: Pixel>(src: &Plane<T>, dst: &mut Plane<T>) {
+    let src_rows: Vec<&[T]> = src.rows().collect();
+    let (w, h) = (src.width().get(), src.height().get());
+    let px = |x: usize, y: usize| src_rows[y.min(h - 1)][x.min(w - 1)].to_u32().unwrap_or(0);
+
+    for (y, row) in dst.rows_mut().enumerate() {
+        for (x, out) in row.iter_mut().enumerate() {
+            let (sx, sy) = (x * 2, y * 2);
+            let sum = px(sx, sy) + px(sx + 1, sy) + px(sx, sy + 1) + px(sx + 1, sy + 1);
+            *out = T::from((sum + 2) / 4).unwrap_or_default();
+        }
+    }
+}
+
+/// Zero a region in every plane. Blanked pixels are identical in every
+/// frame, so they add nothing to frame differences.
+fn blank_region<T: Pixel>(frame: &mut Frame<T>, region: &Region) {
+    blank_plane(&mut frame.y_plane, region);
+
+    if let Some((ss_x, ss_y)) = frame.subsampling.subsample_ratio() {
+        let (ss_x, ss_y) = (ss_x.get() as usize, ss_y.get() as usize);
+        let chroma_region = Region {
+            x: region.x / ss_x,
+            y: region.y / ss_y,
+            width: region.width / ss_x,
+            height: region.height / ss_y,
+        };
+        for plane in [frame.u_plane.as_mut(), frame.v_plane.as_mut()]
+            .into_iter()
+            .flatten()
+        {
+            blank_plane(plane, &chroma_region);
+        }
+    }
+}
+
+fn blank_plane<T: Pixel>(plane: &mut Plane<T>, region: &Region) {
+    for row in plane.rows_mut().skip(region.y).take(region.height) {
+        row[region.x..region.x + region.width].fill(T::default());
+    }
+}
+
+fn crop_frame<T: Pixel>(frame: &Frame<T>, region: &Region) -> Result<Frame<T>> {
+    let mut out = new_frame(
+        region.width,
+        region.height,
+        frame.subsampling,
+        frame.bit_depth,
+    )?;
+    crop_plane(&frame.y_plane, &mut out.y_plane, region.x, region.y);
+
+    if let Some((ss_x, ss_y)) = frame.subsampling.subsample_ratio() {
+        let x = region.x / ss_x.get() as usize;
+        let y = region.y / ss_y.get() as usize;
+        if let (Some(src), Some(dst)) = (&frame.u_plane, &mut out.u_plane) {
+            crop_plane(src, dst, x, y);
+        }
+        if let (Some(src), Some(dst)) = (&frame.v_plane, &mut out.v_plane) {
+            crop_plane(src, dst, x, y);
+        }
+    }
+    Ok(out)
+}
+
+fn crop_plane<T: Pixel>(src: &Plane<T>, dst: &mut Plane<T>, x: usize, y: usize) {
+    for (src_row, dst_row) in src.rows().skip(y).zip(dst.rows_mut()) {
+        dst_row.copy_from_slice(&src_row[x..x + dst_row.len()]);
+    }
+}
+
+fn rotate_frame<T: Pixel>(frame: &Frame<T>, rotation: Rotation) -> Result<Frame<T>> {
+    let width = frame.y_plane.width().get();
+    let height = frame.y_plane.height().get();
+    let (out_width, out_height) = if rotation.swaps_dimensions() {
+        (height, width)
+    } else {
+        (width, height)
+    };
+
+    let mut out = new_frame(out_width, out_height, frame.subsampling, frame.bit_depth)?;
+    rotate_plane(&frame.y_plane, &mut out.y_plane, rotation);
+    if let (Some(src), Some(dst)) = (&frame.u_plane, &mut out.u_plane) {
+        rotate_plane(src, dst, rotation);
+    }
+    if let (Some(src), Some(dst)) = (&frame.v_plane, &mut out.v_plane) {
+        rotate_plane(src, dst, rotation);
+    }
+    Ok(out)
+}
+
+fn rotate_plane<T: Pixel>(src: &Plane<T>, dst: &mut Plane<T>, rotation: Rotation) {
+    let src_rows: Vec<&[T]> = src.rows().collect();
+    let (w, h) = (src.width().get(), src.height().get());
+
+    for (y, row) in dst.rows_mut().enumerate() {
+        for (x, px) in row.iter_mut().enumerate() {
+            *px = match rotation {
+                Rotation::None => src_rows[y][x],
+                Rotation::Cw90 => src_rows[h - 1 - x][y],
+                Rotation::Cw180 => src_rows[h - 1 - y][w - 1 - x],
+                Rotation::Cw270 => src_rows[x][w - 1 - y],
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use av_scenechange::av_decoders::Rational32;
+
+    /// A 3x2 luma-only frame:
+    ///   1 2 3
+    ///   4 5 6
+    fn test_frame() -> Frame<u8> {
+        let mut frame = new_frame(
+            3,
+            2,
+            ChromaSubsampling::Monochrome,
+            NonZeroU8::new(8).unwrap(),
+        )
+        .unwrap();
+        for (y, row) in frame.y_plane.rows_mut().enumerate() {
+            for (x, px) in row.iter_mut().enumerate() {
+                *px = (y * 3 + x + 1) as u8;
+            }
+        }
+        frame
+    }
+
+    fn details(width: usize, height: usize, chroma_sampling: ChromaSubsampling) -> VideoDetails {
+        VideoDetails {
+            width,
+            height,
+            bit_depth: 8,
+            chroma_sampling,
+            frame_rate: Rational32::new(24, 1),
+            total_frames: None,
+        }
+    }
+
+    fn luma(frame: &Frame<u8>) -> Vec<Vec<u8>> {
+        frame.y_plane.rows().map(|r| r.to_vec()).collect()
+    }
+
+    #[test]
+    fn test_rotate_90() {
+        let filters = PreFilters {
+            rotate: Rotation::Cw90,
+            ..Default::default()
+        };
+        let out = filters.apply(test_frame()).unwrap();
+        assert_eq!(luma(&out), vec![vec![4, 1], vec![5, 2], vec![6, 3]]);
+    }
+
+    #[test]
+    fn test_rotate_180() {
+        let filters = PreFilters {
+            rotate: Rotation::Cw180,
+            ..Default::default()
+        };
+        let out = filters.apply(test_frame()).unwrap();
+        assert_eq!(luma(&out), vec![vec![6, 5, 4], vec![3, 2, 1]]);
+    }
+
+    #[test]
+    fn test_rotate_270() {
+        let filters = PreFilters {
+            rotate: Rotation::Cw270,
+            ..Default::default()
+        };
+        let out = filters.apply(test_frame()).unwrap();
+        assert_eq!(luma(&out), vec![vec![3, 6], vec![2, 5], vec![1, 4]]);
+    }
+
+    #[test]
+    fn test_rotate_rejects_422() {
+        let filters = PreFilters {
+            rotate: Rotation::Cw90,
+            ..Default::default()
+        };
+        assert!(filters
+            .validate(&details(1920, 1080, ChromaSubsampling::Yuv422))
+            .is_err());
+        assert!(filters
+            .validate(&details(1920, 1080, ChromaSubsampling::Yuv420))
+            .is_ok());
+    }
+
+    #[test]
+    fn test_downscale_half() {
+        let frame = downscale_half(&test_frame()).unwrap();
+        // Blocks (1 2 4 5) and (3 3 6 6), the edge column repeated
+        assert_eq!(luma(&frame), vec![vec![3, 5]]);
+    }
+
+    #[test]
+    fn test_crop() {
+        let filters = PreFilters {
+            roi: Some("1:0:2:2".parse().unwrap()),
+            ..Default::default()
+        };
+        let out = filters.apply(test_frame()).unwrap();
+        assert_eq!(luma(&out), vec![vec![2, 3], vec![5, 6]]);
+    }
+
+    #[test]
+    fn test_rotate_then_crop() {
+        // The region is in displayed coordinates: the bottom two rows of
+        // the rotated 2x3 frame, which don't fit the stored 3x2 one
+        let filters = PreFilters {
+            roi: Some("0:1:2:2".parse().unwrap()),
+            ignore: vec!["1:2:1:1".parse().unwrap()],
+            rotate: Rotation::Cw90,
+            ..Default::default()
+        };
+        let mono = details(3, 2, ChromaSubsampling::Monochrome);
+        assert!(filters.validate(&mono).is_ok());
+        assert_eq!(filters.output_size(3, 2), (2, 2));
+        let out = filters.apply(test_frame()).unwrap();
+        assert_eq!(luma(&out), vec![vec![5, 2], vec![6, 0]]);
+
+        let filters = PreFilters {
+            rotate: Rotation::None,
+            ..filters
+        };
+        assert!(filters.validate(&mono).is_err());
+        assert_eq!(filters.output_size(3, 2), (2, 2));
+    }
+
+    #[test]
+    fn test_ignore_regions() {
+        let filters = PreFilters {
+            ignore: vec!["0:0:1:2".parse().unwrap(), "2:1:1:1".parse().unwrap()],
+            ..Default::default()
+        };
+        let out = filters.apply(test_frame()).unwrap();
+        assert_eq!(luma(&out), vec![vec![0, 2, 3], vec![0, 5, 0]]);
+    }
+
+    #[test]
+    fn test_region_validation() {
+        let yuv420 = details(1920, 1080, ChromaSubsampling::Yuv420);
+        let roi = |s: &str| PreFilters {
+            roi: Some(s.parse().unwrap()),
+            ..Default::default()
+        };
+        assert!(roi("0:0:1920:960").validate(&yuv420).is_ok());
+        // Outside the frame
+        assert!(roi("0:200:1920:960").validate(&yuv420).is_err());
+        // Offsets so large the end overflows
+        assert!(roi("18446744073709551615:0:2:2").validate(&yuv420).is_err());
+        assert!(roi("0:18446744073709551614:2:2").validate(&yuv420).is_err());
+        // Odd offset splits a 4:2:0 chroma sample
+        assert!(roi("1:0:1280:720").validate(&yuv420).is_err());
+
+        assert!("0:0:0:10".parse::<Region>().is_err());
+        assert!("0:0:10".parse::<Region>().is_err());
+    }
+}
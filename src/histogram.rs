@@ -0,0 +1,223 @@
+//! Histogram-difference cut detection, comparable to PySceneDetect's
+//! ContentDetector/AdaptiveDetector for users migrating from it.
+//!
+//! Each frame gets value (luma), hue and saturation histograms, with hue
+//! and saturation taken from the chroma planes. A frame's distance is how
+//! much its histograms differ from the previous frame's; it becomes a cut
+//! when that distance stands out from its neighbours' by an adaptive
+//! ratio, so sustained motion doesn't trigger cuts the way a fixed
+//! threshold would.
+
+use av_scenechange::av_decoders::v_frame::{frame::Frame, pixel::Pixel};
+use std::collections::BTreeMap;
+use std::f64::consts::PI;
+
+/// Default ratio of a frame's distance to its neighbours' average
+pub const DEFAULT_ADAPTIVE_THRESHOLD: f64 = 3.0;
+
+/// Distance a cut must reach regardless of its neighbours, on a 0-1 scale
+const MIN_DISTANCE: f64 = 0.1;
+
+/// Frames on each side averaged for the adaptive threshold
+const WINDOW: usize = 2;
+
+/// Minimum frames between cuts, PySceneDetect's default `min_scene_len`
+const MIN_SCENE_LEN: usize = 15;
+
+/// Bins per histogram
+const BINS: usize = 32;
+
+/// Chroma pixels less saturated than this have no meaningful hue
+const MIN_HUE_SATURATION: f64 = 1.0 / BINS as f64;
+
+/// Value, hue and saturation histograms for one frame, normalized to sum
+/// to 1 (hue sums to less when the frame is partly grey).
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrameHistogram {
+    value: [f64; BINS],
+    hue: [f64; BINS],
+    saturation: [f64; BINS],
+    /// False for monochrome frames
+    has_chroma: bool,
+}
+
+impl FrameHistogram {
+    pub fn new<T: Pixel>(frame: &Frame<T>) -> Self {
+        let bit_depth = frame.bit_depth.get() as u32;
+        let max = ((1u32 << bit_depth) - 1) as f64;
+        let mid = (1u32 << (bit_depth - 1)) as f64;
+        let bin = |v: f64| ((v * BINS as f64) as usize).min(BINS - 1);
+
+        let mut value = [0.0; BINS];
+        let mut count = 0usize;
+        for row in frame.y_plane.rows() {
+            for &px in row {
+                value[bin(px.to_f64().unwrap_or(0.0) / max)] += 1.0;
+            }
+            count += row.len();
+        }
+        normalize(&mut value, count);
+
+        let mut hue = [0.0; BINS];
+        let mut saturation = [0.0; BINS];
+        let has_chroma = match (&frame.u_plane, &frame.v_plane) {
+            (Some(u), Some(v)) => {
+                let mut count = 0usize;
+                for (u_row, v_row) in u.rows().zip(v.rows()) {
+                    for (&cb, &cr) in u_row.iter().zip(v_row) {
+                        let cb = cb.to_f64().unwrap_or(mid) - mid;
+                        let cr = cr.to_f64().unwrap_or(mid) - mid;
+                        let sat = (cb.hypot(cr) / (mid * 2f64.sqrt())).min(1.0);
+                        saturation[bin(sat)] += 1.0;
+                        if sat >= MIN_HUE_SATURATION {
+                            hue[bin((cr.atan2(cb) + PI) / (2.0 * PI))] += 1.0;
+                        }
+                    }
+                    count += u_row.len();
+                }
+                normalize(&mut hue, count);
+                normalize(&mut saturation, count);
+                true
+            }
+            _ => false,
+        };
+
+        FrameHistogram {
+            value,
+            hue,
+            saturation,
+            has_chroma,
+        }
+    }
+
+    /// Mean half-L1 distance over the channels both frames have, from 0
+    /// (identical) to 1 (disjoint).
+    pub fn distance(&self, other: &FrameHistogram) -> f64 {
+        let half_l1 = |a: &[f64; BINS], b: &[f64; BINS]| {
+            a.iter().zip(b).map(|(a, b)| (a - b).abs()).sum::<f64>() / 2.0
+        };
+        let value = half_l1(&self.value, &other.value);
+        if !(self.has_chroma && other.has_chroma) {
+            return value;
+        }
+        (value + half_l1(&self.hue, &other.hue) + half_l1(&self.saturation, &other.saturation))
+            / 3.0
+    }
+}
+
+fn normalize(histogram: &mut [f64; BINS], count: usize) {
+    if count > 0 {
+        histogram.iter_mut().for_each(|v| *v /= count as f64);
+    }
+}
+
+/// Cuts from per-frame distances, where `distances[i]` compares frame `i`
+/// with frame `i - 1` (`distances[0]` is unused).
+///
+/// A frame is a cut when its distance is at least `threshold` times the
+/// average of the `WINDOW` frames on each side, clears `MIN_DISTANCE`, and
+/// is at least `MIN_SCENE_LEN` frames after the previous cut. Frame 0 is
+/// always a cut.
+pub fn adaptive_cuts(distances: &[f64], threshold: f64) -> Vec<usize> {
+    let mut cuts = vec![0];
+    for (frame, &distance) in distances.iter().enumerate().skip(1) {
+        if distance < MIN_DISTANCE || frame - cuts[cuts.len() - 1] < MIN_SCENE_LEN {
+            continue;
+        }
+        if adaptive_ratio(distances, frame) >= threshold {
+            cuts.push(frame);
+        }
+    }
+    cuts
+}
+
+/// How far each frame from 1 on cleared the cut threshold, 1.0 exactly at
+/// it, for fusing with other detectors' strengths. Frames below
+/// `MIN_DISTANCE` score 0.
+pub fn strengths(distances: &[f64], threshold: f64) -> BTreeMap<usize, f64> {
+    (1..distances.len())
+        .map(|frame| {
+            let strength = if distances[frame] < MIN_DISTANCE || threshold <= 0.0 {
+                0.0
+            } else {
+                adaptive_ratio(distances, frame) / threshold
+            };
+            (frame, strength)
+        })
+        .collect()
+}
+
+/// Frame `frame`'s distance over the average of the `WINDOW` frames on
+/// each side.
+fn adaptive_ratio(distances: &[f64], frame: usize) -> f64 {
+    let neighbours: Vec<f64> = (frame.saturating_sub(WINDOW).max(1)..=frame + WINDOW)
+        .filter(|&i| i != frame)
+        .filter_map(|i| distances.get(i).copied())
+        .collect();
+    let average = neighbours.iter().sum::<f64>() / neighbours.len().max(1) as f64;
+    // A lone change in otherwise static content counts as standing out
+    if average <= f64::EPSILON {
+        f64::INFINITY
+    } else {
+        distances[frame] / average
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filters::new_frame;
+    use av_scenechange::av_decoders::v_frame::chroma::ChromaSubsampling;
+    use std::num::NonZeroU8;
+
+    fn flat_frame(y: u8, u: u8, v: u8) -> Frame<u8> {
+        let mut frame =
+            new_frame(4, 4, ChromaSubsampling::Yuv420, NonZeroU8::new(8).unwrap()).unwrap();
+        let planes = [
+            (Some(&mut frame.y_plane), y),
+            (frame.u_plane.as_mut(), u),
+            (frame.v_plane.as_mut(), v),
+        ];
+        for (plane, value) in planes {
+            for row in plane.unwrap().rows_mut() {
+                row.fill(value);
+            }
+        }
+        frame
+    }
+
+    #[test]
+    fn test_distance() {
+        let grey = FrameHistogram::new(&flat_frame(128, 128, 128));
+        let red = FrameHistogram::new(&flat_frame(76, 85, 255));
+        assert_eq!(grey.distance(&grey), 0.0);
+        // Value and saturation move to different bins; grey has no hue, so
+        // hue only counts half
+        assert!((grey.distance(&red) - 2.5 / 3.0).abs() < 1e-9);
+        // Same chroma, different brightness
+        let dark = FrameHistogram::new(&flat_frame(20, 128, 128));
+        assert!((grey.distance(&dark) - 1.0 / 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_adaptive_cuts() {
+        let mut distances = vec![0.05; 60];
+        // A cut, a second one too soon after it, and a high-motion stretch
+        // where no frame stands out from its neighbours
+        distances[20] = 0.6;
+        distances[25] = 0.6;
+        for d in &mut distances[40..50] {
+            *d = 0.5;
+        }
+        distances[45] = 0.7;
+        assert_eq!(
+            adaptive_cuts(&distances, DEFAULT_ADAPTIVE_THRESHOLD),
+            vec![0, 20]
+        );
+
+        let strengths = strengths(&distances, DEFAULT_ADAPTIVE_THRESHOLD);
+        assert!((strengths[&20] - 4.0).abs() < 1e-9);
+        assert!(strengths[&45] < 1.0);
+        assert_eq!(strengths[&10], 0.0);
+    }
+}
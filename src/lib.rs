@@ -0,0 +1,42 @@
+//! Scene detection and chunk boundary processing behind the `shear` CLI.
+//!
+//! Embedders can run the same decode pass with their own cut detection by
+//! implementing [`detect::SceneDetectorBackend`] and calling
+//! [`detect::detect_with`], then reuse [`split`] for length limits and
+//! snapping and [`output`] for the scene file formats.
+//! Applications that decode frames themselves can push them into
+//! [`push::PushDetector`] instead.
+//!
+//! Independent detections share no state, so they can run on separate
+//! threads of one process. A pass reads its source on the calling thread
+//! and analyzes frames on one it spawns, so sources need not be `Send`;
+//! they stay with the thread that opened them. Options, backends, results
+//! and cancellation tokens are `Send + Sync`.
+
+pub mod byte_ranges;
+pub mod chapters;
+pub mod checksum;
+pub mod detect;
+pub mod ecr;
+pub mod edl;
+pub mod exec;
+#[cfg(feature = "ffmpeg")]
+pub mod ffmpeg;
+pub mod filters;
+pub mod histogram;
+pub mod numbering;
+pub mod output;
+pub mod push;
+pub mod report;
+pub mod scene_stats;
+pub mod script;
+pub mod segments;
+pub mod silence;
+pub mod source;
+pub mod split;
+pub mod sweep;
+#[cfg(test)]
+mod test_util;
+pub mod timecode;
+pub mod transition;
+pub mod zones;
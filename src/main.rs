@@ -3,20 +3,75 @@
 //! Uses av-scenechange with FFmpeg backend to detect scene boundaries.
 //! Long scenes are automatically split at regular intervals.
 
-use anyhow::{Context, Result};
-use av_scenechange::{detect_scene_changes, Decoder, DetectionOptions, SceneDetectionSpeed};
-use clap::Parser;
+mod progress;
+#[cfg(unix)]
+mod serve;
+mod state_db;
+mod summary;
+#[cfg(test)]
+mod test_util;
+mod timings;
+#[cfg(feature = "ffmpeg")]
+mod verify;
+mod verify_manifest;
+
+use anyhow::{bail, Context, Result};
+use av_scenechange::av_decoders::v_frame::pixel::Pixel;
+use av_scenechange::{Decoder, DetectionOptions, SceneDetectionSpeed};
+use clap::{Parser, Subcommand, ValueEnum};
+use progress::{Event, ProgressFd};
+use shear::byte_ranges;
+use shear::chapters::{self, ChapterFormat};
+use shear::detect::{self, Backend, CancellationToken, Hysteresis, PassProgress, Rejected};
+use shear::filters::{PreFilters, Region, Rotation};
+use shear::numbering::{self, Numbering};
+use shear::output::{self, OutputFormat, OutputMode, SceneDocument};
+use shear::script::{self, ScriptFormat};
+use shear::segments::{self, FrameRange, RangeSpec};
+use shear::source::{self, FrameSource, RangeSource};
+use shear::split::{
+    avoid_ranges, enforce_min_chunk_len, frame_costs, isolate_ranges, short_chunks,
+    snap_forced_splits, splice_range, split_long_scenes, split_long_scenes_balanced,
+};
+use shear::sweep::{self, SweepRange};
+use shear::zones::{self, ChunkMetrics, Metric, ZoneRule};
+use shear::{checksum, ecr, exec, histogram, report, scene_stats, silence, transition};
 use std::cmp::min;
-use std::fs::File;
-use std::io::{BufWriter, Write};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use summary::Summary;
+use timings::Timings;
 
 #[derive(Parser, Debug)]
 #[command(name = "shear")]
 #[command(about = "Scene change detection for chunked video encoding")]
 #[command(version)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    args: Option<Args>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Check each boundary in a scene file can be seeked to and decoded
+    #[cfg(feature = "ffmpeg")]
+    Verify(verify::VerifyArgs),
+    /// Check a JSON scene file or manifest was made from a source and its
+    /// boundaries haven't been edited since
+    VerifyManifest(verify_manifest::VerifyManifestArgs),
+    /// Run as a daemon, taking detection jobs over a control socket
+    #[cfg(unix)]
+    Serve(serve::ServeArgs),
+}
+
+#[derive(clap::Args, Debug, Clone)]
 struct Args {
-    /// Input video file
+    /// Input video file, or - to read y4m from stdin
     #[arg(short, long)]
     input: PathBuf,
 
@@ -24,6 +79,30 @@ struct Args {
     #[arg(short, long)]
     output: PathBuf,
 
+    /// Output scene file format
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
+
+    /// With --format text, write scene start frames or start/end ranges
+    #[arg(long, value_enum, default_value_t = OutputMode::Starts)]
+    output_mode: OutputMode,
+
+    /// With --output-mode ranges, the text between start and end
+    #[arg(long, default_value = " ")]
+    range_separator: String,
+
+    /// With --output-mode ranges, write each scene's last frame as its end
+    /// instead of the frame after it
+    #[arg(long, default_value_t = false)]
+    inclusive_end: bool,
+
+    /// Count the scene file's frames in presentation (display) order, or
+    /// in decode order, matching tools that count packets. Decode order
+    /// differs on streams with B-frame reordering; other outputs always
+    /// use presentation order.
+    #[arg(long, value_enum, default_value_t = Numbering::Presentation)]
+    numbering: Numbering,
+
     /// FPS numerator
     #[arg(long)]
     fps_num: u32,
@@ -32,73 +111,883 @@ struct Args {
     #[arg(long)]
     fps_den: u32,
 
-    /// Total number of frames in the video
+    /// Total number of frames in the video, or 0 if unknown. A count that
+    /// disagrees with the frames decoded is warned about and replaced.
     #[arg(long)]
     total_frames: usize,
 
-    /// Maximum scene length in seconds (default: 10)
-    #[arg(long, default_value_t = 10)]
-    max_scene_secs: u32,
+    /// Maximum scene length in seconds, fractions allowed (default: 10)
+    #[arg(long, default_value_t = 10.0)]
+    max_scene_secs: f64,
+
+    /// Maximum scene length in frames, on top of --max-scene-secs (default:
+    /// none, so the limit scales with the frame rate)
+    #[arg(long)]
+    max_scene_frames: Option<usize>,
+
+    /// Minimum chunk length in seconds, enforced after all splitting
+    /// (default: 0, disabled)
+    #[arg(long, default_value_t = 0.0)]
+    min_chunk_secs: f64,
+
+    /// How forced splits divide long scenes
+    #[arg(long, value_enum, default_value_t = Balance::Frames)]
+    balance: Balance,
+
+    /// Write a JSON report estimating relative encode cost and bitrate share per chunk
+    #[arg(long)]
+    report: Option<PathBuf>,
+
+    /// Detect runs of identical frames lasting at least this many seconds
+    #[arg(long)]
+    freeze_min_secs: Option<f64>,
+
+    /// How forced splits treat detected freeze runs
+    #[arg(long, value_enum, default_value_t = FreezeSplits::Allow, requires = "freeze_min_secs")]
+    freeze_splits: FreezeSplits,
+
+    /// Detect black segments lasting at least this many seconds
+    #[arg(long)]
+    black_min_secs: Option<f64>,
+
+    /// Add ranges where the main audio track stays silent for at least
+    /// this many seconds to the JSON output. Decodes the audio in a second
+    /// pass; needs the ffmpeg feature.
+    #[arg(long)]
+    silence_min_secs: Option<f64>,
+
+    /// Audio level in dBFS at or below which audio counts as silent
+    #[arg(long, default_value_t = -50.0, requires = "silence_min_secs", allow_negative_numbers = true)]
+    silence_threshold_db: f64,
+
+    /// Force a chunk boundary at the midpoint of each black segment
+    #[arg(long, default_value_t = false, requires = "black_min_secs")]
+    black_splits: bool,
+
+    /// Label each boundary in the JSON or manifest output as a hard cut,
+    /// fade-in, fade-out, dissolve or forced split
+    #[arg(long, default_value_t = false)]
+    transitions: bool,
+
+    /// Add encoder setting overrides to chunks of the manifest whose
+    /// statistics match a rule, as METRIC OP VALUE => SETTING ADJUSTMENT,
+    /// e.g. 'complexity>0.8 => crf-4' or 'luma<16 => crf+2'. Metrics are
+    /// complexity, luma, motion, duration and frames; adjustments are +N,
+    /// -N or =N. Repeatable; rules apply in order.
+    #[arg(long, value_name = "RULE")]
+    zone: Vec<ZoneRule>,
+
+    /// Embed checksums of the input (sampled bytes) and of the boundary
+    /// list in the JSON or manifest output, for `shear verify-manifest`
+    #[arg(long, default_value_t = false)]
+    checksum: bool,
+
+    /// Add each scene's average, darkest-frame and brightest-frame luma to
+    /// the JSON output
+    #[arg(long, default_value_t = false)]
+    luma_stats: bool,
+
+    /// Add each scene's average and peak motion, from the cost detector's
+    /// inter-frame costs, to the JSON output
+    #[arg(long, default_value_t = false)]
+    motion_stats: bool,
+
+    /// Add each scene's peak and average light level, reading luma as PQ
+    /// (HDR10), to the JSON output
+    #[arg(long, default_value_t = false)]
+    hdr_stats: bool,
+
+    /// Write chapters grouping consecutive scenes to this file
+    #[arg(long)]
+    chapters_out: Option<PathBuf>,
+
+    /// Minimum chapter length in seconds (default: 300)
+    #[arg(long, default_value_t = 300.0)]
+    chapter_min_secs: f64,
+
+    /// Chapter file format
+    #[arg(long, value_enum, default_value_t = ChapterFormat::Ogm)]
+    chapter_format: ChapterFormat,
+
+    /// Write per-chunk ffmpeg extraction commands to this file
+    #[arg(long)]
+    emit_script: Option<PathBuf>,
+
+    /// Extraction script format
+    #[arg(long, value_enum, default_value_t = ScriptFormat::Shell, requires = "emit_script")]
+    script_format: ScriptFormat,
+
+    /// Write each chunk's byte range in the input, starting at the nearest
+    /// keyframe at or before it, to this JSON file for byte-range HLS
+    /// packaging
+    #[arg(long)]
+    byte_ranges: Option<PathBuf>,
+
+    /// Shell command to expand once per chunk, printed to stdout unless
+    /// --exec-jobs is given. Placeholders: {input}, {stream}, {index},
+    /// {start}, {end}, {frames} (frame numbers, end exclusive) and
+    /// {start_ts}, {end_ts}, {duration} (seconds)
+    #[arg(long, value_name = "TEMPLATE")]
+    exec_template: Option<String>,
+
+    /// Run the expanded commands, at most this many at once
+    #[arg(long, value_name = "N", requires = "exec_template")]
+    exec_jobs: Option<usize>,
+
+    /// Only analyze this rectangle of the frame, as X:Y:W:H in displayed
+    /// pixels (after --rotate), e.g. to leave out a news ticker or timestamp overlay.
+    /// Offsets and sizes must be multiples of the chroma subsampling
+    /// (even numbers for 4:2:0).
+    #[arg(long, value_name = "X:Y:W:H")]
+    roi: Option<Region>,
+
+    /// Zero out the rectangle X:Y:W:H (displayed pixels, after --rotate)
+    /// before analysis, so
+    /// burned-in overlays such as scoreboards or channel bugs can't trigger
+    /// or suppress cuts. Repeatable; same alignment rules as --roi.
+    #[arg(long, value_name = "X:Y:W:H")]
+    ignore_region: Vec<Region>,
+
+    /// Rotate frames clockwise before analysis, e.g. for phone footage
+    /// stored sideways. Boundaries are unaffected; --roi and
+    /// --ignore-region are given in the rotated frame's coordinates.
+    #[arg(long, value_enum, default_value_t = Rotation::None)]
+    rotate: Rotation,
+
+    /// Skip frames START to END (end exclusive) during analysis and keep
+    /// them as one chunk, split only by the maximum scene length, e.g. for
+    /// color bars, slates or known-bad regions. Give frames as 0:240 or
+    /// timestamps as 00:00:00-00:00:10.5. Repeatable.
+    #[arg(long, value_name = "START:END")]
+    exclude: Vec<RangeSpec>,
+
+    /// Analyze every video stream, not just the main one. Each stream gets
+    /// its own output files with the stream index inserted before the
+    /// extension (scenes.txt becomes scenes.1.txt). Frame rate and frame
+    /// count options apply to all streams.
+    #[arg(long, default_value_t = false)]
+    all_streams: bool,
+
+    /// Analyze a file that is still being written, such as an ongoing
+    /// capture: wait for new data at the end of the file, print each cut
+    /// to stdout as it is found, and finish once the file stops growing
+    #[arg(long, default_value_t = false)]
+    follow: bool,
+
+    /// With --follow, how long the file must stop growing before it counts
+    /// as finished
+    #[arg(long, default_value_t = 10.0, requires = "follow")]
+    follow_idle_secs: f64,
+
+    /// Re-detect only frames START to END (end exclusive) and splice the
+    /// cuts into the scene file given with --from-scenes, replacing its
+    /// boundaries inside the range. Splitting and minimum chunk length are
+    /// then re-applied to the whole file.
+    #[arg(long, value_name = "START:END", requires = "from_scenes")]
+    patch_range: Option<FrameRange>,
+
+    /// Existing scene file (text or JSON) to patch with --patch-range
+    #[arg(long, requires = "patch_range")]
+    from_scenes: Option<PathBuf>,
+
+    /// Decide cuts with two thresholds instead of the detector's one, as
+    /// CONFIRM:EXTEND, e.g. 40:20. A transition needs a frame above CONFIRM
+    /// and lasts while frames stay above EXTEND, producing a single cut.
+    /// Uses the same units as --sweep-threshold.
+    #[arg(long, value_name = "CONFIRM:EXTEND")]
+    hysteresis: Option<Hysteresis>,
+
+    /// Drop detected cuts whose confidence is below this (0-1), without
+    /// re-tuning the detector threshold. Confidence is 0 for a cut exactly
+    /// at the threshold and 0.5 for one scoring twice it. Dropped cuts are
+    /// listed under `rejected` in JSON output.
+    #[arg(long, value_name = "CONFIDENCE")]
+    min_confidence: Option<f64>,
+
+    /// File of externally known cut points: a CMX3600 EDL, a chapter file
+    /// in any --chapter-format, or a scene file (one frame number per line
+    /// or JSON), e.g. a previous encode's keyframes. Each is confirmed by,
+    /// and aligned to, a detected cut nearby, or becomes one if the content
+    /// there comes close
+    #[arg(long)]
+    hints: Option<PathBuf>,
+
+    /// How many frames from a hint a detected cut may be and still confirm
+    /// it
+    #[arg(long, default_value_t = 5, requires = "hints")]
+    hint_window: usize,
 
-    /// Maximum scene length in frames (default: 300)
-    #[arg(long, default_value_t = 300)]
-    max_scene_frames: usize,
+    /// Cut detection algorithm. Several, comma-separated, run in the same
+    /// pass and vote on each cut
+    #[arg(long, value_enum, value_delimiter = ',', default_value = "cost")]
+    detector: Vec<Detector>,
+
+    /// With several detectors, how many must agree on a cut (default: a
+    /// majority)
+    #[arg(long, value_name = "N")]
+    vote: Option<usize>,
+
+    /// With --detector histogram, how many times its neighbours' average
+    /// a frame's histogram distance must be to count as a cut
+    #[arg(long, default_value_t = histogram::DEFAULT_ADAPTIVE_THRESHOLD)]
+    histogram_threshold: f64,
+
+    /// With --detector ecr, the fraction of edges that must change (0-1)
+    /// for a frame to count as a cut
+    #[arg(long, default_value_t = ecr::DEFAULT_ECR_THRESHOLD)]
+    ecr_threshold: f64,
+
+    /// Also analyze every frame at half width and height in the same pass,
+    /// and merge both scales' cuts by fusing their scores, for each
+    /// detector. Recovers cuts a single scale misses on very noisy or very
+    /// soft content.
+    #[arg(long, default_value_t = false, conflicts_with = "hysteresis")]
+    ensemble: bool,
+
+    /// av-scenechange analysis speed for the cost detector. Fast compares
+    /// downscaled pixels instead of estimating encoder costs; pair it with
+    /// --refine to re-check its borderline cuts.
+    #[arg(long, value_enum, default_value_t = Speed::Standard)]
+    speed: Speed,
+
+    /// After the main pass, re-decode a short window around each cut whose
+    /// confidence is below 0.5 and re-analyze it at full resolution and
+    /// Standard speed, moving the cut to the refined one or dropping it.
+    /// Useful with --speed fast or --ensemble.
+    #[arg(long, default_value_t = false)]
+    refine: bool,
+
+    /// Print how many scenes each threshold from START to END (step STEP)
+    /// would produce, e.g. 20:40:5. Thresholds are the percentage of a
+    /// frame's intra cost its inter cost must reach; the detector uses 30.
+    /// All thresholds are evaluated from the same detection pass.
+    #[arg(long, value_name = "START:END:STEP")]
+    sweep_threshold: Option<SweepRange>,
 
     /// Show progress output
     #[arg(long, default_value_t = false)]
     progress: bool,
+
+    /// Write progress and the final summary as JSON lines to this inherited
+    /// file descriptor, for frontends
+    #[arg(long, value_name = "FD")]
+    progress_fd: Option<u32>,
+
+    /// Print how long probing, decoding, detection, post-processing and
+    /// writing outputs took, and the decode speed
+    #[arg(long, default_value_t = false)]
+    timings: bool,
+
+    /// Print a summary table to stderr at the end of the run: scenes
+    /// found, forced splits, chunk lengths, duration and output path.
+    /// Colored on a terminal unless NO_COLOR is set.
+    #[arg(long, default_value_t = false)]
+    summary: bool,
+
+    /// Guarantee identical boundaries across runs and machines for the same
+    /// input and options, e.g. for content-addressed encode caches: decode
+    /// and analyze on one thread, and refuse options whose results depend
+    /// on timing (--follow) or on the platform's math library (--detector
+    /// histogram). Slower. Refused in `shear serve` jobs, whose analysis
+    /// pool is shared.
+    #[arg(long, default_value_t = false)]
+    deterministic: bool,
+
+    /// Record each completed run in this JSON lines file: the input's
+    /// fingerprint, a checksum of the options and the output path
+    #[arg(long)]
+    state_db: Option<PathBuf>,
+
+    /// Exit without doing anything if --state-db records this input as
+    /// completed with identical settings and the output still exists
+    #[arg(long, default_value_t = false, requires = "state_db")]
+    skip_done: bool,
+}
+
+/// Cut detection algorithm
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum Detector {
+    /// av-scenechange's encoder cost comparison
+    Cost,
+    /// Luma, hue and saturation histogram differences with an adaptive
+    /// threshold, comparable to PySceneDetect's content detection
+    Histogram,
+    /// Edge change ratio, robust to brightness changes and flashes
+    Ecr,
+}
+
+/// Cost detector analysis speed
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum Speed {
+    /// Inter and intra cost estimates, as an encoder would make
+    Standard,
+    /// Sum of absolute differences between downscaled frames
+    Fast,
+}
+
+/// Strategy for placing forced splits inside long scenes
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum Balance {
+    /// Equal frame counts per chunk
+    Frames,
+    /// Roughly equal estimated encoding work per chunk
+    Complexity,
+}
+
+/// Placement of forced splits relative to freeze runs
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum FreezeSplits {
+    /// Ignore freeze runs when placing forced splits
+    Allow,
+    /// Move forced splits out of freeze runs to the nearest run edge
+    Avoid,
+    /// Move forced splits onto nearby freeze run edges
+    Prefer,
 }
 
 fn main() -> Result<()> {
-    let args = Args::parse();
+    let cli = Cli::parse();
+    match cli.command {
+        #[cfg(feature = "ffmpeg")]
+        Some(Command::Verify(verify_args)) => verify::run(&verify_args),
+        Some(Command::VerifyManifest(verify_args)) => verify_manifest::run(&verify_args),
+        #[cfg(unix)]
+        Some(Command::Serve(serve_args)) => serve::run(&serve_args),
+        None => {
+            let args = cli
+                .args
+                .expect("clap requires detection arguments without a subcommand");
+            if args.deterministic {
+                // Set before av-scenechange first uses rayon, which sizes its
+                // global pool once. Boundaries don't depend on the pool size;
+                // this pins it so runs are also alike in every other respect.
+                std::env::set_var("RAYON_NUM_THREADS", "1");
+            }
+            detect_file(&args, None)
+        }
+    }
+}
+
+/// Frames analyzed by every detection pass in this process, for `shear
+/// serve` metrics
+static FRAMES_ANALYZED: AtomicU64 = AtomicU64::new(0);
+
+/// Run detection for the input in `args`, writing all requested outputs,
+/// unless `--skip-done` finds it already done.
+///
+/// Cancelling `cancel` aborts detection at the next frame.
+fn detect_file(args: &Args, cancel: Option<&CancellationToken>) -> Result<()> {
+    let Some(db) = &args.state_db else {
+        return detect_input(args, cancel);
+    };
+    if args.input == Path::new("-") {
+        bail!("--state-db can't be used with stdin input");
+    }
+
+    let record = state_db::Record::new(args)?;
+    // --all-streams writes one output per stream instead of the named one
+    if args.skip_done && state_db::is_done(db, &record, !args.all_streams)? {
+        eprintln!(
+            "Skipping {:?}: already done with the same settings",
+            args.input
+        );
+        return Ok(());
+    }
+    detect_input(args, cancel)?;
+    state_db::mark_done(db, &record)
+}
+
+/// Run detection for the input in `args`, writing all requested outputs.
+fn detect_input(args: &Args, cancel: Option<&CancellationToken>) -> Result<()> {
+    // Threshold re-decisions only see the cost detector's scores
+    if args.detector != [Detector::Cost]
+        && (args.hysteresis.is_some() || args.sweep_threshold.is_some())
+    {
+        bail!("--hysteresis and --sweep-threshold need --detector cost alone");
+    }
+    // Their thresholds are in Standard speed's cost units
+    if args.speed == Speed::Fast && (args.hysteresis.is_some() || args.sweep_threshold.is_some()) {
+        bail!("--hysteresis and --sweep-threshold need --speed standard");
+    }
+    if (args.luma_stats || args.motion_stats || args.hdr_stats) && args.format != OutputFormat::Json
+    {
+        bail!("--luma-stats, --motion-stats and --hdr-stats need --format json");
+    }
+    if args.silence_min_secs.is_some() {
+        if args.format != OutputFormat::Json {
+            bail!("--silence-min-secs needs --format json");
+        }
+        if args.input == Path::new("-") {
+            bail!("--silence-min-secs can't be used with stdin input");
+        }
+    }
+    if args.transitions && args.format == OutputFormat::Text {
+        bail!("--transitions needs --format json or manifest");
+    }
+    if args.checksum {
+        if args.format == OutputFormat::Text {
+            bail!("--checksum needs --format json or manifest");
+        }
+        if args.input == Path::new("-") {
+            bail!("--checksum can't be used with stdin input");
+        }
+    }
+    if args.byte_ranges.is_some() && args.input == Path::new("-") {
+        bail!("--byte-ranges can't be used with stdin input");
+    }
+    if !args.zone.is_empty() {
+        if args.format != OutputFormat::Manifest {
+            bail!("--zone needs --format manifest");
+        }
+        if zones::uses(&args.zone, Metric::Motion) && !args.detector.contains(&Detector::Cost) {
+            bail!("--zone rules on motion need the cost detector");
+        }
+    }
+    if args.motion_stats && !args.detector.contains(&Detector::Cost) {
+        bail!("--motion-stats needs the cost detector");
+    }
+    // Without cost scores every frame would weigh the same
+    if args.balance == Balance::Complexity && !args.detector.contains(&Detector::Cost) {
+        bail!("--balance complexity needs the cost detector");
+    }
+    if args.report.is_some() && !args.detector.contains(&Detector::Cost) {
+        bail!("--report needs the cost detector");
+    }
+    // Motion is inter cost over intra cost, which only Standard speed
+    // estimates; Fast scores pixel differences against a fixed threshold
+    if args.speed == Speed::Fast && (args.motion_stats || zones::uses(&args.zone, Metric::Motion)) {
+        bail!("--motion-stats and --zone rules on motion need --speed standard");
+    }
+    if args.fps_num == 0 || args.fps_den == 0 {
+        bail!("--fps-num and --fps-den must be positive");
+    }
+    if !args.max_scene_secs.is_finite() || args.max_scene_secs <= 0.0 {
+        bail!("--max-scene-secs must be positive");
+    }
+    if args.max_scene_frames == Some(0) {
+        bail!("--max-scene-frames must be positive");
+    }
+    if !args.min_chunk_secs.is_finite() || args.min_chunk_secs < 0.0 {
+        bail!("--min-chunk-secs must be zero or positive");
+    }
+    if args.min_chunk_secs > 0.0 && args.min_chunk_secs >= args.max_scene_secs {
+        bail!("--min-chunk-secs must be less than --max-scene-secs");
+    }
+    if args.output_mode == OutputMode::Ranges && args.format != OutputFormat::Text {
+        bail!("--output-mode ranges needs --format text");
+    }
+    if args.numbering == Numbering::Decode {
+        if args.format == OutputFormat::Manifest
+            || args.luma_stats
+            || args.hdr_stats
+            || args.motion_stats
+        {
+            bail!(
+                "--numbering decode can't be used with --format manifest or per-scene statistics"
+            );
+        }
+        if args.input == Path::new("-") || args.patch_range.is_some() {
+            bail!("--numbering decode can't be used with stdin input or --patch-range");
+        }
+    }
+    if let Some(min) = args.min_confidence {
+        if !(0.0..=1.0).contains(&min) {
+            bail!("--min-confidence must be between 0 and 1");
+        }
+        if !args.detector.contains(&Detector::Cost) {
+            bail!("--min-confidence needs the cost detector");
+        }
+    }
+    if args.refine {
+        if args.detector != [Detector::Cost] {
+            bail!("--refine needs --detector cost alone");
+        }
+        if args.input == Path::new("-") || args.follow || args.patch_range.is_some() {
+            bail!("--refine can't be used with stdin input, --follow or --patch-range");
+        }
+    }
+    let detectors = args.detector.len();
+    if args
+        .vote
+        .is_some_and(|votes| votes == 0 || votes > detectors)
+    {
+        bail!(
+            "--vote must be between 1 and the number of detectors ({})",
+            detectors
+        );
+    }
+
+    if let Some(range) = args.patch_range {
+        // Everything else is only known for the re-detected frames
+        let whole_source = [
+            ("--all-streams", args.all_streams),
+            ("--sweep-threshold", args.sweep_threshold.is_some()),
+            ("--freeze-min-secs", args.freeze_min_secs.is_some()),
+            ("--black-min-secs", args.black_min_secs.is_some()),
+            ("--luma-stats", args.luma_stats),
+            ("--hdr-stats", args.hdr_stats),
+            ("--motion-stats", args.motion_stats),
+            ("--transitions", args.transitions),
+            ("--silence-min-secs", args.silence_min_secs.is_some()),
+            ("--balance complexity", args.balance == Balance::Complexity),
+            ("--report", args.report.is_some()),
+            ("--chapters-out", args.chapters_out.is_some()),
+            ("--byte-ranges", args.byte_ranges.is_some()),
+            ("--format manifest", args.format == OutputFormat::Manifest),
+            ("--exclude", !args.exclude.is_empty()),
+        ];
+        if let Some((option, _)) = whole_source.iter().find(|(_, set)| *set) {
+            bail!("{} can't be used with --patch-range", option);
+        }
+        if args.total_frames == 0 {
+            bail!("--patch-range needs --total-frames");
+        }
+        if range.end > args.total_frames {
+            bail!(
+                "--patch-range ends after the last frame ({})",
+                args.total_frames
+            );
+        }
+    }
+
+    if args.deterministic {
+        if args.follow {
+            bail!("--deterministic can't be used with --follow, whose end depends on timing");
+        }
+        if args.detector.contains(&Detector::Histogram) {
+            // Its hue bins use atan2 and hypot, which can round differently
+            // between math libraries
+            bail!("--deterministic can't be used with --detector histogram");
+        }
+    }
+
+    if args.follow {
+        if args.all_streams || args.patch_range.is_some() || args.input == Path::new("-") {
+            bail!("--follow can't be used with --all-streams, --patch-range or stdin input");
+        }
+        if !args.follow_idle_secs.is_finite() || args.follow_idle_secs <= 0.0 {
+            bail!("--follow-idle-secs must be positive");
+        }
+        return follow_file(args, cancel);
+    }
+
+    if args.all_streams {
+        return detect_all_streams(args, cancel);
+    }
+
+    if args.deterministic && args.input != Path::new("-") && !is_y4m(&args.input) {
+        return detect_single_threaded(args, cancel);
+    }
+
+    // Create decoder for scene detection
+    let started = Instant::now();
+    let decoder = if args.input == Path::new("-") {
+        Decoder::from_stdin()
+    } else {
+        Decoder::from_file(&args.input)
+    };
+    let mut decoder = decoder.context("Failed to create decoder")?;
+    run_source(args, &mut decoder, None, started.elapsed(), cancel)
+}
+
+/// Run detection on a file that is still being written.
+fn follow_file(args: &Args, cancel: Option<&CancellationToken>) -> Result<()> {
+    let idle = Duration::from_secs_f64(args.follow_idle_secs);
+    let started = Instant::now();
+    if is_y4m(&args.input) {
+        let mut decoder = source::follow_y4m(&args.input, idle)?;
+        return run_source(args, &mut decoder, None, started.elapsed(), cancel);
+    }
+    follow_container(args, idle, started, cancel)
+}
+
+#[cfg(feature = "ffmpeg")]
+fn follow_container(
+    args: &Args,
+    idle: Duration,
+    started: Instant,
+    cancel: Option<&CancellationToken>,
+) -> Result<()> {
+    use shear::ffmpeg;
+
+    let Some(&index) = ffmpeg::video_streams(&args.input)?.first() else {
+        bail!("No video streams found in {:?}", args.input);
+    };
+    let mut decoder = ffmpeg::StreamDecoder::open_following(&args.input, index, idle)?;
+    run_source(args, &mut decoder, None, started.elapsed(), cancel)
+}
+
+#[cfg(not(feature = "ffmpeg"))]
+fn follow_container(
+    _args: &Args,
+    _idle: Duration,
+    _started: Instant,
+    _cancel: Option<&CancellationToken>,
+) -> Result<()> {
+    bail!("--follow needs shear built with the ffmpeg feature for inputs other than y4m");
+}
+
+/// With --deterministic, decode the main stream of a container on one
+/// thread. y4m and stdin input are always decoded on one thread.
+#[cfg(feature = "ffmpeg")]
+fn detect_single_threaded(args: &Args, cancel: Option<&CancellationToken>) -> Result<()> {
+    use shear::ffmpeg;
+
+    let started = Instant::now();
+    let index = ffmpeg::best_video_stream(&args.input)?;
+    let mut decoder = ffmpeg::StreamDecoder::open_single_threaded(&args.input, index)?;
+    run_source(args, &mut decoder, None, started.elapsed(), cancel)
+}
+
+#[cfg(not(feature = "ffmpeg"))]
+fn detect_single_threaded(_args: &Args, _cancel: Option<&CancellationToken>) -> Result<()> {
+    bail!("Inputs other than y4m need shear built with the ffmpeg feature");
+}
+
+/// Open the input again as `run` got it, for a second pass over it.
+#[cfg(feature = "ffmpeg")]
+fn reopen_source<T: Pixel>(args: &Args, stream: Option<usize>) -> Result<Box<dyn FrameSource<T>>> {
+    use shear::ffmpeg::{self, StreamDecoder};
+
+    let index = match stream {
+        Some(index) => Some(index),
+        None if args.deterministic && !is_y4m(&args.input) => {
+            Some(ffmpeg::best_video_stream(&args.input)?)
+        }
+        None => None,
+    };
+    Ok(match index {
+        Some(index) if args.deterministic => {
+            Box::new(StreamDecoder::open_single_threaded(&args.input, index)?)
+        }
+        Some(index) => Box::new(StreamDecoder::open(&args.input, index)?),
+        None => Box::new(Decoder::from_file(&args.input).context("Failed to create decoder")?),
+    })
+}
+
+#[cfg(not(feature = "ffmpeg"))]
+fn reopen_source<T: Pixel>(args: &Args, _stream: Option<usize>) -> Result<Box<dyn FrameSource<T>>> {
+    Ok(Box::new(
+        Decoder::from_file(&args.input).context("Failed to create decoder")?,
+    ))
+}
+
+/// Run detection once per video stream of the input.
+#[cfg(feature = "ffmpeg")]
+fn detect_all_streams(args: &Args, cancel: Option<&CancellationToken>) -> Result<()> {
+    use shear::ffmpeg;
+
+    let streams = ffmpeg::video_streams(&args.input)?;
+    if streams.is_empty() {
+        bail!("No video streams found in {:?}", args.input);
+    }
+    for index in streams {
+        let started = Instant::now();
+        let mut decoder = if args.deterministic {
+            ffmpeg::StreamDecoder::open_single_threaded(&args.input, index)?
+        } else {
+            ffmpeg::StreamDecoder::open(&args.input, index)?
+        };
+        run_source(args, &mut decoder, Some(index), started.elapsed(), cancel)
+            .with_context(|| format!("Failed to analyze stream {}", index))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(not(feature = "ffmpeg"))]
+fn detect_all_streams(_args: &Args, _cancel: Option<&CancellationToken>) -> Result<()> {
+    bail!("--all-streams needs shear built with the ffmpeg feature");
+}
+
+/// True for the extensions av-decoders reads as y4m.
+fn is_y4m(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("y4m") || ext.eq_ignore_ascii_case("yuv"))
+}
+
+/// Run on `source` with 8-bit pixels, or 16-bit ones when the stream has
+/// more than 8 bits per sample.
+fn run_source<S: FrameSource<u8> + FrameSource<u16>>(
+    args: &Args,
+    source: &mut S,
+    stream: Option<usize>,
+    probe_time: Duration,
+    cancel: Option<&CancellationToken>,
+) -> Result<()> {
+    if FrameSource::<u8>::video_details(source).bit_depth > 8 {
+        run::<u16>(args, source, stream, probe_time, cancel)
+    } else {
+        run::<u8>(args, source, stream, probe_time, cancel)
+    }
+}
+
+/// Detect, split and write outputs for one video stream.
+///
+/// `stream` is the stream index when analyzing several streams, and is
+/// added to every output path. `probe_time` is how long opening the source
+/// took, for `--timings`.
+fn run<T: Pixel>(
+    args: &Args,
+    source: &mut dyn FrameSource<T>,
+    stream: Option<usize>,
+    probe_time: Duration,
+    cancel: Option<&CancellationToken>,
+) -> Result<()> {
+    let output_path = stream_path(&args.output, stream);
+    let probed_total = source.video_details().total_frames;
+    let progress_fd = args.progress_fd.map(ProgressFd::open).transpose()?;
 
     // Calculate effective FPS for max scene length calculation
     let fps = args.fps_num as f64 / args.fps_den as f64;
 
     // Max scene length: max_scene_secs or max_scene_frames, whichever is smaller
     let max_scene_frames = min(
-        (fps * args.max_scene_secs as f64).ceil() as usize,
-        args.max_scene_frames,
+        (fps * args.max_scene_secs).ceil() as usize,
+        args.max_scene_frames.unwrap_or(usize::MAX),
     );
 
+    // Hard minimum chunk length, independent of detection
+    let min_chunk_frames = (fps * args.min_chunk_secs).ceil() as usize;
+
     if args.progress {
-        eprintln!(
-            "Detecting scene changes in {:?} (max {} frames/scene)",
-            args.input, max_scene_frames
-        );
+        match stream {
+            Some(index) => eprintln!(
+                "Detecting scene changes in {:?} stream {} (max {} frames/scene)",
+                args.input, index, max_scene_frames
+            ),
+            None => eprintln!(
+                "Detecting scene changes in {:?} (max {} frames/scene)",
+                args.input, max_scene_frames
+            ),
+        }
     }
 
-    // Create decoder for scene detection
-    let mut decoder = Decoder::from_file(&args.input).context("Failed to create decoder")?;
+    // Frame count for percentages, when the caller knows it
+    let known_total = args
+        .patch_range
+        .map_or(args.total_frames, |range| range.len());
+
+    if let Some(fd) = &progress_fd {
+        fd.send(&Event::Start {
+            input: &args.input,
+            stream,
+            total_frames: (known_total > 0).then_some(known_total),
+        });
+    }
 
     // Configure scene detection
     let opts = DetectionOptions {
-        analysis_speed: SceneDetectionSpeed::Standard,
+        analysis_speed: match args.speed {
+            Speed::Standard => SceneDetectionSpeed::Standard,
+            Speed::Fast => SceneDetectionSpeed::Fast,
+        },
         detect_flashes: true,
         lookahead_distance: 5,
         ..Default::default()
     };
 
-    // Progress callback - use args.total_frames since callback's total is unreliable
-    let known_total = args.total_frames;
-    let progress_fn = |current: usize, _total: usize| {
-        if known_total > 0 && current.is_multiple_of(100) {
-            let pct = (current as f64 / known_total as f64) * 100.0;
-            // Clamp to 100% in case of frame count mismatch
-            let pct = if pct > 100.0 { 100.0 } else { pct };
+    // Progress callback - use args.total_frames since the pass can't know it
+    let progress_fn = |pass: &PassProgress| {
+        FRAMES_ANALYZED.fetch_add(1, Ordering::Relaxed);
+        if let (true, Some(frame)) = (args.follow, pass.new_cut) {
+            // Provisional: splitting and minimum chunk length apply at the end
+            println!("{}", frame);
+            if let Some(fd) = &progress_fd {
+                fd.send(&Event::Cut { frame });
+            }
+        }
+        let current = pass.frames;
+        if !current.is_multiple_of(100) {
+            return;
+        }
+        let pct = progress::percent(current, known_total);
+        if let (true, Some(pct)) = (args.progress, pct) {
             eprint!("\rAnalyzing: {:.1}%", pct);
         }
+        if let Some(fd) = &progress_fd {
+            fd.send(&Event::Progress {
+                frames: current,
+                percent: pct,
+            });
+        }
     };
 
-    let progress_callback: Option<&dyn Fn(usize, usize)> = if args.progress {
-        Some(&progress_fn)
-    } else {
-        None
+    // Frame statistics are only needed for freeze and black detection,
+    // per-scene brightness and transition types
+    let collect_stats = args.freeze_min_secs.is_some()
+        || args.black_min_secs.is_some()
+        || args.luma_stats
+        || args.hdr_stats
+        || args.transitions
+        || zones::uses(&args.zone, Metric::Luma);
+
+    let mut exclude = Vec::new();
+    for spec in &args.exclude {
+        let range = spec.resolve(args.fps_num, args.fps_den);
+        if range.is_empty() {
+            bail!("--exclude ranges must cover at least one frame");
+        }
+        exclude.push(range);
+    }
+
+    let filters = PreFilters {
+        ignore: args.ignore_region.clone(),
+        roi: args.roi,
+        rotate: args.rotate,
+        exclude,
+    };
+
+    let backends: Vec<Backend> = args
+        .detector
+        .iter()
+        .map(|detector| match detector {
+            Detector::Cost => Backend::Cost {
+                ensemble: args.ensemble,
+            },
+            Detector::Histogram => Backend::Histogram {
+                threshold: args.histogram_threshold,
+                ensemble: args.ensemble,
+            },
+            Detector::Ecr => Backend::Ecr {
+                threshold: args.ecr_threshold,
+                ensemble: args.ensemble,
+            },
+        })
+        .collect();
+    let min_votes = args.vote.unwrap_or(backends.len() / 2 + 1);
+
+    let mut ranged;
+    let source: &mut dyn FrameSource<T> = match args.patch_range {
+        Some(range) => {
+            ranged = RangeSource::new(source, range);
+            &mut ranged
+        }
+        None => source,
     };
 
     // Run scene detection
-    let results = detect_scene_changes::<u8>(&mut decoder, opts, None, progress_callback)
-        .context("Scene detection failed")?;
+    let pass_started = Instant::now();
+    let mut results = detect::detect::<T>(
+        source,
+        opts,
+        &filters,
+        collect_stats,
+        &backends,
+        min_votes,
+        cancel,
+        Some(&progress_fn),
+    )
+    .context("Scene detection failed")?;
+    let mut timings = Timings {
+        probe: probe_time,
+        decode: results.decode_time,
+        detection: results.analysis_time,
+        pass: pass_started.elapsed(),
+        frames: results.frame_count,
+        ..Default::default()
+    };
+    let post_started = Instant::now();
 
     if args.progress {
         eprintln!(
@@ -106,122 +995,575 @@ fn main() -> Result<()> {
             results.scene_changes.len()
         );
     }
+    let detected_scenes = results.scene_changes.len();
+
+    // Patched detection numbers frames from the range start
+    if let Some(range) = args.patch_range {
+        if results.frame_count < range.len() {
+            bail!(
+                "Input ended at frame {}, before the end of --patch-range",
+                range.start + results.frame_count
+            );
+        }
+        for frame in &mut results.scene_changes {
+            *frame += range.start;
+        }
+        results.scores = std::mem::take(&mut results.scores)
+            .into_iter()
+            .map(|(frame, score)| (frame + range.start, score))
+            .collect();
+    }
 
     // Extract scene boundaries
-    let mut scene_starts: Vec<usize> = results.scene_changes;
+    let mut scene_starts: Vec<usize> = match &args.hysteresis {
+        Some(hysteresis) => hysteresis.cuts(&results.scores),
+        None => results.scene_changes,
+    };
+
+    // Drop low-confidence cuts, keeping them for the JSON output
+    let mut rejected = args.min_confidence.map(|min| {
+        let (kept, rejected) = detect::filter_by_confidence(&scene_starts, &results.scores, min);
+        scene_starts = kept;
+        rejected
+    });
+
+    // Re-check borderline cuts at full resolution and Standard speed
+    if args.refine {
+        let mut source = reopen_source::<T>(args, stream)?;
+        let (kept, dropped) = detect::refine_cuts(
+            source.as_mut(),
+            &scene_starts,
+            &results.scores,
+            opts,
+            &filters,
+            cancel,
+        )
+        .context("Refinement failed")?;
+        scene_starts = kept;
+        if !dropped.is_empty() {
+            let rejected = rejected.get_or_insert_with(Vec::new);
+            rejected.extend(dropped.into_iter().map(|frame| Rejected {
+                frame,
+                confidence: results.scores.get(&frame).map_or(0.0, detect::confidence),
+            }));
+            rejected.sort_by_key(|cut| cut.frame);
+        }
+    }
+
+    // Confirm, align or add externally known cuts
+    if let Some(path) = &args.hints {
+        let mut hints = output::read_hints(path, args.fps_num, args.fps_den)?;
+        if let Some(range) = args.patch_range {
+            hints.retain(|&hint| range.splits_at(hint));
+        }
+        scene_starts =
+            detect::apply_hints(&scene_starts, &hints, &results.scores, args.hint_window);
+    }
+
+    // Replace the old file's boundaries inside the patched range
+    if let (Some(range), Some(path)) = (args.patch_range, &args.from_scenes) {
+        let old = output::read_scene_starts(path)?;
+        scene_starts = splice_range(&old, &scene_starts, range);
+    }
 
     // Ensure we always have frame 0 as first scene start
     if scene_starts.is_empty() || scene_starts[0] != 0 {
         scene_starts.insert(0, 0);
     }
 
-    // Use total_frames from args (more reliable than frame_count for some formats)
-    let total_frames = if args.total_frames > 0 {
+    // Split against the frames actually decoded; a wrong --total-frames or
+    // container count would leave the final chunk the wrong length. Patched
+    // detection only decodes the range, so the given count stands.
+    let total_frames = if args.patch_range.is_some() {
         args.total_frames
     } else {
-        results.frame_count
+        let (total, warning) =
+            reconcile_total_frames(args.total_frames, probed_total, results.frame_count);
+        if let Some(warning) = warning {
+            eprintln!("Warning: {}", warning);
+        }
+        total
+    };
+    scene_starts.retain(|&start| start < total_frames.max(1));
+    let found_scenes = scene_starts.len();
+
+    if let Some(range) = &args.sweep_threshold {
+        if let Some(index) = stream {
+            println!("Stream {}:", index);
+        }
+        sweep::print_sweep(&sweep::sweep(&results.scores, total_frames, range), fps);
+    }
+
+    // Runs of identical frames (stills, freezes)
+    let freezes = args.freeze_min_secs.map(|secs| {
+        let min_frames = (fps * secs).ceil() as usize;
+        segments::freeze_runs(&results.frame_stats, min_frames)
+    });
+
+    // Sustained black segments (fade-throughs, commercial blacks)
+    let blacks = args.black_min_secs.map(|secs| {
+        let min_frames = (fps * secs).ceil() as usize;
+        segments::black_segments(&results.frame_stats, min_frames)
+    });
+
+    // Silent audio ranges, from a separate audio pass
+    let silences = match args.silence_min_secs {
+        Some(secs) => {
+            let min_frames = (fps * secs).ceil() as usize;
+            let levels = audio_levels(&args.input, args.fps_num, args.fps_den)?;
+            Some(silence::silences(
+                &levels,
+                args.silence_threshold_db,
+                min_frames,
+                args.fps_num,
+                args.fps_den,
+            ))
+        }
+        None => None,
+    };
+
+    // Black segment midpoints become boundaries just like detected cuts
+    if let (true, Some(segments)) = (args.black_splits, &blacks) {
+        scene_starts.extend(segments.iter().map(FrameRange::midpoint));
+        scene_starts.sort();
+        scene_starts.dedup();
+    }
+
+    // Excluded ranges are chunks of their own
+    if !filters.exclude.is_empty() {
+        scene_starts = isolate_ranges(&scene_starts, &filters.exclude, total_frames);
+    }
+
+    // Per-frame cost estimates for complexity balancing and the chunk report
+    let costs = frame_costs(&results.scores, total_frames);
+
+    // Split long scenes at regular intervals, or by estimated encoding work
+    let final_scenes = match args.balance {
+        Balance::Frames => split_long_scenes(&scene_starts, total_frames, max_scene_frames),
+        Balance::Complexity => {
+            split_long_scenes_balanced(&scene_starts, total_frames, max_scene_frames, &costs)
+        }
     };
 
-    // Split long scenes at regular intervals
-    let final_scenes = split_long_scenes(&scene_starts, total_frames, max_scene_frames);
+    // Keep forced splits out of, or move them onto, freeze runs
+    let final_scenes = match (args.freeze_splits, &freezes) {
+        (FreezeSplits::Avoid, Some(runs)) => avoid_ranges(
+            &final_scenes,
+            &scene_starts,
+            total_frames,
+            max_scene_frames,
+            runs,
+        ),
+        (FreezeSplits::Prefer, Some(runs)) => {
+            let edges: Vec<usize> = runs.iter().flat_map(|r| [r.start, r.end]).collect();
+            snap_forced_splits(
+                &final_scenes,
+                &scene_starts,
+                total_frames,
+                max_scene_frames,
+                &edges,
+            )
+        }
+        _ => final_scenes,
+    };
+
+    // Merge away any chunk that is still too short for the encoder, without
+    // exceeding the maximum or merging into an excluded range
+    let exclude_edges: Vec<usize> = filters
+        .exclude
+        .iter()
+        .flat_map(|range| [range.start, range.end])
+        .collect();
+    let final_scenes = enforce_min_chunk_len(
+        &final_scenes,
+        total_frames,
+        min_chunk_frames,
+        max_scene_frames,
+        &exclude_edges,
+    );
+    let short = short_chunks(&final_scenes, total_frames, min_chunk_frames);
+    if final_scenes.len() > 1 && !short.is_empty() {
+        eprintln!(
+            "Warning: {} chunks are shorter than --min-chunk-secs (first at frame {}); \
+             merging them would exceed the maximum scene length or an excluded range's edge",
+            short.len(),
+            short[0]
+        );
+    }
+
+    let transitions = if args.transitions {
+        transition::transitions(&final_scenes, &scene_starts, &results.frame_stats)
+    } else {
+        Vec::new()
+    };
+
+    // The scene file's own numbering; everything else stays in
+    // presentation order
+    let file_scenes = match args.numbering {
+        Numbering::Presentation => final_scenes.clone(),
+        Numbering::Decode => {
+            let indices = decode_indices(args, stream, results.frame_count)?;
+            numbering::renumber(&final_scenes, &indices)
+        }
+    };
+
+    timings.post_processing = post_started.elapsed();
+    let output_started = Instant::now();
+
+    let checksum = if args.checksum {
+        // Over the boundaries as written, in the file's own numbering
+        Some(checksum::Checksum::new(
+            &args.input,
+            &file_scenes,
+            total_frames,
+        )?)
+    } else {
+        None
+    };
 
     // Write output file
-    let file = File::create(&args.output)
-        .with_context(|| format!("Failed to create output file {:?}", args.output))?;
-    let mut writer = BufWriter::new(file);
+    match args.format {
+        OutputFormat::Text => match args.output_mode {
+            OutputMode::Starts => output::write_text(&output_path, &file_scenes)?,
+            OutputMode::Ranges => output::write_ranges(
+                &output_path,
+                &file_scenes,
+                total_frames,
+                &args.range_separator,
+                args.inclusive_end,
+            )?,
+        },
+        OutputFormat::Json => {
+            let mut scenes = output::scenes_from_starts(&file_scenes, total_frames);
+            for (i, scene) in scenes.iter_mut().enumerate() {
+                scene.transition = transitions.get(i).copied().flatten();
+                if args.luma_stats {
+                    scene.luma =
+                        scene_stats::luma_stats(&results.frame_stats, scene.start, scene.end);
+                }
+                if args.hdr_stats {
+                    scene.hdr =
+                        scene_stats::hdr_stats(&results.frame_stats, scene.start, scene.end);
+                }
+                if args.motion_stats {
+                    scene.motion =
+                        scene_stats::motion_stats(&results.scores, scene.start, scene.end);
+                }
+            }
+            let doc = SceneDocument {
+                fps_num: args.fps_num,
+                fps_den: args.fps_den,
+                total_frames,
+                scenes,
+                freezes,
+                blacks,
+                silences,
+                rejected,
+                checksum,
+            };
+            output::write_json(&output_path, &doc)?;
+        }
+        OutputFormat::Manifest => {
+            let mut manifest = output::build_manifest(
+                &final_scenes,
+                &scene_starts,
+                total_frames,
+                &costs,
+                args.fps_num,
+                args.fps_den,
+            );
+            for (chunk, transition) in manifest.chunks.iter_mut().zip(&transitions) {
+                chunk.transition = *transition;
+            }
+            if !args.zone.is_empty() {
+                for chunk in &mut manifest.chunks {
+                    let (start, end) = (chunk.start, chunk.end);
+                    let metrics = ChunkMetrics {
+                        // Without scores every frame costs the same
+                        complexity: (!results.scores.is_empty()).then_some(chunk.complexity),
+                        luma: scene_stats::luma_stats(&results.frame_stats, start, end)
+                            .map(|luma| luma.avg),
+                        motion: scene_stats::motion_stats(&results.scores, start, end)
+                            .map(|motion| motion.avg),
+                        duration: chunk.duration,
+                        frames: chunk.frames,
+                    };
+                    chunk.zone = zones::evaluate(&args.zone, &metrics);
+                }
+            }
+            manifest.checksum = checksum;
+            output::write_json(&output_path, &manifest)?;
+        }
+    }
+
+    if let Some(chapters_path) = &args.chapters_out {
+        let chapters_path = &stream_path(chapters_path, stream);
+        // Chapters group detected scenes, ignoring forced splits
+        let boundaries: Vec<(usize, f64)> = scene_starts
+            .iter()
+            .map(|&frame| {
+                let strength = results.scores.get(&frame).map_or(0.0, detect::cut_strength);
+                (frame, strength)
+            })
+            .collect();
+        let min_frames = (fps * args.chapter_min_secs).ceil() as usize;
+        let chapter_starts = chapters::group_chapters(&boundaries, total_frames, min_frames);
+        chapters::write_chapters(
+            chapters_path,
+            args.chapter_format,
+            &chapter_starts,
+            total_frames,
+            args.fps_num,
+            args.fps_den,
+        )?;
+
+        if args.progress {
+            eprintln!(
+                "Wrote {} chapters to {:?}",
+                chapter_starts.len(),
+                chapters_path
+            );
+        }
+    }
+
+    if let Some(script_path) = &args.emit_script {
+        let script_path = &stream_path(script_path, stream);
+        let jobs = script::build_jobs(
+            &args.input,
+            stream,
+            &final_scenes,
+            total_frames,
+            args.fps_num,
+            args.fps_den,
+        );
+        script::write_script(script_path, args.script_format, &jobs)?;
+
+        if args.progress {
+            eprintln!("Wrote {} extraction jobs to {:?}", jobs.len(), script_path);
+        }
+    }
+
+    if let Some(ranges_path) = &args.byte_ranges {
+        let ranges_path = &stream_path(ranges_path, stream);
+        let packets = video_packets(&args.input, stream)?;
+        let keyframes = byte_ranges::keyframes(&packets);
+        let file_size = std::fs::metadata(&args.input)
+            .with_context(|| format!("Failed to read input {:?}", args.input))?
+            .len();
+        let ranges = byte_ranges::byte_ranges(
+            &final_scenes,
+            total_frames,
+            &keyframes,
+            file_size,
+            args.fps_num,
+            args.fps_den,
+        )?;
+        output::write_json(ranges_path, &ranges)?;
 
-    for frame in &final_scenes {
-        writeln!(writer, "{}", frame)?;
+        if args.progress {
+            let unaligned = ranges.segments.iter().filter(|s| !s.aligned).count();
+            eprintln!(
+                "Wrote {} byte ranges to {:?} ({} starting before their boundary)",
+                ranges.segments.len(),
+                ranges_path,
+                unaligned
+            );
+        }
     }
 
-    writer.flush()?;
+    if let Some(report_path) = &args.report {
+        let report_path = &stream_path(report_path, stream);
+        let chunk_report = report::build_report(&final_scenes, total_frames, &costs);
+        output::write_json(report_path, &chunk_report)?;
+
+        if args.progress {
+            eprintln!("Wrote chunk cost report to {:?}", report_path);
+        }
+    }
 
     if args.progress {
         eprintln!(
             "Wrote {} scene boundaries to {:?}",
             final_scenes.len(),
-            args.output
+            output_path
         );
     }
 
-    Ok(())
-}
-
-/// Split long scenes into smaller chunks at regular intervals.
-///
-/// When a scene is longer than max_frames, we split it evenly to create
-/// chunks that are as close to equal length as possible while staying
-/// under the max_frames limit.
-fn split_long_scenes(scene_starts: &[usize], total_frames: usize, max_frames: usize) -> Vec<usize> {
-    let mut result = Vec::new();
-
-    // Build scene ranges
-    for i in 0..scene_starts.len() {
-        let start = scene_starts[i];
-        let end = if i + 1 < scene_starts.len() {
-            scene_starts[i + 1]
-        } else {
-            total_frames
-        };
+    timings.output = output_started.elapsed();
+    if args.timings {
+        if let Some(index) = stream {
+            eprintln!("Stream {}:", index);
+        }
+        eprintln!("{}", timings);
+    }
 
-        result.push(start);
+    if args.summary {
+        if let Some(index) = stream {
+            eprintln!("Stream {}:", index);
+        }
+        let summary = Summary::new(
+            found_scenes,
+            &scene_starts,
+            &final_scenes,
+            total_frames,
+            args.fps_num,
+            args.fps_den,
+            &output_path,
+        );
+        eprintln!("{}", summary.render(summary::use_color()));
+    }
 
-        let scene_len = end.saturating_sub(start);
-        if scene_len > max_frames {
-            // Calculate how many chunks we need
-            let num_chunks = scene_len.div_ceil(max_frames);
-            let chunk_size = scene_len / num_chunks;
+    if let Some(fd) = &progress_fd {
+        fd.send(&Event::Done {
+            stream,
+            frames: total_frames,
+            detected_scenes,
+            chunks: final_scenes.len(),
+            output: &output_path,
+        });
+    }
 
-            // Add intermediate split points
-            for j in 1..num_chunks {
-                let split = start + j * chunk_size;
-                if split < end {
-                    result.push(split);
-                }
-            }
+    if let Some(template) = &args.exec_template {
+        let commands = exec::expand_template(
+            template,
+            &args.input,
+            stream,
+            &final_scenes,
+            total_frames,
+            args.fps_num,
+            args.fps_den,
+        );
+        match args.exec_jobs {
+            Some(jobs) => exec::run_commands(&commands, jobs, args.progress)?,
+            None => commands.iter().for_each(|command| println!("{}", command)),
         }
     }
 
-    // Sort and deduplicate
-    result.sort();
-    result.dedup();
-    result
+    Ok(())
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// Frame count of the source, from the `given` count (0 if unknown), the
+/// container's `probed` one and the `decoded` one. The decoded count wins
+/// unless nothing was decoded; a warning is returned when the counts
+/// disagree.
+fn reconcile_total_frames(
+    given: usize,
+    probed: Option<usize>,
+    decoded: usize,
+) -> (usize, Option<String>) {
+    if decoded == 0 {
+        return (given, None);
+    }
+    let warning = if given > 0 && given != decoded {
+        Some(format!(
+            "decoded {} frames but --total-frames is {}; using the decoded count",
+            decoded, given
+        ))
+    } else if given == 0 && probed.is_some_and(|probed| probed != decoded) {
+        Some(format!(
+            "decoded {} frames but the container reports {}; using the decoded count",
+            decoded,
+            probed.unwrap_or_default()
+        ))
+    } else {
+        None
+    };
+    (decoded, warning)
+}
 
-    #[test]
-    fn test_split_long_scenes_no_split_needed() {
-        let scenes = vec![0, 100, 200];
-        let result = split_long_scenes(&scenes, 300, 150);
-        assert_eq!(result, vec![0, 100, 200]);
+/// Decode-order index of each presentation-order frame of the input.
+/// `frames` is the decoded frame count, compared with the packet count.
+fn decode_indices(args: &Args, stream: Option<usize>, frames: usize) -> Result<Vec<usize>> {
+    if is_y4m(&args.input) {
+        // Raw frames are never reordered
+        return Ok((0..frames).collect());
     }
+    let timestamps = packet_timestamps(&args.input, stream)?;
+    if timestamps.len() != frames {
+        eprintln!(
+            "Warning: decoded {} frames but the stream has {} packets; boundaries after \
+             dropped or undecodable frames may be offset",
+            frames,
+            timestamps.len()
+        );
+    }
+    Ok(numbering::decode_indices(&timestamps))
+}
 
-    #[test]
-    fn test_split_long_scenes_single_split() {
-        let scenes = vec![0];
-        let result = split_long_scenes(&scenes, 400, 250);
-        // 400 frames, max 250 -> needs 2 chunks of 200 each
-        assert_eq!(result, vec![0, 200]);
+#[cfg(feature = "ffmpeg")]
+fn packet_timestamps(path: &Path, stream: Option<usize>) -> Result<Vec<i64>> {
+    shear::ffmpeg::packet_timestamps(path, stream)
+}
+
+#[cfg(not(feature = "ffmpeg"))]
+fn packet_timestamps(_path: &Path, _stream: Option<usize>) -> Result<Vec<i64>> {
+    bail!("--numbering decode needs shear built with the ffmpeg feature for inputs other than y4m");
+}
+
+#[cfg(feature = "ffmpeg")]
+fn video_packets(path: &Path, stream: Option<usize>) -> Result<Vec<byte_ranges::VideoPacket>> {
+    shear::ffmpeg::video_packets(path, stream)
+}
+
+#[cfg(not(feature = "ffmpeg"))]
+fn video_packets(_path: &Path, _stream: Option<usize>) -> Result<Vec<byte_ranges::VideoPacket>> {
+    bail!("--byte-ranges needs shear built with the ffmpeg feature");
+}
+
+#[cfg(feature = "ffmpeg")]
+fn audio_levels(path: &Path, fps_num: u32, fps_den: u32) -> Result<Vec<f64>> {
+    shear::ffmpeg::audio_levels(path, fps_num, fps_den).context("Audio analysis failed")
+}
+
+#[cfg(not(feature = "ffmpeg"))]
+fn audio_levels(_path: &Path, _fps_num: u32, _fps_den: u32) -> Result<Vec<f64>> {
+    bail!("--silence-min-secs needs shear built with the ffmpeg feature");
+}
+
+/// Output path for a stream: the index goes before the extension, so
+/// `scenes.txt` becomes `scenes.1.txt`. Unchanged when `stream` is None.
+fn stream_path(path: &Path, stream: Option<usize>) -> PathBuf {
+    let Some(index) = stream else {
+        return path.to_path_buf();
+    };
+    let mut name = path.file_stem().unwrap_or_default().to_os_string();
+    name.push(format!(".{}", index));
+    if let Some(ext) = path.extension() {
+        name.push(".");
+        name.push(ext);
     }
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
     #[test]
-    fn test_split_long_scenes_multiple_splits() {
-        let scenes = vec![0];
-        let result = split_long_scenes(&scenes, 1000, 300);
-        // 1000 frames, max 300 -> needs 4 chunks of 250 each
-        assert_eq!(result, vec![0, 250, 500, 750]);
+    fn test_reconcile_total_frames() {
+        assert_eq!(reconcile_total_frames(400, Some(400), 400), (400, None));
+        let (total, warning) = reconcile_total_frames(450, None, 400);
+        assert_eq!(total, 400);
+        assert!(warning.unwrap().contains("--total-frames is 450"));
+        let (total, warning) = reconcile_total_frames(0, Some(390), 400);
+        assert_eq!(total, 400);
+        assert!(warning.unwrap().contains("container reports 390"));
+        // Unknown everywhere but in the decoded count
+        assert_eq!(reconcile_total_frames(0, None, 400), (400, None));
+        assert_eq!(reconcile_total_frames(400, None, 0), (400, None));
     }
 
     #[test]
-    fn test_split_long_scenes_mixed() {
-        let scenes = vec![0, 100, 600];
-        let result = split_long_scenes(&scenes, 900, 200);
-        // Scene 0-100: 100 frames, no split
-        // Scene 100-600: 500 frames, needs 3 chunks of 166 each
-        // Scene 600-900: 300 frames, needs 2 chunks of 150 each
-        assert_eq!(result, vec![0, 100, 266, 432, 600, 750]);
+    fn test_stream_path() {
+        let path = Path::new("out/scenes.txt");
+        assert_eq!(stream_path(path, None), PathBuf::from("out/scenes.txt"));
+        assert_eq!(
+            stream_path(path, Some(1)),
+            PathBuf::from("out/scenes.1.txt")
+        );
+        assert_eq!(
+            stream_path(Path::new("scenes"), Some(2)),
+            PathBuf::from("scenes.2")
+        );
     }
 }
@@ -0,0 +1,62 @@
+//! Frame numbering in presentation or decode order.
+//!
+//! Decoders return frames in presentation (display) order, and that is how
+//! shear numbers frames everywhere. With B-frame reordering a stream stores
+//! and decodes frames in a different order, so tools that count packets
+//! rather than decoded frames see different numbers for the same frame: in
+//! an open GOP, the B-frames shown just before a keyframe follow it in
+//! decode order.
+
+use clap::ValueEnum;
+
+/// Which order the scene file's frame numbers count in.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Numbering {
+    /// Display order, as frames are decoded and shown
+    #[default]
+    Presentation,
+    /// Order of the stream's packets, as stored in the file
+    Decode,
+}
+
+/// For each frame in presentation order, its index in decode order, given
+/// the presentation timestamps of the stream's packets in decode order.
+pub fn decode_indices(pts: &[i64]) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..pts.len()).collect();
+    order.sort_by_key(|&index| pts[index]);
+    order
+}
+
+/// Renumber presentation-order boundaries into decode order. Frames past
+/// the end of `decode_indices` keep their number.
+pub fn renumber(starts: &[usize], decode_indices: &[usize]) -> Vec<usize> {
+    let mut result: Vec<usize> = starts
+        .iter()
+        .map(|&frame| decode_indices.get(frame).copied().unwrap_or(frame))
+        .collect();
+    result.sort();
+    result.dedup();
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_indices() {
+        // I0 P3 B1 B2 P6 B4 B5, timestamps in units of one frame
+        let closed = decode_indices(&[0, 3, 1, 2, 6, 4, 5]);
+        assert_eq!(closed, vec![0, 2, 3, 1, 5, 6, 4]);
+
+        // Open GOP: the second keyframe (shown at 6) is decoded before the
+        // two B-frames shown ahead of it
+        let open = decode_indices(&[0, 3, 1, 2, 6, 4, 5, 9, 7, 8]);
+        assert_eq!(open[6], 4);
+        assert_eq!(renumber(&[0, 6], &open), vec![0, 4]);
+
+        // Without reordering both orders agree
+        assert_eq!(decode_indices(&[0, 1, 2]), vec![0, 1, 2]);
+        assert_eq!(renumber(&[0, 5], &[0, 1, 2]), vec![0, 5]);
+    }
+}
@@ -0,0 +1,385 @@
+//! Scene file writers.
+
+use crate::chapters;
+use crate::checksum::Checksum;
+use crate::detect::Rejected;
+use crate::edl;
+use crate::report;
+use crate::scene_stats::{HdrStats, LumaStats, MotionStats};
+use crate::segments::FrameRange;
+use crate::silence::Silence;
+use crate::timecode;
+use crate::transition::Transition;
+use crate::zones::Override;
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// Scene file format
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// One scene start frame per line
+    Text,
+    /// JSON document with scene ranges and analysis results
+    Json,
+    /// Chunk-oriented JSON document for encode orchestrators
+    Manifest,
+}
+
+/// What each line of a text scene file holds
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputMode {
+    /// Scene start frame
+    Starts,
+    /// Start and end frame of the scene
+    Ranges,
+}
+
+/// Everything written to a JSON scene file.
+#[derive(Serialize, Debug)]
+pub struct SceneDocument {
+    pub fps_num: u32,
+    pub fps_den: u32,
+    pub total_frames: usize,
+    pub scenes: Vec<Scene>,
+    /// Runs of identical frames, when freeze detection is enabled
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub freezes: Option<Vec<FrameRange>>,
+    /// Sustained black segments, when black detection is enabled
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub blacks: Option<Vec<FrameRange>>,
+    /// Silent audio ranges, when the silence map is enabled
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub silences: Option<Vec<Silence>>,
+    /// Cuts dropped by `--min-confidence` or `--refine`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rejected: Option<Vec<Rejected>>,
+    /// Input and boundary checksums, when requested
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<Checksum>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Scene {
+    pub start: usize,
+    /// Exclusive end frame
+    pub end: usize,
+    pub frames: usize,
+    /// How the scene follows the previous one, when transitions are
+    /// classified (never set for the first scene)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transition: Option<Transition>,
+    /// Brightness, when luma statistics are enabled
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub luma: Option<LumaStats>,
+    /// Light levels, when HDR statistics are enabled
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hdr: Option<HdrStats>,
+    /// Motion, when motion statistics are enabled
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub motion: Option<MotionStats>,
+}
+
+/// Build scene ranges from a sorted list of scene start frames.
+pub fn scenes_from_starts(scene_starts: &[usize], total_frames: usize) -> Vec<Scene> {
+    scene_starts
+        .iter()
+        .enumerate()
+        .map(|(i, &start)| {
+            let end = scene_starts.get(i + 1).copied().unwrap_or(total_frames);
+            Scene {
+                start,
+                end,
+                frames: end.saturating_sub(start),
+                transition: None,
+                luma: None,
+                hdr: None,
+                motion: None,
+            }
+        })
+        .collect()
+}
+
+/// Everything written to a chunk manifest.
+#[derive(Serialize, Debug)]
+pub struct Manifest {
+    pub fps_num: u32,
+    pub fps_den: u32,
+    pub total_frames: usize,
+    pub chunks: Vec<ManifestChunk>,
+    /// Input and boundary checksums, when requested
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<Checksum>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct ManifestChunk {
+    pub index: usize,
+    pub start: usize,
+    /// Exclusive end frame
+    pub end: usize,
+    pub frames: usize,
+    /// Start time in seconds
+    pub start_ts: f64,
+    /// End time in seconds
+    pub end_ts: f64,
+    /// Duration in seconds
+    pub duration: f64,
+    /// True if the chunk starts at a forced split rather than a detected
+    /// scene change
+    pub forced: bool,
+    /// How the chunk follows the previous one, when transitions are
+    /// classified
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transition: Option<Transition>,
+    /// Mean per-frame cost relative to the whole video (1.0 = average)
+    pub complexity: f64,
+    /// Encoder setting overrides from matching `--zone` rules
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub zone: Option<BTreeMap<String, Override>>,
+}
+
+/// Build the manifest for the final chunk list.
+///
+/// `scene_starts` are the detected boundaries, before splitting, and
+/// `costs` holds one cost per frame, as produced by `frame_costs`.
+pub fn build_manifest(
+    chunk_starts: &[usize],
+    scene_starts: &[usize],
+    total_frames: usize,
+    costs: &[f64],
+    fps_num: u32,
+    fps_den: u32,
+) -> Manifest {
+    let seconds = |frame: usize| timecode::frame_to_nanos(frame, fps_num, fps_den) as f64 / 1e9;
+    let chunks = report::build_report(chunk_starts, total_frames, costs)
+        .chunks
+        .into_iter()
+        .map(|chunk| ManifestChunk {
+            index: chunk.index,
+            start: chunk.start,
+            end: chunk.end,
+            frames: chunk.frames,
+            start_ts: seconds(chunk.start),
+            end_ts: seconds(chunk.end),
+            duration: seconds(chunk.end) - seconds(chunk.start),
+            forced: chunk.start != 0 && !scene_starts.contains(&chunk.start),
+            transition: None,
+            complexity: chunk.complexity,
+            zone: None,
+        })
+        .collect();
+
+    Manifest {
+        fps_num,
+        fps_den,
+        total_frames,
+        chunks,
+        checksum: None,
+    }
+}
+
+/// Write one scene start frame per line.
+pub fn write_text(path: &Path, scene_starts: &[usize]) -> Result<()> {
+    let mut writer = create(path)?;
+    for frame in scene_starts {
+        writeln!(writer, "{}", frame)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Write each scene's start and end frame per line, joined by `separator`.
+/// Ends are exclusive unless `inclusive_end` is set, when they are the
+/// scene's last frame.
+pub fn write_ranges(
+    path: &Path,
+    scene_starts: &[usize],
+    total_frames: usize,
+    separator: &str,
+    inclusive_end: bool,
+) -> Result<()> {
+    let mut writer = create(path)?;
+    for line in range_lines(scene_starts, total_frames, separator, inclusive_end) {
+        writeln!(writer, "{}", line)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn range_lines(
+    scene_starts: &[usize],
+    total_frames: usize,
+    separator: &str,
+    inclusive_end: bool,
+) -> Vec<String> {
+    scenes_from_starts(scene_starts, total_frames)
+        .iter()
+        .filter(|scene| scene.frames > 0)
+        .map(|scene| {
+            let end = if inclusive_end {
+                scene.end - 1
+            } else {
+                scene.end
+            };
+            format!("{}{}{}", scene.start, separator, end)
+        })
+        .collect()
+}
+
+/// Write a scene document, manifest or report as pretty-printed JSON.
+pub fn write_json<D: Serialize>(path: &Path, doc: &D) -> Result<()> {
+    let mut writer = create(path)?;
+    serde_json::to_writer_pretty(&mut writer, doc)?;
+    writeln!(writer)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// The part of a JSON scene file or manifest needed to read it back.
+#[derive(Deserialize)]
+struct SceneList {
+    #[serde(alias = "chunks")]
+    scenes: Vec<SceneStart>,
+}
+
+#[derive(Deserialize)]
+struct SceneStart {
+    start: usize,
+}
+
+/// Read scene start frames from a text or JSON scene file. Text lines may
+/// be ranges; only their start frame is read.
+pub fn read_scene_starts(path: &Path) -> Result<Vec<usize>> {
+    let contents = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read scene file {:?}", path))?;
+    parse_scene_starts(&contents).with_context(|| format!("Invalid scene file {:?}", path))
+}
+
+/// Read `--hints` cut points from a chapter file (any `ChapterFormat`), a
+/// CMX3600 EDL or a scene file, sorted and without duplicates.
+pub fn read_hints(path: &Path, fps_num: u32, fps_den: u32) -> Result<Vec<usize>> {
+    let contents = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read hints file {:?}", path))?;
+    let parse = || -> Result<Vec<usize>> {
+        match chapters::parse_chapters(&contents, fps_num, fps_den)? {
+            Some(cuts) => Ok(cuts),
+            None => match edl::parse_edl(&contents, fps_num, fps_den)? {
+                Some(cuts) => Ok(cuts),
+                None => parse_scene_starts(&contents),
+            },
+        }
+    };
+    let mut hints = parse().with_context(|| format!("Invalid hints file {:?}", path))?;
+    hints.sort();
+    hints.dedup();
+    Ok(hints)
+}
+
+fn parse_scene_starts(contents: &str) -> Result<Vec<usize>> {
+    if contents.trim_start().starts_with('{') {
+        let list: SceneList = serde_json::from_str(contents)?;
+        return Ok(list.scenes.iter().map(|s| s.start).collect());
+    }
+
+    contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            let line = line.trim();
+            let start = line
+                .find(|c: char| !c.is_ascii_digit())
+                .map_or(line, |end| &line[..end]);
+            start
+                .parse::<usize>()
+                .with_context(|| format!("line {}: expected a frame number", i + 1))
+        })
+        .collect()
+}
+
+fn create(path: &Path) -> Result<BufWriter<File>> {
+    let file =
+        File::create(path).with_context(|| format!("Failed to create output file {:?}", path))?;
+    Ok(BufWriter::new(file))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempPath;
+
+    #[test]
+    fn test_scenes_from_starts() {
+        let scenes = scenes_from_starts(&[0, 100, 250], 300);
+        let ranges: Vec<_> = scenes.iter().map(|s| (s.start, s.end, s.frames)).collect();
+        assert_eq!(ranges, vec![(0, 100, 100), (100, 250, 150), (250, 300, 50)]);
+    }
+
+    #[test]
+    fn test_parse_scene_starts() {
+        assert_eq!(
+            parse_scene_starts("0\n100\n\n250\n").unwrap(),
+            vec![0, 100, 250]
+        );
+        assert!(parse_scene_starts("0\nabc\n").is_err());
+        assert_eq!(
+            parse_scene_starts("0 100\n100-250\n").unwrap(),
+            vec![0, 100]
+        );
+
+        let json = r#"{"fps_num": 24, "fps_den": 1, "total_frames": 300,
+            "scenes": [{"start": 0, "end": 100, "frames": 100},
+                       {"start": 100, "end": 300, "frames": 200}]}"#;
+        assert_eq!(parse_scene_starts(json).unwrap(), vec![0, 100]);
+
+        let manifest = serde_json::to_string(&build_manifest(&[0, 100], &[0], 300, &[], 24, 1));
+        assert_eq!(
+            parse_scene_starts(&manifest.unwrap()).unwrap(),
+            vec![0, 100]
+        );
+    }
+
+    #[test]
+    fn test_read_hints() {
+        let path = TempPath::new("hints");
+        fs::write(&path, "CHAPTER01=00:00:04.000\nCHAPTER02=00:00:00.000\n").unwrap();
+        assert_eq!(read_hints(&path, 25, 1).unwrap(), vec![0, 100]);
+        fs::write(&path, "250\n0\n100\n100\n").unwrap();
+        assert_eq!(read_hints(&path, 25, 1).unwrap(), vec![0, 100, 250]);
+    }
+
+    #[test]
+    fn test_range_lines() {
+        assert_eq!(
+            range_lines(&[0, 100, 250], 300, " ", false),
+            vec!["0 100", "100 250", "250 300"]
+        );
+        assert_eq!(
+            range_lines(&[0, 100, 250], 300, "-", true),
+            vec!["0-99", "100-249", "250-299"]
+        );
+    }
+
+    #[test]
+    fn test_build_manifest() {
+        // Cut at 48, forced split at 96; the second chunk costs twice as much
+        let costs: Vec<f64> = (0..120)
+            .map(|frame| if (48..96).contains(&frame) { 2.0 } else { 1.0 })
+            .collect();
+        let manifest = build_manifest(&[0, 48, 96], &[0, 48], 120, &costs, 24, 1);
+        let chunk = &manifest.chunks[1];
+        assert_eq!((chunk.start, chunk.end, chunk.frames), (48, 96, 48));
+        assert_eq!(
+            (chunk.start_ts, chunk.end_ts, chunk.duration),
+            (2.0, 4.0, 2.0)
+        );
+        assert!(!chunk.forced);
+        assert!(manifest.chunks[2].forced);
+        assert!((chunk.complexity - 2.0 / 1.4).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,134 @@
+//! Structured progress for frontends, written as JSON lines to an inherited
+//! file descriptor (`--progress-fd`) so stderr stays free for warnings.
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::fs::File;
+use std::io::Write;
+use std::mem::ManuallyDrop;
+use std::path::Path;
+
+/// One progress line.
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event<'a> {
+    /// Detection is starting on an input
+    Start {
+        input: &'a Path,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        stream: Option<usize>,
+        /// Expected frame count, when known
+        #[serde(skip_serializing_if = "Option::is_none")]
+        total_frames: Option<usize>,
+    },
+    /// Frames analyzed so far
+    Progress {
+        frames: usize,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        percent: Option<f64>,
+    },
+    /// With --follow, a cut found so far
+    Cut { frame: usize },
+    /// Final summary, after all outputs are written
+    Done {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        stream: Option<usize>,
+        frames: usize,
+        detected_scenes: usize,
+        chunks: usize,
+        output: &'a Path,
+    },
+}
+
+/// Progress sink on an inherited file descriptor. The descriptor stays
+/// open when this is dropped, since every stream and run of the process
+/// writes to it.
+pub struct ProgressFd {
+    file: ManuallyDrop<File>,
+}
+
+impl ProgressFd {
+    /// Use descriptor `fd`, which the parent process must have left open.
+    #[cfg(unix)]
+    pub fn open(fd: u32) -> Result<Self> {
+        use std::os::fd::{FromRawFd, RawFd};
+
+        let raw = RawFd::try_from(fd)
+            .with_context(|| format!("Invalid progress file descriptor {}", fd))?;
+        // SAFETY: the parent hands the descriptor over for our writes, and
+        // ManuallyDrop keeps it from ever being closed here
+        let file = ManuallyDrop::new(unsafe { File::from_raw_fd(raw) });
+        file.metadata()
+            .with_context(|| format!("Failed to open progress file descriptor {}", fd))?;
+        Ok(ProgressFd { file })
+    }
+
+    #[cfg(not(unix))]
+    pub fn open(_fd: u32) -> Result<Self> {
+        anyhow::bail!("--progress-fd is only supported on Unix");
+    }
+
+    /// Write one event line. Errors are ignored: a frontend closing its end
+    /// of the pipe shouldn't abort detection.
+    pub fn send(&self, event: &Event) {
+        if let Ok(mut line) = serde_json::to_vec(event) {
+            line.push(b'\n');
+            let _ = (&*self.file).write_all(&line);
+        }
+    }
+}
+
+/// Percentage of `total` done, clamped to 100 in case of a frame count
+/// mismatch. None when the total is unknown.
+pub fn percent(frames: usize, total: usize) -> Option<f64> {
+    (total > 0).then(|| (frames as f64 / total as f64 * 100.0).min(100.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempPath;
+
+    #[test]
+    fn test_event_lines() {
+        let event = Event::Progress {
+            frames: 100,
+            percent: percent(100, 400),
+        };
+        assert_eq!(
+            serde_json::to_string(&event).unwrap(),
+            r#"{"event":"progress","frames":100,"percent":25.0}"#
+        );
+
+        let event = Event::Start {
+            input: Path::new("in.mkv"),
+            stream: None,
+            total_frames: None,
+        };
+        assert_eq!(
+            serde_json::to_string(&event).unwrap(),
+            r#"{"event":"start","input":"in.mkv"}"#
+        );
+        assert_eq!(percent(500, 400), Some(100.0));
+        assert_eq!(percent(10, 0), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_progress_fd() {
+        use std::os::fd::AsRawFd;
+
+        let path = TempPath::new("progress-fd");
+        let mut file = File::create(&path).unwrap();
+        let fd = file.as_raw_fd() as u32;
+        ProgressFd::open(fd)
+            .unwrap()
+            .send(&Event::Cut { frame: 48 });
+        // Dropping the sink leaves the descriptor open
+        file.write_all(b"end\n").unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "{\"event\":\"cut\",\"frame\":48}\nend\n"
+        );
+    }
+}
@@ -0,0 +1,302 @@
+//! Detection on frames the caller decodes itself.
+//!
+//! Players and transcoders already have every frame decoded. Instead of
+//! handing shear a file to decode again, they push each frame's raw planes
+//! into a `PushDetector`, which runs the same detection pass on a background
+//! thread and splits the result into chunks at the end.
+
+use crate::detect::{self, Backend, DetectionResults};
+use crate::filters::{new_frame, PreFilters};
+use crate::source::FrameSource;
+use crate::split::{enforce_min_chunk_len, split_long_scenes};
+use anyhow::{anyhow, bail, Context, Result};
+use av_scenechange::av_decoders::v_frame::{frame::Frame, pixel::Pixel};
+use av_scenechange::av_decoders::VideoDetails;
+use av_scenechange::DetectionOptions;
+use std::mem::size_of;
+use std::num::NonZeroU8;
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+use std::thread::{self, JoinHandle};
+
+/// Frames buffered between `push_frame` and the detection thread
+const PUSH_QUEUE_DEPTH: usize = 8;
+
+/// Frames received from a `PushDetector`.
+struct ChannelSource<T: Pixel> {
+    details: VideoDetails,
+    frames: Receiver<Frame<T>>,
+}
+
+impl<T: Pixel> FrameSource<T> for ChannelSource<T> {
+    fn video_details(&self) -> VideoDetails {
+        self.details
+    }
+
+    fn read_frame(&mut self) -> Result<Option<Frame<T>>> {
+        // The sender is dropped by `finish`
+        Ok(self.frames.recv().ok())
+    }
+}
+
+/// Boundaries found in pushed frames.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PushResults {
+    /// Detected scene starts, including frame 0
+    pub scenes: Vec<usize>,
+    /// Chunk starts after splitting long scenes and merging short chunks
+    pub chunks: Vec<usize>,
+    /// Timestamp each chunk's first frame was pushed with
+    pub chunk_pts: Vec<i64>,
+    pub frame_count: usize,
+}
+
+/// Scene detection fed one frame at a time.
+///
+/// Frames are pushed in presentation order as tightly packed planar YUV in
+/// the layout of `details`: the Y plane, then U and V (none for 4:0:0),
+/// with one byte per sample at 8 bits and two little-endian bytes above.
+pub struct PushDetector<T: Pixel> {
+    details: VideoDetails,
+    frames: Option<SyncSender<Frame<T>>>,
+    detection: Option<JoinHandle<Result<DetectionResults>>>,
+    pts: Vec<i64>,
+}
+
+impl<T: Pixel> PushDetector<T> {
+    /// Start a detection pass over frames of the given layout, with the
+    /// same options, backends and pre-filters as `detect::detect`.
+    pub fn new(
+        details: VideoDetails,
+        opts: DetectionOptions,
+        backends: &[Backend],
+        filters: PreFilters,
+    ) -> Result<Self> {
+        if (details.bit_depth > 8) != (size_of::<T>() > 1) {
+            bail!(
+                "{}-bit frames need {} pixels",
+                details.bit_depth,
+                if details.bit_depth > 8 { "u16" } else { "u8" }
+            );
+        }
+        filters.validate(&details)?;
+
+        let (frames, receiver) = sync_channel(PUSH_QUEUE_DEPTH);
+        let backends = backends.to_vec();
+        let min_votes = backends.len() / 2 + 1;
+        let detection = thread::spawn(move || {
+            let mut source = ChannelSource {
+                details,
+                frames: receiver,
+            };
+            detect::detect(
+                &mut source,
+                opts,
+                &filters,
+                false,
+                &backends,
+                min_votes,
+                None,
+                None,
+            )
+        });
+
+        Ok(PushDetector {
+            details,
+            frames: Some(frames),
+            detection: Some(detection),
+            pts: Vec::new(),
+        })
+    }
+
+    /// Add the next frame, blocking while the detection thread is behind.
+    /// `pts` must increase from frame to frame.
+    pub fn push_frame(&mut self, data: &[u8], pts: i64) -> Result<()> {
+        if self.pts.last().is_some_and(|&last| pts <= last) {
+            bail!(
+                "Frame {} has timestamp {}, not after the previous frame's; \
+                 frames must be pushed in presentation order",
+                self.pts.len(),
+                pts
+            );
+        }
+        let frame = self.frame_from(data)?;
+
+        let Some(frames) = &self.frames else {
+            bail!("Detection has already finished");
+        };
+        if frames.send(frame).is_err() {
+            // The pass only stops taking frames when it fails
+            self.frames = None;
+            return Err(self
+                .join()
+                .err()
+                .unwrap_or_else(|| anyhow!("Detection stopped early")));
+        }
+        self.pts.push(pts);
+        Ok(())
+    }
+
+    /// Finish detection and split the scenes into chunks of at most
+    /// `max_scene_frames` and, where merging stays within that, at least
+    /// `min_chunk_frames` frames.
+    pub fn finish(
+        mut self,
+        max_scene_frames: usize,
+        min_chunk_frames: usize,
+    ) -> Result<PushResults> {
+        self.frames = None;
+        let results = self.join()?;
+        let frame_count = results.frame_count;
+
+        let mut scenes = results.scene_changes;
+        if scenes.first() != Some(&0) {
+            scenes.insert(0, 0);
+        }
+        let max_scene_frames = max_scene_frames.max(1);
+        let chunks = split_long_scenes(&scenes, frame_count, max_scene_frames);
+        let chunks = enforce_min_chunk_len(
+            &chunks,
+            frame_count,
+            min_chunk_frames,
+            max_scene_frames,
+            &[],
+        );
+        let chunk_pts = chunks
+            .iter()
+            .filter_map(|&start| self.pts.get(start).copied())
+            .collect();
+        Ok(PushResults {
+            scenes,
+            chunks,
+            chunk_pts,
+            frame_count,
+        })
+    }
+
+    fn join(&mut self) -> Result<DetectionResults> {
+        let detection = self
+            .detection
+            .take()
+            .context("Detection has already finished")?;
+        detection
+            .join()
+            .map_err(|_| anyhow!("scene detection thread panicked"))?
+    }
+
+    /// Copy packed planes into a frame of the detector's layout.
+    fn frame_from(&self, data: &[u8]) -> Result<Frame<T>> {
+        let details = &self.details;
+        let bit_depth = NonZeroU8::new(details.bit_depth as u8).context("Bit depth is zero")?;
+        let mut frame = new_frame::<T>(
+            details.width,
+            details.height,
+            details.chroma_sampling,
+            bit_depth,
+        )?;
+
+        let mut planes = vec![&mut frame.y_plane];
+        planes.extend(frame.u_plane.as_mut());
+        planes.extend(frame.v_plane.as_mut());
+        let sizes: Vec<usize> = planes
+            .iter()
+            .map(|plane| plane.width().get() * plane.height().get() * size_of::<T>())
+            .collect();
+        let expected: usize = sizes.iter().sum();
+        if data.len() != expected {
+            bail!(
+                "Frame has {} bytes, expected {} for {}x{} at {} bits",
+                data.len(),
+                expected,
+                details.width,
+                details.height,
+                details.bit_depth
+            );
+        }
+
+        let mut offset = 0;
+        for (plane, size) in planes.into_iter().zip(sizes) {
+            plane.copy_from_u8_slice(&data[offset..offset + size])?;
+            offset += size;
+        }
+        Ok(frame)
+    }
+}
+
+impl<T: Pixel> Drop for PushDetector<T> {
+    fn drop(&mut self) {
+        // Let the detection thread see the end of the frames and exit
+        self.frames = None;
+        if let Some(detection) = self.detection.take() {
+            let _ = detection.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use av_scenechange::av_decoders::v_frame::chroma::ChromaSubsampling;
+    use av_scenechange::Rational32;
+
+    fn details() -> VideoDetails {
+        VideoDetails {
+            width: 64,
+            height: 64,
+            bit_depth: 8,
+            chroma_sampling: ChromaSubsampling::Yuv420,
+            frame_rate: Rational32::new(24, 1),
+            total_frames: None,
+        }
+    }
+
+    fn detector() -> PushDetector<u8> {
+        let opts = DetectionOptions {
+            lookahead_distance: 5,
+            ..Default::default()
+        };
+        PushDetector::new(
+            details(),
+            opts,
+            &[Backend::Cost { ensemble: false }],
+            PreFilters::default(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_push_frames() {
+        // Dark frames, then a cut to bright noise-like ones at 30
+        let mut detector = detector();
+        let size = 64 * 64 + 2 * 32 * 32;
+        for i in 0..60 {
+            let data: Vec<u8> = if i < 30 {
+                vec![16; size]
+            } else {
+                (0..size).map(|n| (n * 37 % 200 + 40) as u8).collect()
+            };
+            detector.push_frame(&data, 1000 + i * 2).unwrap();
+        }
+        let results = detector.finish(24, 0).unwrap();
+        assert_eq!(results.frame_count, 60);
+        assert_eq!(results.scenes, vec![0, 30]);
+        // Both 30-frame scenes are split in two for the 24-frame limit
+        assert_eq!(results.chunks, vec![0, 15, 30, 45]);
+        assert_eq!(results.chunk_pts, vec![1000, 1030, 1060, 1090]);
+    }
+
+    #[test]
+    fn test_push_frame_errors() {
+        let mut detector = detector();
+        let size = 64 * 64 + 2 * 32 * 32;
+        assert!(detector.push_frame(&vec![0; size - 1], 0).is_err());
+        detector.push_frame(&vec![0; size], 5).unwrap();
+        assert!(detector.push_frame(&vec![0; size], 5).is_err());
+        assert!(PushDetector::<u16>::new(
+            details(),
+            DetectionOptions::default(),
+            &[Backend::Cost { ensemble: false }],
+            PreFilters::default(),
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,119 @@
+//! Per-chunk encode cost and bitrate estimation report.
+//!
+//! Estimates are derived from the detector's inter-frame costs, which track
+//! motion and residual complexity. They are relative, not absolute: a chunk
+//! with an estimated cost of 480 takes roughly twice as long to encode as one
+//! with 240, and schedulers can calibrate against a measured encode.
+
+use serde::Serialize;
+
+#[derive(Serialize, Debug)]
+pub struct ChunkReport {
+    /// Sum of est_encode_cost over all chunks
+    pub total_est_encode_cost: f64,
+    pub chunks: Vec<ChunkEstimate>,
+    /// Chunk indices ordered by estimated encode cost, most expensive first
+    pub schedule: Vec<usize>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ChunkEstimate {
+    pub index: usize,
+    pub start: usize,
+    /// Exclusive end frame
+    pub end: usize,
+    pub frames: usize,
+    /// Mean per-frame cost relative to the whole video (1.0 = average)
+    pub complexity: f64,
+    /// Estimated encode work in units of one average frame
+    pub est_encode_cost: f64,
+    /// Estimated fraction of the total bitrate spent on this chunk
+    pub bitrate_share: f64,
+}
+
+/// Build the report for the final chunk list.
+///
+/// `costs` holds one cost per frame, as produced by `frame_costs`.
+pub fn build_report(scene_starts: &[usize], total_frames: usize, costs: &[f64]) -> ChunkReport {
+    let cost = |frame: usize| costs.get(frame).copied().unwrap_or(0.0).max(0.0);
+    let total_cost: f64 = (0..total_frames).map(cost).sum();
+    let mean_cost = if total_frames > 0 && total_cost > 0.0 {
+        total_cost / total_frames as f64
+    } else {
+        1.0
+    };
+
+    let chunks: Vec<ChunkEstimate> = scene_starts
+        .iter()
+        .enumerate()
+        .map(|(index, &start)| {
+            let end = scene_starts.get(index + 1).copied().unwrap_or(total_frames);
+            let frames = end.saturating_sub(start);
+            let chunk_cost: f64 = (start..end).map(cost).sum();
+            let complexity = if frames > 0 {
+                chunk_cost / frames as f64 / mean_cost
+            } else {
+                0.0
+            };
+
+            ChunkEstimate {
+                index,
+                start,
+                end,
+                frames,
+                complexity,
+                est_encode_cost: frames as f64 * complexity,
+                bitrate_share: if total_cost > 0.0 {
+                    chunk_cost / total_cost
+                } else {
+                    frames as f64 / total_frames.max(1) as f64
+                },
+            }
+        })
+        .collect();
+
+    let mut schedule: Vec<usize> = (0..chunks.len()).collect();
+    schedule.sort_by(|&a, &b| {
+        chunks[b]
+            .est_encode_cost
+            .total_cmp(&chunks[a].est_encode_cost)
+            .then(a.cmp(&b))
+    });
+
+    ChunkReport {
+        total_est_encode_cost: chunks.iter().map(|c| c.est_encode_cost).sum(),
+        chunks,
+        schedule,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_report_shares() {
+        // Chunk 0: 100 frames at cost 1, chunk 1: 100 frames at cost 3
+        let mut costs = vec![1.0; 100];
+        costs.extend(vec![3.0; 100]);
+        let report = build_report(&[0, 100], 200, &costs);
+
+        assert_eq!(report.chunks.len(), 2);
+        assert!((report.chunks[0].complexity - 0.5).abs() < 1e-9);
+        assert!((report.chunks[1].complexity - 1.5).abs() < 1e-9);
+        assert!((report.chunks[0].bitrate_share - 0.25).abs() < 1e-9);
+        assert!((report.chunks[1].bitrate_share - 0.75).abs() < 1e-9);
+        assert!((report.total_est_encode_cost - 200.0).abs() < 1e-9);
+        assert_eq!(report.schedule, vec![1, 0]);
+    }
+
+    #[test]
+    fn test_build_report_zero_cost() {
+        let costs = vec![0.0; 50];
+        let report = build_report(&[0, 10], 50, &costs);
+        // Falls back to frame counts when there is no cost information
+        assert!((report.chunks[0].bitrate_share - 0.2).abs() < 1e-9);
+        assert!((report.chunks[1].bitrate_share - 0.8).abs() < 1e-9);
+        assert_eq!(report.schedule, vec![0, 1]);
+    }
+}
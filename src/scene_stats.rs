@@ -0,0 +1,188 @@
+//! Per-scene statistics for the JSON scene file, aggregated from per-frame
+//! statistics gathered during the detection pass.
+
+use crate::detect::{FrameStats, DEFAULT_THRESHOLD};
+use av_scenechange::ScenecutResult;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Brightness of a scene, in 8-bit luma units.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct LumaStats {
+    /// Mean luma over all frames
+    pub avg: f64,
+    /// Mean luma of the darkest frame
+    pub min: f64,
+    /// Mean luma of the brightest frame
+    pub max: f64,
+}
+
+/// Luma statistics for frames `start..end`, or None if none have stats.
+pub fn luma_stats(stats: &[FrameStats], start: usize, end: usize) -> Option<LumaStats> {
+    let frames = stats.get(start..end.min(stats.len()))?;
+    if frames.is_empty() {
+        return None;
+    }
+
+    let mut luma = LumaStats {
+        avg: 0.0,
+        min: f64::MAX,
+        max: f64::MIN,
+    };
+    for frame in frames {
+        luma.avg += frame.mean_luma;
+        luma.min = luma.min.min(frame.mean_luma);
+        luma.max = luma.max.max(frame.mean_luma);
+    }
+    luma.avg /= frames.len() as f64;
+    Some(luma)
+}
+
+/// Light levels of a scene in nits, estimated from luma read as PQ: the
+/// per-scene counterparts of HDR10's MaxCLL and MaxFALL.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct HdrStats {
+    /// Brightest pixel in any frame
+    pub max_cll: f64,
+    /// Highest frame-average light level
+    pub max_fall: f64,
+    /// Mean frame-average light level
+    pub avg: f64,
+}
+
+/// HDR light levels for frames `start..end`, or None if none have stats.
+pub fn hdr_stats(stats: &[FrameStats], start: usize, end: usize) -> Option<HdrStats> {
+    let frames = stats.get(start..end.min(stats.len()))?;
+    if frames.is_empty() {
+        return None;
+    }
+
+    let mut hdr = HdrStats {
+        max_cll: 0.0,
+        max_fall: 0.0,
+        avg: 0.0,
+    };
+    for frame in frames {
+        hdr.max_cll = hdr.max_cll.max(frame.max_nits);
+        hdr.max_fall = hdr.max_fall.max(frame.mean_nits);
+        hdr.avg += frame.mean_nits;
+    }
+    hdr.avg /= frames.len() as f64;
+    Some(hdr)
+}
+
+/// Motion in a scene, as the detector's motion-compensated inter cost
+/// relative to the frame's intra cost: near 0 for static shots, and 1 or
+/// more once frames are as expensive to predict as to code from scratch.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct MotionStats {
+    /// Mean over the scored frames
+    pub avg: f64,
+    /// Highest single frame
+    pub peak: f64,
+}
+
+/// Motion statistics for frames `start..end`, or None if none are scored.
+/// The first frame is skipped, since its cost is against the previous scene.
+pub fn motion_stats(
+    scores: &BTreeMap<usize, ScenecutResult>,
+    start: usize,
+    end: usize,
+) -> Option<MotionStats> {
+    // The detector's threshold is a fixed fraction of the intra cost
+    let motion = |score: &ScenecutResult| {
+        if score.threshold > 0.0 {
+            score.inter_cost / score.threshold * DEFAULT_THRESHOLD / 100.0
+        } else {
+            0.0
+        }
+    };
+
+    let values: Vec<f64> = scores
+        .range(start + 1..end)
+        .map(|(_, s)| motion(s))
+        .collect();
+    if values.is_empty() {
+        return None;
+    }
+    Some(MotionStats {
+        avg: values.iter().sum::<f64>() / values.len() as f64,
+        peak: values.iter().copied().fold(0.0, f64::max),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats(means: &[f64]) -> Vec<FrameStats> {
+        means
+            .iter()
+            .map(|&mean_luma| FrameStats {
+                mean_luma,
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_luma_stats() {
+        let s = stats(&[20.0, 30.0, 40.0, 200.0]);
+        assert_eq!(
+            luma_stats(&s, 0, 3),
+            Some(LumaStats {
+                avg: 30.0,
+                min: 20.0,
+                max: 40.0
+            })
+        );
+        // The range is clamped to the frames that have stats
+        assert_eq!(luma_stats(&s, 3, 10).map(|l| l.avg), Some(200.0));
+        assert_eq!(luma_stats(&s, 4, 10), None);
+    }
+
+    #[test]
+    fn test_hdr_stats() {
+        let s: Vec<FrameStats> = [(1000.0, 200.0), (400.0, 300.0), (4000.0, 100.0)]
+            .iter()
+            .map(|&(max_nits, mean_nits)| FrameStats {
+                max_nits,
+                mean_nits,
+                ..Default::default()
+            })
+            .collect();
+        assert_eq!(
+            hdr_stats(&s, 0, 2),
+            Some(HdrStats {
+                max_cll: 1000.0,
+                max_fall: 300.0,
+                avg: 250.0,
+            })
+        );
+        assert_eq!(hdr_stats(&s, 2, 10).unwrap().max_cll, 4000.0);
+        assert_eq!(hdr_stats(&s, 3, 10), None);
+    }
+
+    #[test]
+    fn test_motion_stats() {
+        let score = |inter_cost| ScenecutResult {
+            inter_cost,
+            imp_block_cost: 0.0,
+            backward_adjusted_cost: 0.0,
+            forward_adjusted_cost: 0.0,
+            // Intra cost 100
+            threshold: 30.0,
+        };
+        let scores = BTreeMap::from([
+            (1, score(10.0)),
+            (2, score(30.0)),
+            (3, score(20.0)),
+            (5, score(90.0)),
+        ]);
+        let motion = motion_stats(&scores, 0, 5).unwrap();
+        assert!((motion.avg - 0.2).abs() < 1e-9);
+        assert!((motion.peak - 0.3).abs() < 1e-9);
+        // Frame 5 opens the scene, so nothing is left to score
+        assert_eq!(motion_stats(&scores, 5, 6), None);
+    }
+}
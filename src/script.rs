@@ -0,0 +1,177 @@
+//! Chunk extraction scripts: one ffmpeg command per chunk, as a shell
+//! script or a JSON job list for a job queue.
+
+use crate::timecode;
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use serde::Serialize;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// Extraction script format
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScriptFormat {
+    /// POSIX shell script
+    Shell,
+    /// JSON array of jobs, each with its ffmpeg argument list
+    Json,
+}
+
+/// One chunk extraction job.
+#[derive(Serialize, Debug)]
+pub struct ExtractJob {
+    pub index: usize,
+    pub start: usize,
+    /// Exclusive end frame
+    pub end: usize,
+    pub frames: usize,
+    /// Chunk file, relative to the output directory
+    pub output: String,
+    /// ffmpeg arguments, without the program name
+    pub args: Vec<String>,
+}
+
+/// Build extraction jobs for the final chunk list.
+///
+/// Seeking uses `-ss`/`-to` as input options, so ffmpeg decodes from the
+/// preceding keyframe and starts exactly at the chunk's first frame;
+/// `-frames:v` pins the frame count regardless of timestamp rounding.
+/// `stream` selects the video stream; None means the first one.
+pub fn build_jobs(
+    input: &Path,
+    stream: Option<usize>,
+    scene_starts: &[usize],
+    total_frames: usize,
+    fps_num: u32,
+    fps_den: u32,
+) -> Vec<ExtractJob> {
+    let map = match stream {
+        Some(index) => format!("0:{}", index),
+        None => "0:v:0".to_string(),
+    };
+
+    scene_starts
+        .iter()
+        .enumerate()
+        .map(|(index, &start)| {
+            let end = scene_starts.get(index + 1).copied().unwrap_or(total_frames);
+            let frames = end.saturating_sub(start);
+            let output = format!("{:05}.mkv", index);
+            let args = [
+                "-nostdin",
+                "-y",
+                "-ss",
+                &timecode::format_seconds(start, fps_num, fps_den),
+                "-to",
+                &timecode::format_seconds(end, fps_num, fps_den),
+                "-i",
+                &input.to_string_lossy(),
+                "-map",
+                &map,
+                "-frames:v",
+                &frames.to_string(),
+                "-c:v",
+                "ffv1",
+                &output,
+            ]
+            .map(String::from)
+            .to_vec();
+
+            ExtractJob {
+                index,
+                start,
+                end,
+                frames,
+                output,
+                args,
+            }
+        })
+        .collect()
+}
+
+/// Write the jobs in the given format.
+pub fn write_script(path: &Path, format: ScriptFormat, jobs: &[ExtractJob]) -> Result<()> {
+    let file =
+        File::create(path).with_context(|| format!("Failed to create script file {:?}", path))?;
+    let mut writer = BufWriter::new(file);
+
+    match format {
+        ScriptFormat::Shell => write_shell(&mut writer, jobs)?,
+        ScriptFormat::Json => {
+            serde_json::to_writer_pretty(&mut writer, jobs)?;
+            writeln!(writer)?;
+        }
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+/// Chunks are written to $OUTDIR (default `chunks`); the ffmpeg binary
+/// can be overridden with $FFMPEG.
+fn write_shell<W: Write>(w: &mut W, jobs: &[ExtractJob]) -> Result<()> {
+    writeln!(w, "#!/bin/sh")?;
+    writeln!(w, "# Chunk extraction script generated by shear")?;
+    writeln!(w, "set -e")?;
+    writeln!(w, "FFMPEG=\"${{FFMPEG:-ffmpeg}}\"")?;
+    writeln!(w, "OUTDIR=\"${{OUTDIR:-chunks}}\"")?;
+    writeln!(w, "mkdir -p \"$OUTDIR\"")?;
+    writeln!(w)?;
+
+    for job in jobs {
+        let (output, args) = job.args.split_last().expect("jobs have arguments");
+        write!(w, "\"$FFMPEG\"")?;
+        for arg in args {
+            write!(w, " {}", shell_quote(arg))?;
+        }
+        writeln!(w, " \"$OUTDIR\"/{}", shell_quote(output))?;
+    }
+    Ok(())
+}
+
+/// Quote a word for POSIX sh, leaving plain words untouched.
+pub fn shell_quote(word: &str) -> String {
+    let plain = !word.is_empty()
+        && word
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./:=+,".contains(c));
+    if plain {
+        word.to_string()
+    } else {
+        format!("'{}'", word.replace('\'', "'\\''"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_jobs() {
+        let jobs = build_jobs(Path::new("in.mkv"), None, &[0, 48], 72, 24, 1);
+        assert_eq!(jobs.len(), 2);
+        assert_eq!((jobs[1].start, jobs[1].end, jobs[1].frames), (48, 72, 24));
+        assert_eq!(
+            jobs[1].args.join(" "),
+            "-nostdin -y -ss 2.000000 -to 3.000000 -i in.mkv -map 0:v:0 \
+             -frames:v 24 -c:v ffv1 00001.mkv"
+        );
+
+        let jobs = build_jobs(Path::new("in.mkv"), Some(2), &[0], 10, 24, 1);
+        assert!(jobs[0].args.join(" ").contains("-map 0:2"));
+    }
+
+    #[test]
+    fn test_shell_script() {
+        let jobs = build_jobs(Path::new("my film's.mkv"), None, &[0], 24, 24, 1);
+        let mut out = Vec::new();
+        write_shell(&mut out, &jobs).unwrap();
+        let script = String::from_utf8(out).unwrap();
+        assert!(script.starts_with("#!/bin/sh\n"));
+        assert!(script.contains(
+            "\"$FFMPEG\" -nostdin -y -ss 0.000000 -to 1.000000 -i 'my film'\\''s.mkv' \
+             -map 0:v:0 -frames:v 24 -c:v ffv1 \"$OUTDIR\"/00000.mkv\n"
+        ));
+    }
+}
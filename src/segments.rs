@@ -0,0 +1,250 @@
+//! Detection of special frame ranges from per-frame statistics.
+
+use crate::detect::FrameStats;
+use crate::timecode;
+use serde::Serialize;
+use std::str::FromStr;
+
+/// Largest mean absolute luma difference (8-bit units) at which two
+/// consecutive frames are still considered identical. Allows for a little
+/// decoder noise on re-encoded stills.
+const FREEZE_MAX_DIFF: f64 = 0.5;
+
+/// Fraction of black pixels at which a whole frame counts as black
+const BLACK_FRAME_MIN_RATIO: f64 = 0.98;
+
+/// A half-open range of frames.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameRange {
+    pub start: usize,
+    /// Exclusive end frame
+    pub end: usize,
+}
+
+impl FrameRange {
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.end <= self.start
+    }
+
+    /// Middle frame of the range
+    pub fn midpoint(&self) -> usize {
+        self.start + self.len() / 2
+    }
+
+    /// True if `frame` lies strictly inside the range, so that a boundary
+    /// there would cut the range in two.
+    pub fn splits_at(&self, frame: usize) -> bool {
+        frame > self.start && frame < self.end
+    }
+}
+
+impl FromStr for FrameRange {
+    type Err = String;
+
+    /// Parse `start:end`, e.g. `1000:2500` (end exclusive).
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((start, end)) = s.split_once(':') else {
+            return Err("expected start:end, e.g. 1000:2500".to_string());
+        };
+        let parse = |v: &str| {
+            v.trim()
+                .parse::<usize>()
+                .map_err(|_| format!("invalid frame number {:?}", v))
+        };
+        let range = FrameRange {
+            start: parse(start)?,
+            end: parse(end)?,
+        };
+        if range.is_empty() {
+            return Err("range end must be after its start".to_string());
+        }
+        Ok(range)
+    }
+}
+
+/// A range given on the command line either in frames (`start:end`) or as
+/// timestamps (`start-end`, each `[[HH:]MM:]SS[.fff]`).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RangeSpec {
+    Frames(FrameRange),
+    Seconds { start: f64, end: f64 },
+}
+
+impl RangeSpec {
+    /// The frames covered at the given frame rate.
+    pub fn resolve(&self, fps_num: u32, fps_den: u32) -> FrameRange {
+        match *self {
+            RangeSpec::Frames(range) => range,
+            RangeSpec::Seconds { start, end } => FrameRange {
+                start: timecode::seconds_to_frame(start, fps_num, fps_den),
+                end: timecode::seconds_to_frame(end, fps_num, fps_den),
+            },
+        }
+    }
+}
+
+impl FromStr for RangeSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((start, end)) = s.split_once('-') else {
+            return s.parse().map(RangeSpec::Frames);
+        };
+        let parse = |v: &str| {
+            timecode::parse_seconds(v).ok_or_else(|| format!("invalid timestamp {:?}", v))
+        };
+        let (start, end) = (parse(start)?, parse(end)?);
+        if end <= start {
+            return Err("range end must be after its start".to_string());
+        }
+        Ok(RangeSpec::Seconds { start, end })
+    }
+}
+
+/// Find runs of identical frames lasting at least `min_frames`.
+pub fn freeze_runs(stats: &[FrameStats], min_frames: usize) -> Vec<FrameRange> {
+    let mut runs = Vec::new();
+    let mut run_start: Option<usize> = None;
+
+    for (frame, s) in stats.iter().enumerate() {
+        if s.luma_diff <= FREEZE_MAX_DIFF {
+            // The run includes the frame this one duplicates
+            run_start.get_or_insert(frame.saturating_sub(1));
+        } else if let Some(start) = run_start.take() {
+            runs.push(FrameRange { start, end: frame });
+        }
+    }
+    if let Some(start) = run_start {
+        runs.push(FrameRange {
+            start,
+            end: stats.len(),
+        });
+    }
+
+    runs.retain(|r| r.len() >= min_frames.max(2));
+    runs
+}
+
+/// Find sustained black segments lasting at least `min_frames`.
+pub fn black_segments(stats: &[FrameStats], min_frames: usize) -> Vec<FrameRange> {
+    let mut segments = Vec::new();
+    let mut seg_start: Option<usize> = None;
+
+    for (frame, s) in stats.iter().enumerate() {
+        if s.black_ratio >= BLACK_FRAME_MIN_RATIO {
+            seg_start.get_or_insert(frame);
+        } else if let Some(start) = seg_start.take() {
+            segments.push(FrameRange { start, end: frame });
+        }
+    }
+    if let Some(start) = seg_start {
+        segments.push(FrameRange {
+            start,
+            end: stats.len(),
+        });
+    }
+
+    segments.retain(|r| r.len() >= min_frames.max(1));
+    segments
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats(diffs: &[f64]) -> Vec<FrameStats> {
+        diffs
+            .iter()
+            .map(|&luma_diff| FrameStats {
+                luma_diff,
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    fn black(ratios: &[f64]) -> Vec<FrameStats> {
+        ratios
+            .iter()
+            .map(|&black_ratio| FrameStats {
+                black_ratio,
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_freeze_runs() {
+        // Frames 2-5 are identical, as are frames 7-9
+        let s = stats(&[f64::MAX, 5.0, 4.0, 0.0, 0.0, 0.2, 3.0, 6.0, 0.0, 0.0]);
+        assert_eq!(
+            freeze_runs(&s, 3),
+            vec![
+                FrameRange { start: 2, end: 6 },
+                FrameRange { start: 7, end: 10 },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_freeze_runs_min_length() {
+        let s = stats(&[f64::MAX, 0.0, 3.0, 0.0, 0.0, 0.0, 3.0]);
+        assert_eq!(freeze_runs(&s, 3), vec![FrameRange { start: 2, end: 6 }]);
+    }
+
+    #[test]
+    fn test_black_segments() {
+        let s = black(&[0.1, 1.0, 0.99, 1.0, 0.5, 1.0, 0.2, 0.98, 1.0, 1.0]);
+        assert_eq!(
+            black_segments(&s, 2),
+            vec![
+                FrameRange { start: 1, end: 4 },
+                FrameRange { start: 7, end: 10 },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_frame_range_midpoint() {
+        assert_eq!(FrameRange { start: 10, end: 20 }.midpoint(), 15);
+        assert_eq!(FrameRange { start: 10, end: 13 }.midpoint(), 11);
+    }
+
+    #[test]
+    fn test_parse_frame_range() {
+        assert_eq!(
+            "1000:2500".parse::<FrameRange>(),
+            Ok(FrameRange {
+                start: 1000,
+                end: 2500
+            })
+        );
+        assert!("2500:1000".parse::<FrameRange>().is_err());
+        assert!("1000".parse::<FrameRange>().is_err());
+    }
+
+    #[test]
+    fn test_parse_range_spec() {
+        let frames = FrameRange { start: 0, end: 240 };
+        assert_eq!("0:240".parse(), Ok(RangeSpec::Frames(frames)));
+        let spec: RangeSpec = "00:00:10-00:00:20.5".parse().unwrap();
+        assert_eq!(
+            spec,
+            RangeSpec::Seconds {
+                start: 10.0,
+                end: 20.5
+            }
+        );
+        assert_eq!(
+            spec.resolve(24, 1),
+            FrameRange {
+                start: 240,
+                end: 492
+            }
+        );
+        assert!("20-10".parse::<RangeSpec>().is_err());
+    }
+}
@@ -0,0 +1,785 @@
+//! `shear serve`: a long-running process taking detection jobs over a Unix
+//! domain socket, so orchestrators pay process startup and FFmpeg
+//! initialization once instead of per file.
+//!
+//! The protocol is newline-delimited JSON, one response line per request
+//! line:
+//!
+//! ```text
+//! {"op": "submit", "args": ["-i", "in.mkv", "-o", "scenes.txt", ...]}
+//! {"ok": true, "id": 1, "state": "queued"}
+//! {"op": "status", "id": 1}
+//! {"ok": true, "id": 1, "state": "running"}
+//! {"op": "cancel", "id": 1}
+//! {"ok": true, "id": 1, "state": "running"}
+//! {"op": "list"}
+//! {"ok": true, "jobs": [{"id": 1, "args": [...], "state": "cancelled"}]}
+//! {"op": "retry", "id": 1}
+//! {"ok": true, "id": 1, "state": "queued"}
+//! {"op": "metrics"}
+//! {"ok": true, "metrics": {"jobs_done": 0, "jobs_failed": 0, ...}}
+//! ```
+//!
+//! Submitted arguments are the same as for a one-shot run, except that
+//! `--deterministic` is refused, since it pins the analysis thread pool the
+//! daemon shares between all its jobs, and so is `--progress-fd`, whose
+//! descriptor would be one of the daemon's, not the client's. Jobs run one at a time, in
+//! submission order; a job that panics fails without stopping the daemon.
+//! `list` returns every job, and `retry` queues a failed or cancelled job
+//! again. `metrics` returns job counters, the queue depth and analysis
+//! speed, which `--metrics-addr` also serves over HTTP at `/metrics` in
+//! Prometheus' text format.
+//!
+//! With `--state-file`, the queue is saved after every change and reloaded
+//! on startup; jobs that were running when the daemon stopped are queued
+//! again.
+
+use crate::{detect_file, Args, Cli, FRAMES_ANALYZED};
+use anyhow::{anyhow, bail, Context, Result};
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+use shear::detect::CancellationToken;
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+use std::sync::{Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[derive(clap::Args, Debug)]
+pub struct ServeArgs {
+    /// Unix domain socket to listen on for job requests
+    #[arg(long)]
+    pub control_socket: PathBuf,
+
+    /// Save the job queue here and restore it on startup, so queued and
+    /// in-flight jobs survive restarts
+    #[arg(long)]
+    pub state_file: Option<PathBuf>,
+
+    /// Serve Prometheus metrics over HTTP at /metrics on this address,
+    /// e.g. 127.0.0.1:9184
+    #[arg(long, value_name = "ADDR")]
+    pub metrics_addr: Option<SocketAddr>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum Request {
+    /// Queue a detection job with the given command-line arguments
+    Submit {
+        args: Vec<String>,
+    },
+    Status {
+        id: u64,
+    },
+    /// Drop a queued job, or stop a running one at its next frame
+    Cancel {
+        id: u64,
+    },
+    List,
+    /// Queue a failed or cancelled job again
+    Retry {
+        id: u64,
+    },
+    Metrics,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum JobState {
+    Queued,
+    Running,
+    Done,
+    Failed,
+    Cancelled,
+}
+
+#[derive(Serialize, Debug, Default, PartialEq)]
+struct Response {
+    ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    state: Option<JobState>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    /// Every job, for `list`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    jobs: Option<Vec<JobRecord>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    metrics: Option<Metrics>,
+}
+
+/// Daemon activity since it started, for `metrics` and `--metrics-addr`.
+#[derive(Serialize, Debug, Default, PartialEq)]
+struct Metrics {
+    jobs_done: u64,
+    jobs_failed: u64,
+    jobs_cancelled: u64,
+    /// Jobs waiting to run
+    queue_depth: usize,
+    /// 1 while a job runs, else 0
+    jobs_running: usize,
+    frames_analyzed: u64,
+    /// Analysis speed of the running job, 0 when idle
+    frames_per_second: f64,
+}
+
+impl Metrics {
+    /// Prometheus text exposition format.
+    fn prometheus(&self) -> String {
+        let metrics: [(&str, &str, &str, f64); 7] = [
+            (
+                "shear_jobs_done_total",
+                "counter",
+                "Jobs completed",
+                self.jobs_done as f64,
+            ),
+            (
+                "shear_jobs_failed_total",
+                "counter",
+                "Jobs failed",
+                self.jobs_failed as f64,
+            ),
+            (
+                "shear_jobs_cancelled_total",
+                "counter",
+                "Jobs cancelled",
+                self.jobs_cancelled as f64,
+            ),
+            (
+                "shear_queue_depth",
+                "gauge",
+                "Jobs waiting to run",
+                self.queue_depth as f64,
+            ),
+            (
+                "shear_jobs_running",
+                "gauge",
+                "Jobs running",
+                self.jobs_running as f64,
+            ),
+            (
+                "shear_frames_analyzed_total",
+                "counter",
+                "Frames analyzed",
+                self.frames_analyzed as f64,
+            ),
+            (
+                "shear_frames_per_second",
+                "gauge",
+                "Analysis speed of the running job",
+                self.frames_per_second,
+            ),
+        ];
+        metrics
+            .iter()
+            .map(|(name, kind, help, value)| {
+                format!("# HELP {name} {help}\n# TYPE {name} {kind}\n{name} {value}\n")
+            })
+            .collect()
+    }
+}
+
+impl Response {
+    fn job(job: &Job) -> Self {
+        Response {
+            ok: true,
+            id: Some(job.id),
+            state: Some(job.state),
+            error: job.error.clone(),
+            ..Default::default()
+        }
+    }
+
+    fn error(message: impl Into<String>) -> Self {
+        Response {
+            error: Some(message.into()),
+            ..Default::default()
+        }
+    }
+}
+
+struct Job {
+    id: u64,
+    /// Arguments as submitted, kept for the state file
+    argv: Vec<String>,
+    args: Args,
+    state: JobState,
+    /// Why the job failed
+    error: Option<String>,
+    cancel: CancellationToken,
+}
+
+impl Job {
+    fn new(id: u64, argv: Vec<String>, args: Args) -> Self {
+        Job {
+            id,
+            argv,
+            args,
+            state: JobState::Queued,
+            error: None,
+            cancel: CancellationToken::new(),
+        }
+    }
+
+    fn record(&self) -> JobRecord {
+        JobRecord {
+            id: self.id,
+            args: self.argv.clone(),
+            state: self.state,
+            error: self.error.clone(),
+        }
+    }
+}
+
+/// A job as saved in the state file and returned by `list`.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct JobRecord {
+    id: u64,
+    args: Vec<String>,
+    state: JobState,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct StateFile {
+    last_id: u64,
+    jobs: Vec<JobRecord>,
+}
+
+#[derive(Default)]
+struct Queue {
+    last_id: u64,
+    /// All jobs in submission order, including finished ones
+    jobs: Vec<Job>,
+    /// Jobs finished since the daemon started, for metrics. Retried jobs
+    /// count again when they finish again.
+    done: u64,
+    failed: u64,
+    cancelled: u64,
+    /// When the running job started, and `FRAMES_ANALYZED` then
+    running_since: Option<(Instant, u64)>,
+}
+
+#[derive(Default)]
+struct Daemon {
+    queue: Mutex<Queue>,
+    /// Signalled when a job is queued
+    wake: Condvar,
+    state_file: Option<PathBuf>,
+    /// `FRAMES_ANALYZED` when the daemon started
+    frames_at_start: u64,
+}
+
+impl Daemon {
+    /// Create a daemon, restoring the queue from `state_file` if it exists.
+    fn load(state_file: Option<PathBuf>) -> Result<Self> {
+        let mut queue = Queue::default();
+        if let Some(path) = state_file.as_deref().filter(|path| path.exists()) {
+            let contents = fs::read_to_string(path)
+                .with_context(|| format!("Failed to read state file {:?}", path))?;
+            let state: StateFile = serde_json::from_str(&contents)
+                .with_context(|| format!("Invalid state file {:?}", path))?;
+
+            queue.last_id = state.last_id;
+            for record in state.jobs {
+                let mut job = match parse_job_args(&record.args) {
+                    Ok(args) => Job::new(record.id, record.args, args),
+                    // Options may have changed since the job was saved
+                    Err(e) => {
+                        eprintln!("Dropping saved job {}: {:#}", record.id, e);
+                        continue;
+                    }
+                };
+                // Interrupted jobs start over
+                if record.state != JobState::Running {
+                    job.state = record.state;
+                    job.error = record.error;
+                }
+                queue.jobs.push(job);
+            }
+        }
+
+        Ok(Daemon {
+            queue: Mutex::new(queue),
+            state_file,
+            frames_at_start: FRAMES_ANALYZED.load(Ordering::Relaxed),
+            ..Default::default()
+        })
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Queue> {
+        self.queue.lock().expect("job queue lock")
+    }
+
+    /// Write the queue to the state file, if there is one. Called with the
+    /// lock held so saves happen in order.
+    fn save(&self, queue: &Queue) {
+        let Some(path) = &self.state_file else {
+            return;
+        };
+        let state = StateFile {
+            last_id: queue.last_id,
+            jobs: queue.jobs.iter().map(Job::record).collect(),
+        };
+        // Write then rename, so a crash never leaves a truncated file
+        let tmp = path.with_extension("tmp");
+        let result = serde_json::to_vec_pretty(&state)
+            .map_err(anyhow::Error::from)
+            .and_then(|json| Ok(fs::write(&tmp, json)?))
+            .and_then(|()| Ok(fs::rename(&tmp, path)?));
+        if let Err(e) = result {
+            eprintln!("Failed to save state file {:?}: {:#}", path, e);
+        }
+    }
+
+    fn handle(&self, request: Request) -> Response {
+        match request {
+            Request::Submit { args: argv } => match parse_job_args(&argv) {
+                Ok(args) => {
+                    let mut queue = self.lock();
+                    queue.last_id += 1;
+                    let job = Job::new(queue.last_id, argv, args);
+                    let response = Response::job(&job);
+                    queue.jobs.push(job);
+                    self.save(&queue);
+                    self.wake.notify_one();
+                    response
+                }
+                Err(e) => Response::error(format!("{:#}", e)),
+            },
+            Request::Status { id } => match self.lock().jobs.iter().find(|job| job.id == id) {
+                Some(job) => Response::job(job),
+                None => Response::error(format!("No job {}", id)),
+            },
+            Request::Cancel { id } => {
+                let mut queue = self.lock();
+                let Some(index) = queue.jobs.iter().position(|job| job.id == id) else {
+                    return Response::error(format!("No job {}", id));
+                };
+                let job = &mut queue.jobs[index];
+                match job.state {
+                    JobState::Queued => {
+                        job.state = JobState::Cancelled;
+                        queue.cancelled += 1;
+                    }
+                    // The worker marks the job cancelled once detection stops
+                    JobState::Running => job.cancel.cancel(),
+                    _ => return Response::error(format!("Job {} already finished", id)),
+                }
+                let response = Response::job(&queue.jobs[index]);
+                self.save(&queue);
+                response
+            }
+            Request::List => Response {
+                ok: true,
+                jobs: Some(self.lock().jobs.iter().map(Job::record).collect()),
+                ..Default::default()
+            },
+            Request::Retry { id } => {
+                let mut queue = self.lock();
+                let Some(job) = queue.jobs.iter_mut().find(|job| job.id == id) else {
+                    return Response::error(format!("No job {}", id));
+                };
+                if !matches!(job.state, JobState::Failed | JobState::Cancelled) {
+                    return Response::error(format!("Job {} has not failed or been cancelled", id));
+                }
+                *job = Job::new(job.id, job.argv.clone(), job.args.clone());
+                let response = Response::job(job);
+                self.save(&queue);
+                self.wake.notify_one();
+                response
+            }
+            Request::Metrics => Response {
+                ok: true,
+                metrics: Some(self.metrics()),
+                ..Default::default()
+            },
+        }
+    }
+
+    fn metrics(&self) -> Metrics {
+        let queue = self.lock();
+        let frames = FRAMES_ANALYZED.load(Ordering::Relaxed);
+        let frames_per_second = queue.running_since.map_or(0.0, |(started, at_start)| {
+            let secs = started.elapsed().as_secs_f64();
+            if secs > 0.0 {
+                (frames - at_start) as f64 / secs
+            } else {
+                0.0
+            }
+        });
+        Metrics {
+            jobs_done: queue.done,
+            jobs_failed: queue.failed,
+            jobs_cancelled: queue.cancelled,
+            queue_depth: count(&queue, JobState::Queued),
+            jobs_running: count(&queue, JobState::Running),
+            frames_analyzed: frames.saturating_sub(self.frames_at_start),
+            frames_per_second,
+        }
+    }
+
+    /// Run queued jobs one at a time, forever.
+    fn work(&self) {
+        loop {
+            self.run_next(|args, cancel| detect_file(args, Some(cancel)));
+        }
+    }
+
+    /// Wait for the next queued job and run it with `run`. A panic in the
+    /// job fails it rather than the worker, so later jobs still run.
+    fn run_next(&self, run: impl Fn(&Args, &CancellationToken) -> Result<()>) {
+        let (id, args, cancel) = {
+            let mut queue = self.lock();
+            loop {
+                let next = queue
+                    .jobs
+                    .iter_mut()
+                    .find(|job| job.state == JobState::Queued);
+                if let Some(job) = next {
+                    job.state = JobState::Running;
+                    let next = (job.id, job.args.clone(), job.cancel.clone());
+                    queue.running_since =
+                        Some((Instant::now(), FRAMES_ANALYZED.load(Ordering::Relaxed)));
+                    self.save(&queue);
+                    break next;
+                }
+                queue = self.wake.wait(queue).expect("job queue lock");
+            }
+        };
+
+        let result = panic::catch_unwind(AssertUnwindSafe(|| run(&args, &cancel))).unwrap_or_else(
+            |payload| {
+                let message = payload
+                    .downcast_ref::<&str>()
+                    .map(|s| s.to_string())
+                    .or_else(|| payload.downcast_ref::<String>().cloned())
+                    .unwrap_or_else(|| "unknown panic".to_string());
+                Err(anyhow!("Job panicked: {}", message))
+            },
+        );
+
+        let mut queue = self.lock();
+        queue.running_since = None;
+        let (state, error) = match result {
+            Ok(()) => (JobState::Done, None),
+            Err(_) if cancel.is_cancelled() => (JobState::Cancelled, None),
+            Err(e) => (JobState::Failed, Some(format!("{:#}", e))),
+        };
+        match state {
+            JobState::Done => queue.done += 1,
+            JobState::Failed => queue.failed += 1,
+            _ => queue.cancelled += 1,
+        }
+        if let Some(job) = queue.jobs.iter_mut().find(|job| job.id == id) {
+            job.state = state;
+            job.error = error;
+        }
+        self.save(&queue);
+    }
+}
+
+fn count(queue: &Queue, state: JobState) -> usize {
+    queue.jobs.iter().filter(|job| job.state == state).count()
+}
+
+/// Parse job arguments exactly like a one-shot command line.
+fn parse_job_args(args: &[String]) -> Result<Args> {
+    let argv = std::iter::once("shear").chain(args.iter().map(String::as_str));
+    let cli = Cli::try_parse_from(argv)?;
+    match (cli.command, cli.args) {
+        // The daemon's rayon pool is sized once for every job, so it can't
+        // be pinned to one thread for a single job
+        (None, Some(args)) if args.deterministic => {
+            bail!("--deterministic needs a one-shot run, not a daemon job")
+        }
+        // A descriptor number would name one of the daemon's own files, not
+        // one the client passed
+        (None, Some(args)) if args.progress_fd.is_some() => {
+            bail!("--progress-fd needs a one-shot run, not a daemon job")
+        }
+        (None, Some(args)) => Ok(args),
+        _ => bail!("Jobs take detection arguments, not subcommands"),
+    }
+}
+
+/// Answer requests on one connection until the client disconnects.
+fn serve_connection(daemon: &Daemon, stream: UnixStream) -> Result<()> {
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = match serde_json::from_str::<Request>(&line) {
+            Ok(request) => daemon.handle(request),
+            Err(e) => Response::error(format!("Invalid request: {}", e)),
+        };
+        serde_json::to_writer(&mut writer, &response)?;
+        writeln!(writer)?;
+    }
+    Ok(())
+}
+
+/// Answer one HTTP request: the metrics at `/metrics`, 404 elsewhere.
+fn serve_metrics(daemon: &Daemon, stream: TcpStream) -> Result<()> {
+    // A client that never finishes its request mustn't hold up the next one
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut writer = stream.try_clone()?;
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // Skip the headers; GET requests have no body
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 0 && !header.trim().is_empty() {
+        header.clear();
+    }
+    writer.write_all(metrics_response(daemon, &request_line).as_bytes())?;
+    Ok(())
+}
+
+fn metrics_response(daemon: &Daemon, request_line: &str) -> String {
+    let mut parts = request_line.split_whitespace();
+    let (status, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => ("200 OK", daemon.metrics().prometheus()),
+        _ => ("404 Not Found", "Not found\n".to_string()),
+    };
+    format!(
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )
+}
+
+/// Listen on `path`, replacing a socket left behind by a previous run.
+fn bind(path: &Path) -> Result<UnixListener> {
+    if let Ok(metadata) = fs::symlink_metadata(path) {
+        if !metadata.file_type().is_socket() {
+            bail!("{:?} exists and is not a socket", path);
+        }
+        if UnixStream::connect(path).is_ok() {
+            bail!("Another process is already listening on {:?}", path);
+        }
+        fs::remove_file(path).with_context(|| format!("Failed to remove stale {:?}", path))?;
+    }
+    UnixListener::bind(path).with_context(|| format!("Failed to listen on {:?}", path))
+}
+
+pub fn run(args: &ServeArgs) -> Result<()> {
+    let listener = bind(&args.control_socket)?;
+    eprintln!("Listening for jobs on {:?}", args.control_socket);
+
+    let metrics = args
+        .metrics_addr
+        .map(|addr| {
+            let listener = TcpListener::bind(addr)
+                .with_context(|| format!("Failed to listen for metrics on {}", addr))?;
+            eprintln!("Serving metrics on http://{}/metrics", addr);
+            anyhow::Ok(listener)
+        })
+        .transpose()?;
+
+    let daemon = Daemon::load(args.state_file.clone())?;
+    let daemon = &daemon;
+    thread::scope(|s| {
+        s.spawn(|| daemon.work());
+        if let Some(listener) = metrics {
+            s.spawn(move || {
+                for stream in listener.incoming() {
+                    let result = stream
+                        .map_err(anyhow::Error::from)
+                        .and_then(|stream| serve_metrics(daemon, stream));
+                    if let Err(e) = result {
+                        eprintln!("Metrics request failed: {:#}", e);
+                    }
+                }
+            });
+        }
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    s.spawn(move || {
+                        if let Err(e) = serve_connection(daemon, stream) {
+                            eprintln!("Control connection failed: {:#}", e);
+                        }
+                    });
+                }
+                Err(e) => eprintln!("Failed to accept control connection: {}", e),
+            }
+        }
+    });
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempPath;
+
+    fn submit(args: &[&str]) -> Request {
+        Request::Submit {
+            args: args.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    const JOB: &[&str] = &[
+        "-i",
+        "in.mkv",
+        "-o",
+        "scenes.txt",
+        "--fps-num",
+        "24",
+        "--fps-den",
+        "1",
+        "--total-frames",
+        "0",
+    ];
+
+    #[test]
+    fn test_submit_and_cancel_queued() {
+        let daemon = Daemon::default();
+        let response = daemon.handle(submit(JOB));
+        assert_eq!(response.id, Some(1));
+        assert_eq!(response.state, Some(JobState::Queued));
+
+        let response = daemon.handle(Request::Cancel { id: 1 });
+        assert_eq!(response.state, Some(JobState::Cancelled));
+        assert!(!daemon.handle(Request::Cancel { id: 1 }).ok);
+        assert!(!daemon.handle(Request::Status { id: 2 }).ok);
+    }
+
+    #[test]
+    fn test_submit_rejects_bad_args() {
+        let daemon = Daemon::default();
+        assert!(!daemon.handle(submit(&["-i", "in.mkv"])).ok);
+        assert!(!daemon.handle(submit(&["verify", "-s", "a", "-i", "b"])).ok);
+        let deterministic: Vec<&str> = JOB.iter().copied().chain(["--deterministic"]).collect();
+        assert!(!daemon.handle(submit(&deterministic)).ok);
+        let progress_fd: Vec<&str> = JOB.iter().copied().chain(["--progress-fd", "3"]).collect();
+        assert!(!daemon.handle(submit(&progress_fd)).ok);
+        assert!(daemon.lock().jobs.is_empty());
+    }
+
+    #[test]
+    fn test_retry_and_list() {
+        let daemon = Daemon::default();
+        daemon.handle(submit(JOB));
+        assert!(!daemon.handle(Request::Retry { id: 1 }).ok);
+        daemon.handle(Request::Cancel { id: 1 });
+        let response = daemon.handle(Request::Retry { id: 1 });
+        assert_eq!(response.state, Some(JobState::Queued));
+
+        let jobs = daemon.handle(Request::List).jobs.unwrap();
+        assert_eq!(jobs.len(), 1);
+        assert_eq!(jobs[0].args, JOB);
+        assert_eq!(jobs[0].state, JobState::Queued);
+    }
+
+    #[test]
+    fn test_job_panic_fails_job() {
+        let daemon = Daemon::default();
+        daemon.handle(submit(JOB));
+        daemon.handle(submit(JOB));
+        daemon.run_next(|_, _| panic!("decoder exploded"));
+        daemon.run_next(|_, _| Ok(()));
+
+        let jobs = daemon.handle(Request::List).jobs.unwrap();
+        assert_eq!(jobs[0].state, JobState::Failed);
+        assert!(jobs[0].error.as_ref().unwrap().contains("decoder exploded"));
+        assert_eq!(jobs[1].state, JobState::Done);
+    }
+
+    #[test]
+    fn test_metrics() {
+        let daemon = Daemon::default();
+        for _ in 0..4 {
+            daemon.handle(submit(JOB));
+        }
+        daemon.run_next(|_, _| Ok(()));
+        daemon.run_next(|_, _| bail!("no such file"));
+        daemon.handle(Request::Cancel { id: 3 });
+
+        let metrics = daemon.handle(Request::Metrics).metrics.unwrap();
+        assert_eq!(
+            (
+                metrics.jobs_done,
+                metrics.jobs_failed,
+                metrics.jobs_cancelled,
+                metrics.queue_depth,
+                metrics.jobs_running
+            ),
+            (1, 1, 1, 1, 0)
+        );
+        assert_eq!(metrics.frames_per_second, 0.0);
+
+        let response = metrics_response(&daemon, "GET /metrics HTTP/1.1\r\n");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response
+            .contains("\n# TYPE shear_jobs_failed_total counter\nshear_jobs_failed_total 1\n"));
+        assert!(response.contains("\nshear_queue_depth 1\n"));
+        assert!(metrics_response(&daemon, "GET / HTTP/1.1\r\n").starts_with("HTTP/1.1 404"));
+    }
+
+    #[test]
+    fn test_state_file_round_trip() {
+        let dir = TempPath::new("serve");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("jobs.json");
+
+        let daemon = Daemon::load(Some(path.clone())).unwrap();
+        for _ in 0..3 {
+            daemon.handle(submit(JOB));
+        }
+        daemon.handle(Request::Cancel { id: 2 });
+        // Simulate a crash mid-job
+        {
+            let mut queue = daemon.lock();
+            queue.jobs[0].state = JobState::Running;
+            daemon.save(&queue);
+        }
+
+        let restored = Daemon::load(Some(path)).unwrap();
+        let states: Vec<_> = restored.lock().jobs.iter().map(|job| job.state).collect();
+        assert_eq!(
+            states,
+            [JobState::Queued, JobState::Cancelled, JobState::Queued]
+        );
+        assert_eq!(restored.handle(submit(JOB)).id, Some(4));
+    }
+
+    #[test]
+    fn test_connection_protocol() {
+        let daemon = Daemon::default();
+        let (client, server) = UnixStream::pair().unwrap();
+        let handle = thread::scope(|s| {
+            let handle = s.spawn(|| serve_connection(&daemon, server));
+
+            let mut writer = client.try_clone().unwrap();
+            let mut lines = BufReader::new(client).lines();
+            let job = serde_json::to_string(JOB).unwrap();
+            writeln!(writer, r#"{{"op": "submit", "args": {}}}"#, job).unwrap();
+            assert_eq!(
+                lines.next().unwrap().unwrap(),
+                r#"{"ok":true,"id":1,"state":"queued"}"#
+            );
+            writeln!(writer, "not json").unwrap();
+            assert!(lines.next().unwrap().unwrap().starts_with(r#"{"ok":false"#));
+
+            drop(writer);
+            drop(lines);
+            handle.join().unwrap()
+        });
+        assert!(handle.is_ok());
+    }
+}
@@ -0,0 +1,166 @@
+//! Silence map from the main audio track, for ad-break placement and for
+//! checking that forced splits don't land in dialogue.
+//!
+//! Audio levels are accumulated per video frame, so silent ranges use the
+//! same frame numbers as the scene list.
+
+use crate::timecode;
+use serde::Serialize;
+
+/// Level reported for digital silence, in dBFS
+const FLOOR_DB: f64 = -120.0;
+
+/// A range of frames whose audio stays below the silence threshold.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Silence {
+    pub start: usize,
+    /// Exclusive end frame
+    pub end: usize,
+    /// Duration in seconds
+    pub duration: f64,
+    /// RMS level over the range, in dBFS
+    pub depth_db: f64,
+}
+
+/// Level in dBFS of a mean square sample value (full scale = 1.0).
+pub fn to_db(mean_square: f64) -> f64 {
+    if mean_square > 0.0 {
+        (10.0 * mean_square.log10()).max(FLOOR_DB)
+    } else {
+        FLOOR_DB
+    }
+}
+
+/// Accumulates decoded audio into per-video-frame levels.
+#[derive(Debug, Clone)]
+pub struct LevelMeter {
+    sample_rate: u64,
+    fps_num: u64,
+    fps_den: u64,
+    /// Samples (per channel) added so far
+    position: u64,
+    /// Sum of mean square times samples, per video frame
+    power: Vec<f64>,
+    samples: Vec<u64>,
+}
+
+impl LevelMeter {
+    pub fn new(sample_rate: u32, fps_num: u32, fps_den: u32) -> Self {
+        LevelMeter {
+            sample_rate: sample_rate.max(1) as u64,
+            fps_num: fps_num.max(1) as u64,
+            fps_den: fps_den.max(1) as u64,
+            position: 0,
+            power: Vec::new(),
+            samples: Vec::new(),
+        }
+    }
+
+    /// Add the next `samples` samples per channel, whose mean square value
+    /// is `mean_square`. Blocks spanning several video frames count
+    /// towards each of them.
+    pub fn push(&mut self, samples: usize, mean_square: f64) {
+        let samples_per_frame = self.sample_rate * self.fps_den;
+        let end = self.position + samples as u64;
+        while self.position < end {
+            let frame = (self.position * self.fps_num / samples_per_frame) as usize;
+            let next = ((frame as u64 + 1) * samples_per_frame).div_ceil(self.fps_num);
+            let take = next.min(end) - self.position;
+            if frame >= self.power.len() {
+                self.power.resize(frame + 1, 0.0);
+                self.samples.resize(frame + 1, 0);
+            }
+            self.power[frame] += mean_square * take as f64;
+            self.samples[frame] += take;
+            self.position += take;
+        }
+    }
+
+    /// Level of each video frame with audio, in dBFS.
+    pub fn levels_db(&self) -> Vec<f64> {
+        self.power
+            .iter()
+            .zip(&self.samples)
+            .map(|(&power, &samples)| to_db(power / samples.max(1) as f64))
+            .collect()
+    }
+}
+
+/// Runs of at least `min_frames` frames at or below `threshold_db`.
+pub fn silences(
+    levels_db: &[f64],
+    threshold_db: f64,
+    min_frames: usize,
+    fps_num: u32,
+    fps_den: u32,
+) -> Vec<Silence> {
+    let mut ranges = Vec::new();
+    let mut run_start: Option<usize> = None;
+    for frame in 0..=levels_db.len() {
+        let quiet = levels_db
+            .get(frame)
+            .is_some_and(|&level| level <= threshold_db);
+        match (quiet, run_start) {
+            (true, None) => run_start = Some(frame),
+            (false, Some(start)) => {
+                run_start = None;
+                if frame - start >= min_frames.max(1) {
+                    ranges.push((start, frame));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let seconds = |frame| timecode::frame_to_nanos(frame, fps_num, fps_den) as f64 / 1e9;
+    ranges
+        .into_iter()
+        .map(|(start, end)| {
+            let power = levels_db[start..end]
+                .iter()
+                .map(|&db| 10f64.powf(db / 10.0))
+                .sum::<f64>()
+                / (end - start) as f64;
+            Silence {
+                start,
+                end,
+                duration: seconds(end) - seconds(start),
+                depth_db: to_db(power),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_level_meter() {
+        // 48 kHz audio at 25 fps is 1920 samples per frame; a 2880-sample
+        // block covers frame 0 and half of frame 1
+        let mut meter = LevelMeter::new(48000, 25, 1);
+        meter.push(2880, 1.0);
+        meter.push(960, 0.01);
+        let levels = meter.levels_db();
+        assert_eq!(levels.len(), 2);
+        assert_eq!(levels[0], 0.0);
+        assert!((levels[1] - to_db(0.505)).abs() < 1e-9);
+        assert_eq!(to_db(0.0), FLOOR_DB);
+    }
+
+    #[test]
+    fn test_silences() {
+        let mut levels = vec![-20.0; 10];
+        levels[2..4].fill(-70.0);
+        levels[5..9].fill(-60.0);
+        let found = silences(&levels, -50.0, 3, 25, 1);
+        assert_eq!(found.len(), 1);
+        assert_eq!((found[0].start, found[0].end), (5, 9));
+        assert!((found[0].duration - 0.16).abs() < 1e-9);
+        assert!((found[0].depth_db + 60.0).abs() < 1e-9);
+        // A run reaching the end of the audio still counts
+        levels[7..].fill(-90.0);
+        assert_eq!(silences(&levels, -50.0, 3, 25, 1)[0].end, 10);
+    }
+}
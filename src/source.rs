@@ -0,0 +1,164 @@
+//! Frame sources feeding the detection pass.
+
+use crate::segments::FrameRange;
+use anyhow::{Context, Result};
+use av_scenechange::av_decoders::v_frame::{frame::Frame, pixel::Pixel};
+use av_scenechange::av_decoders::{DecoderError, DecoderImpl, VideoDetails, Y4mDecoder};
+use av_scenechange::Decoder;
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often a followed file is checked for new data
+const FOLLOW_POLL: Duration = Duration::from_millis(200);
+
+/// Anything that yields decoded frames in presentation order.
+///
+/// Sources are only read from the thread that runs the pass, so they
+/// needn't be `Send`; av-decoders' `Decoder` isn't.
+pub trait FrameSource<T: Pixel> {
+    /// Format of the frames this source produces.
+    fn video_details(&self) -> VideoDetails;
+
+    /// Read the next frame, or `None` at the end of the stream.
+    fn read_frame(&mut self) -> Result<Option<Frame<T>>>;
+}
+
+impl<T: Pixel> FrameSource<T> for Decoder {
+    fn video_details(&self) -> VideoDetails {
+        *self.get_video_details()
+    }
+
+    fn read_frame(&mut self) -> Result<Option<Frame<T>>> {
+        match self.read_video_frame::<T>() {
+            Ok(frame) => Ok(Some(frame)),
+            Err(DecoderError::EndOfFile) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// Frames `range.start..range.end` of another source.
+///
+/// Frames before the range are still decoded, but only to be skipped, since
+/// not every source can seek.
+pub struct RangeSource<'a, T: Pixel> {
+    inner: &'a mut dyn FrameSource<T>,
+    range: FrameRange,
+    next: usize,
+}
+
+impl<'a, T: Pixel> RangeSource<'a, T> {
+    pub fn new(inner: &'a mut dyn FrameSource<T>, range: FrameRange) -> Self {
+        Self::resuming(inner, range, 0)
+    }
+
+    /// Like `new`, for an `inner` source that has already read `position`
+    /// frames, so several ranges can be read in one pass.
+    pub fn resuming(inner: &'a mut dyn FrameSource<T>, range: FrameRange, position: usize) -> Self {
+        RangeSource {
+            inner,
+            range,
+            next: position,
+        }
+    }
+
+    /// Frames read from the inner source so far.
+    pub fn position(&self) -> usize {
+        self.next
+    }
+}
+
+impl<T: Pixel> FrameSource<T> for RangeSource<'_, T> {
+    fn video_details(&self) -> VideoDetails {
+        VideoDetails {
+            total_frames: Some(self.range.len()),
+            ..self.inner.video_details()
+        }
+    }
+
+    fn read_frame(&mut self) -> Result<Option<Frame<T>>> {
+        while self.next < self.range.start {
+            if self.inner.read_frame()?.is_none() {
+                return Ok(None);
+            }
+            self.next += 1;
+        }
+        if self.next >= self.range.end {
+            return Ok(None);
+        }
+        self.next += 1;
+        self.inner.read_frame()
+    }
+}
+
+/// A file that is still being written. Reads at the end of the file wait
+/// for more data, and only report the end once the file has stopped
+/// growing for `idle`.
+pub struct FollowReader {
+    file: File,
+    idle: Duration,
+}
+
+impl FollowReader {
+    pub fn open(path: &Path, idle: Duration) -> Result<Self> {
+        let file = File::open(path).with_context(|| format!("Failed to open input {:?}", path))?;
+        Ok(FollowReader { file, idle })
+    }
+}
+
+impl Read for FollowReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut waiting_since = None;
+        loop {
+            let read = self.file.read(buf)?;
+            if read > 0 || buf.is_empty() {
+                return Ok(read);
+            }
+            let since = *waiting_since.get_or_insert_with(Instant::now);
+            if since.elapsed() >= self.idle {
+                return Ok(0);
+            }
+            thread::sleep(FOLLOW_POLL);
+        }
+    }
+}
+
+/// Decoder for a y4m file that is still being written; see `FollowReader`.
+pub fn follow_y4m(path: &Path, idle: Duration) -> Result<Decoder> {
+    let reader = BufReader::new(FollowReader::open(path, idle)?);
+    let y4m = Y4mDecoder::new(Box::new(reader) as Box<dyn Read>)
+        .with_context(|| format!("Failed to read y4m header from {:?}", path))?;
+    Ok(Decoder::from_decoder_impl(DecoderImpl::Y4m(y4m))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempPath;
+    use std::fs::OpenOptions;
+    use std::io::Write;
+
+    #[test]
+    fn test_follow_reader_waits_for_growth() {
+        let path = TempPath::new("follow");
+        std::fs::write(&path, b"first ").unwrap();
+
+        let writer_path = path.to_path_buf();
+        let writer = thread::spawn(move || {
+            thread::sleep(FOLLOW_POLL * 2);
+            let mut file = OpenOptions::new().append(true).open(writer_path).unwrap();
+            file.write_all(b"second").unwrap();
+        });
+
+        let mut contents = String::new();
+        FollowReader::open(&path, FOLLOW_POLL * 3)
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        writer.join().unwrap();
+        assert_eq!(contents, "first second");
+    }
+}
@@ -0,0 +1,531 @@
+//! Boundary post-processing: splitting long scenes, snapping forced splits
+//! and merging short chunks.
+
+use crate::segments::FrameRange;
+use av_scenechange::ScenecutResult;
+use std::collections::BTreeMap;
+
+/// Split long scenes into smaller chunks at regular intervals.
+///
+/// When a scene is longer than max_frames, we split it evenly to create
+/// chunks that are as close to equal length as possible while staying
+/// under the max_frames limit.
+pub fn split_long_scenes(
+    scene_starts: &[usize],
+    total_frames: usize,
+    max_frames: usize,
+) -> Vec<usize> {
+    let mut result = Vec::new();
+
+    // Build scene ranges
+    for i in 0..scene_starts.len() {
+        let start = scene_starts[i];
+        let end = if i + 1 < scene_starts.len() {
+            scene_starts[i + 1]
+        } else {
+            total_frames
+        };
+
+        result.push(start);
+
+        let scene_len = end.saturating_sub(start);
+        if scene_len > max_frames {
+            // Calculate how many chunks we need
+            let num_chunks = scene_len.div_ceil(max_frames);
+            let chunk_size = scene_len / num_chunks;
+
+            // Add intermediate split points
+            for j in 1..num_chunks {
+                let split = start + j * chunk_size;
+                if split < end {
+                    result.push(split);
+                }
+            }
+        }
+    }
+
+    // Sort and deduplicate
+    result.sort();
+    result.dedup();
+    result
+}
+
+/// Build a per-frame cost estimate from the detector's inter-frame costs.
+///
+/// Frames the detector did not score (the first frame and the lookahead tail)
+/// get the average cost of the scored frames.
+pub fn frame_costs(scores: &BTreeMap<usize, ScenecutResult>, total_frames: usize) -> Vec<f64> {
+    let fallback = if scores.is_empty() {
+        1.0
+    } else {
+        scores.values().map(|s| s.inter_cost).sum::<f64>() / scores.len() as f64
+    };
+
+    (0..total_frames)
+        .map(|frame| scores.get(&frame).map_or(fallback, |s| s.inter_cost))
+        .collect()
+}
+
+/// Split long scenes into chunks of roughly equal estimated encoding work.
+///
+/// Uses the same number of chunks as split_long_scenes, but places each split
+/// where the running cost reaches an equal share of the scene's total cost.
+/// Splits are clamped so that no chunk exceeds max_frames.
+pub fn split_long_scenes_balanced(
+    scene_starts: &[usize],
+    total_frames: usize,
+    max_frames: usize,
+    costs: &[f64],
+) -> Vec<usize> {
+    let mut result = Vec::new();
+    let fallback = if costs.is_empty() {
+        1.0
+    } else {
+        costs.iter().sum::<f64>() / costs.len() as f64
+    };
+    let cost = |frame: usize| costs.get(frame).copied().unwrap_or(fallback).max(0.0);
+
+    for i in 0..scene_starts.len() {
+        let start = scene_starts[i];
+        let end = if i + 1 < scene_starts.len() {
+            scene_starts[i + 1]
+        } else {
+            total_frames
+        };
+
+        result.push(start);
+
+        let scene_len = end.saturating_sub(start);
+        if scene_len <= max_frames {
+            continue;
+        }
+
+        let num_chunks = scene_len.div_ceil(max_frames);
+        let scene_cost: f64 = (start..end).map(cost).sum();
+
+        let mut prev = start;
+        let mut running = 0.0;
+        let mut frame = start;
+        for j in 1..num_chunks {
+            let target = scene_cost * j as f64 / num_chunks as f64;
+            while frame < end && running < target {
+                running += cost(frame);
+                frame += 1;
+            }
+
+            // Keep this chunk and the remaining ones within max_frames
+            let lo = (end - (num_chunks - j) * max_frames).max(prev + 1);
+            let hi = (prev + max_frames).min(end - 1);
+            let split = frame.clamp(lo, hi);
+
+            // Re-sync the running cost if clamping moved the split
+            while frame < split {
+                running += cost(frame);
+                frame += 1;
+            }
+            while frame > split {
+                frame -= 1;
+                running -= cost(frame);
+            }
+
+            result.push(split);
+            prev = split;
+        }
+    }
+
+    result.sort();
+    result.dedup();
+    result
+}
+
+/// Move forced splits onto nearby preferred split points.
+///
+/// A forced split is any boundary that is not a detected scene start. Each
+/// one moves to the nearest preferred point that keeps both of its adjacent
+/// chunks within max_frames, and stays put if there is none.
+pub fn snap_forced_splits(
+    boundaries: &[usize],
+    detected: &[usize],
+    total_frames: usize,
+    max_frames: usize,
+    preferred: &[usize],
+) -> Vec<usize> {
+    snap_forced_splits_by(boundaries, detected, total_frames, max_frames, |_| {
+        preferred.to_vec()
+    })
+}
+
+/// Move forced splits that would cut a range in two to one of its edges.
+///
+/// Uses the same length rule as snap_forced_splits, so a split is left
+/// inside the range if neither edge keeps the adjacent chunks within
+/// max_frames.
+pub fn avoid_ranges(
+    boundaries: &[usize],
+    detected: &[usize],
+    total_frames: usize,
+    max_frames: usize,
+    ranges: &[FrameRange],
+) -> Vec<usize> {
+    snap_forced_splits_by(boundaries, detected, total_frames, max_frames, |split| {
+        ranges
+            .iter()
+            .filter(|r| r.splits_at(split))
+            .flat_map(|r| [r.start, r.end])
+            .collect()
+    })
+}
+
+/// Shared implementation of forced split snapping, with the candidate
+/// points for each split supplied by `candidates`.
+fn snap_forced_splits_by(
+    boundaries: &[usize],
+    detected: &[usize],
+    total_frames: usize,
+    max_frames: usize,
+    candidates: impl Fn(usize) -> Vec<usize>,
+) -> Vec<usize> {
+    let mut result = boundaries.to_vec();
+
+    for i in 1..result.len() {
+        let split = result[i];
+        if detected.binary_search(&split).is_ok() {
+            continue;
+        }
+
+        let prev = result[i - 1];
+        let next = result.get(i + 1).copied().unwrap_or(total_frames);
+        let lo = (prev + 1).max(next.saturating_sub(max_frames));
+        let hi = (next.saturating_sub(1)).min(prev + max_frames);
+
+        if let Some(best) = candidates(split)
+            .into_iter()
+            .filter(|&p| p >= lo && p <= hi)
+            .min_by_key(|&p| p.abs_diff(split))
+        {
+            result[i] = best;
+        }
+    }
+
+    result
+}
+
+/// Merge chunks shorter than min_frames into a neighbouring chunk.
+///
+/// This runs after all other processing, including forced splits. The
+/// shortest chunk is merged first, into its shorter neighbour if it can
+/// be, which keeps merged chunks as close to the max length as possible.
+/// A merge never makes a chunk longer than max_frames or removes a
+/// boundary in `keep` (such as the edges of excluded ranges), so a short
+/// chunk with no allowed merge stays; `short_chunks` finds those.
+pub fn enforce_min_chunk_len(
+    scene_starts: &[usize],
+    total_frames: usize,
+    min_frames: usize,
+    max_frames: usize,
+    keep: &[usize],
+) -> Vec<usize> {
+    let mut result = scene_starts.to_vec();
+    if min_frames == 0 {
+        return result;
+    }
+
+    let chunk_len = |starts: &[usize], i: usize| {
+        let end = starts.get(i + 1).copied().unwrap_or(total_frames);
+        end.saturating_sub(starts[i])
+    };
+    // Chunks, by start frame, that can't be merged either way
+    let mut stuck = Vec::new();
+
+    while result.len() > 1 {
+        // Find the shortest chunk below the minimum
+        let Some(short) = (0..result.len())
+            .filter(|&i| chunk_len(&result, i) < min_frames && !stuck.contains(&result[i]))
+            .min_by_key(|&i| chunk_len(&result, i))
+        else {
+            break;
+        };
+
+        // Merging with the next chunk removes the boundary after `short`,
+        // with the previous one the boundary at its start
+        let len = chunk_len(&result, short);
+        let next =
+            (short + 1 < result.len()).then(|| (short + 1, len + chunk_len(&result, short + 1)));
+        let prev = (short > 0).then(|| (short, len + chunk_len(&result, short - 1)));
+        let allowed = |&(boundary, merged): &(usize, usize)| {
+            merged <= max_frames && !keep.contains(&result[boundary])
+        };
+
+        // Prefer the shorter neighbour
+        let mut options = [next, prev];
+        if options[0].is_some_and(|(_, n)| options[1].is_some_and(|(_, p)| p <= n)) {
+            options.swap(0, 1);
+        }
+        match options.into_iter().flatten().find(allowed) {
+            Some((boundary, _)) => {
+                result.remove(boundary);
+            }
+            None => stuck.push(result[short]),
+        }
+    }
+
+    result
+}
+
+/// Start frames of the chunks shorter than min_frames.
+pub fn short_chunks(scene_starts: &[usize], total_frames: usize, min_frames: usize) -> Vec<usize> {
+    (0..scene_starts.len())
+        .filter(|&i| {
+            let end = scene_starts.get(i + 1).copied().unwrap_or(total_frames);
+            end.saturating_sub(scene_starts[i]) < min_frames
+        })
+        .map(|i| scene_starts[i])
+        .collect()
+}
+
+/// Replace the boundaries of `old` strictly inside `range` with `cuts`,
+/// keeping everything outside it.
+///
+/// A boundary at `range.start` is left as it was: the first frame of the
+/// range has no frame before it to be compared with.
+pub fn splice_range(old: &[usize], cuts: &[usize], range: FrameRange) -> Vec<usize> {
+    let mut result: Vec<usize> = old
+        .iter()
+        .chain(cuts)
+        .copied()
+        .filter(|&frame| !range.splits_at(frame) || cuts.contains(&frame))
+        .filter(|&frame| frame != range.start || old.contains(&frame))
+        .collect();
+    result.sort();
+    result.dedup();
+    result
+}
+
+/// Make each range a scene of its own: boundaries strictly inside a range
+/// are dropped, and its start and end (if before `total_frames`) become
+/// boundaries. Overlapping ranges end up as one scene.
+pub fn isolate_ranges(starts: &[usize], ranges: &[FrameRange], total_frames: usize) -> Vec<usize> {
+    let mut result: Vec<usize> = starts
+        .iter()
+        .copied()
+        .chain(ranges.iter().flat_map(|range| [range.start, range.end]))
+        .filter(|&frame| frame < total_frames)
+        .filter(|&frame| !ranges.iter().any(|range| range.splits_at(frame)))
+        .collect();
+    result.sort();
+    result.dedup();
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_long_scenes_no_split_needed() {
+        let scenes = vec![0, 100, 200];
+        let result = split_long_scenes(&scenes, 300, 150);
+        assert_eq!(result, vec![0, 100, 200]);
+    }
+
+    #[test]
+    fn test_split_long_scenes_single_split() {
+        let scenes = vec![0];
+        let result = split_long_scenes(&scenes, 400, 250);
+        // 400 frames, max 250 -> needs 2 chunks of 200 each
+        assert_eq!(result, vec![0, 200]);
+    }
+
+    #[test]
+    fn test_split_long_scenes_multiple_splits() {
+        let scenes = vec![0];
+        let result = split_long_scenes(&scenes, 1000, 300);
+        // 1000 frames, max 300 -> needs 4 chunks of 250 each
+        assert_eq!(result, vec![0, 250, 500, 750]);
+    }
+
+    #[test]
+    fn test_split_long_scenes_mixed() {
+        let scenes = vec![0, 100, 600];
+        let result = split_long_scenes(&scenes, 900, 200);
+        // Scene 0-100: 100 frames, no split
+        // Scene 100-600: 500 frames, needs 3 chunks of 166 each
+        // Scene 600-900: 300 frames, needs 2 chunks of 150 each
+        assert_eq!(result, vec![0, 100, 266, 432, 600, 750]);
+    }
+
+    #[test]
+    fn test_enforce_min_chunk_len_disabled() {
+        let scenes = vec![0, 5, 10];
+        let result = enforce_min_chunk_len(&scenes, 100, 0, usize::MAX, &[]);
+        assert_eq!(result, vec![0, 5, 10]);
+    }
+
+    #[test]
+    fn test_enforce_min_chunk_len_merges_into_shorter_neighbour() {
+        let scenes = vec![0, 100, 110, 150];
+        let result = enforce_min_chunk_len(&scenes, 300, 24, usize::MAX, &[]);
+        // 100-110 is 10 frames; 110-150 (40) is shorter than 0-100 (100)
+        assert_eq!(result, vec![0, 100, 150]);
+    }
+
+    #[test]
+    fn test_enforce_min_chunk_len_first_and_last() {
+        let scenes = vec![0, 5, 100, 195];
+        let result = enforce_min_chunk_len(&scenes, 200, 24, usize::MAX, &[]);
+        // First chunk can only merge forward, last chunk only backward
+        assert_eq!(result, vec![0, 100]);
+    }
+
+    #[test]
+    fn test_enforce_min_chunk_len_short_video() {
+        let scenes = vec![0, 10];
+        let result = enforce_min_chunk_len(&scenes, 20, 24, usize::MAX, &[]);
+        assert_eq!(result, vec![0]);
+    }
+
+    #[test]
+    fn test_enforce_min_chunk_len_respects_max() {
+        // Forced splits of a 300-frame scene at a 100-frame limit, then a
+        // cut 10 frames before the end
+        let scenes = vec![0, 100, 200, 290];
+        let result = enforce_min_chunk_len(&scenes, 300, 24, 100, &[]);
+        // 200-290 plus the last 10 frames would be 100: allowed
+        assert_eq!(result, vec![0, 100, 200]);
+
+        let scenes = vec![0, 100, 198, 203];
+        let result = enforce_min_chunk_len(&scenes, 303, 24, 100, &[]);
+        // 198-203 fits neither 100-198 (98 + 5) nor 203-303 (100 + 5)
+        assert_eq!(result, scenes);
+        assert_eq!(short_chunks(&result, 303, 24), vec![198]);
+    }
+
+    #[test]
+    fn test_enforce_min_chunk_len_keeps_exclude_edges() {
+        let ranges = [FrameRange {
+            start: 100,
+            end: 110,
+        }];
+        let scenes = isolate_ranges(&[0, 50], &ranges, 300);
+        assert_eq!(scenes, vec![0, 50, 100, 110]);
+        let edges: Vec<usize> = ranges.iter().flat_map(|r| [r.start, r.end]).collect();
+        // The 10-frame excluded range stays a chunk of its own
+        let result = enforce_min_chunk_len(&scenes, 300, 24, usize::MAX, &edges);
+        assert_eq!(result, vec![0, 50, 100, 110]);
+        // Without the edges kept, it is merged away
+        let result = enforce_min_chunk_len(&scenes, 300, 24, usize::MAX, &[]);
+        assert_eq!(result, vec![0, 50, 110]);
+    }
+
+    #[test]
+    fn test_split_long_scenes_balanced_uniform_cost() {
+        let scenes = vec![0];
+        let costs = vec![1.0; 1000];
+        let result = split_long_scenes_balanced(&scenes, 1000, 300, &costs);
+        // Uniform cost gives the same splits as equal frame counts
+        assert_eq!(result, vec![0, 250, 500, 750]);
+    }
+
+    #[test]
+    fn test_split_long_scenes_balanced_skewed_cost() {
+        let scenes = vec![0];
+        // First half is three times as expensive as the second half
+        let mut costs = vec![3.0; 200];
+        costs.extend(vec![1.0; 200]);
+        let result = split_long_scenes_balanced(&scenes, 400, 250, &costs);
+        // Total cost 800, so the split lands where the running cost hits 400
+        assert_eq!(result, vec![0, 150]);
+    }
+
+    #[test]
+    fn test_split_long_scenes_balanced_respects_max() {
+        let scenes = vec![0, 500];
+        // All the cost is in the first frame
+        let mut costs = vec![0.0; 500];
+        costs[0] = 100.0;
+        let result = split_long_scenes_balanced(&scenes, 500, 200, &costs);
+        // Chunks must still fit in 200 frames
+        assert_eq!(result, vec![0, 100, 300, 500]);
+    }
+
+    #[test]
+    fn test_frame_costs_fills_unscored_frames() {
+        let mut scores = BTreeMap::new();
+        for (frame, inter_cost) in [(1, 2.0), (2, 4.0)] {
+            scores.insert(
+                frame,
+                ScenecutResult {
+                    inter_cost,
+                    imp_block_cost: 0.0,
+                    backward_adjusted_cost: 0.0,
+                    forward_adjusted_cost: 0.0,
+                    threshold: 0.0,
+                },
+            );
+        }
+        assert_eq!(frame_costs(&scores, 4), vec![3.0, 2.0, 4.0, 3.0]);
+    }
+
+    #[test]
+    fn test_snap_forced_splits() {
+        // 0 is detected, 250 is forced; preferred point at 230 fits
+        let result = snap_forced_splits(&[0, 250], &[0], 500, 300, &[230, 400]);
+        assert_eq!(result, vec![0, 230]);
+    }
+
+    #[test]
+    fn test_snap_forced_splits_keeps_detected_and_max() {
+        // 100 is detected and must not move; 400 would make 100-400 too long
+        let result = snap_forced_splits(&[0, 100, 250], &[0, 100], 500, 250, &[90, 400]);
+        assert_eq!(result, vec![0, 100, 250]);
+    }
+
+    #[test]
+    fn test_avoid_ranges() {
+        let runs = [FrameRange {
+            start: 240,
+            end: 280,
+        }];
+        // Forced split at 250 moves to the nearest edge of the freeze run
+        let result = avoid_ranges(&[0, 250], &[0], 500, 300, &runs);
+        assert_eq!(result, vec![0, 240]);
+
+        // Split outside the run is untouched
+        let result = avoid_ranges(&[0, 200], &[0], 400, 300, &runs);
+        assert_eq!(result, vec![0, 200]);
+    }
+
+    #[test]
+    fn test_splice_range() {
+        let range = FrameRange {
+            start: 100,
+            end: 300,
+        };
+        // Old boundaries at 150 and 200 are replaced; the detector's own
+        // cut at the range start is dropped
+        assert_eq!(
+            splice_range(&[0, 150, 200, 300, 400], &[100, 120, 250], range),
+            vec![0, 120, 250, 300, 400]
+        );
+        assert_eq!(splice_range(&[0, 100, 200], &[100], range), vec![0, 100]);
+    }
+
+    #[test]
+    fn test_isolate_ranges() {
+        let ranges = [
+            FrameRange { start: 0, end: 50 },
+            FrameRange {
+                start: 200,
+                end: 260,
+            },
+            FrameRange {
+                start: 250,
+                end: 300,
+            },
+        ];
+        assert_eq!(
+            isolate_ranges(&[0, 30, 100, 220, 280], &ranges, 280),
+            vec![0, 50, 100, 200]
+        );
+    }
+}
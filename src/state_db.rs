@@ -0,0 +1,190 @@
+//! `--state-db`: a record of completed runs, so re-running an interrupted
+//! batch with `--skip-done` only processes the files that haven't yet
+//! completed with the same settings.
+//!
+//! The database is a JSON lines file with one record per completed run,
+//! appended once all of its outputs are written. Each append holds an
+//! exclusive lock on the file, so batch runs in parallel can share one
+//! database, and a run killed mid-append leaves at most one partial line,
+//! which is ignored.
+
+use crate::Args;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use shear::checksum;
+use std::fs::{self, OpenOptions};
+use std::io::{self, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::{self, Path, PathBuf};
+
+/// One completed run.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    pub input: PathBuf,
+    /// Sampled checksum of the input, as for `--checksum`
+    pub fingerprint: String,
+    /// Checksum of the shear version and every option that affects what
+    /// is written
+    pub options: String,
+    pub output: PathBuf,
+}
+
+impl Record {
+    /// The record a run with `args` leaves once it completes.
+    pub fn new(args: &Args) -> Result<Self> {
+        Ok(Record {
+            input: path::absolute(&args.input)?,
+            fingerprint: checksum::input_checksum(&args.input)?,
+            options: options_checksum(args),
+            output: path::absolute(&args.output)?,
+        })
+    }
+}
+
+/// Checksum of the options, leaving out the ones that only change what is
+/// reported along the way or how many commands run at once.
+fn options_checksum(args: &Args) -> String {
+    let mut args = args.clone();
+    args.exec_jobs = args.exec_jobs.map(|_| 1);
+    args.progress = false;
+    args.progress_fd = None;
+    args.timings = false;
+    args.summary = false;
+    args.state_db = None;
+    args.skip_done = false;
+    let options = format!("{} {:?}", env!("CARGO_PKG_VERSION"), args);
+    checksum::bytes_checksum(options.as_bytes())
+}
+
+/// True if `db` holds `record`, and `record.output` still exists when
+/// `check_output` is set.
+pub fn is_done(db: &Path, record: &Record, check_output: bool) -> Result<bool> {
+    let contents = match fs::read_to_string(db) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read state database {:?}", db)),
+    };
+    let done = contents
+        .lines()
+        .filter_map(|line| serde_json::from_str::<Record>(line).ok())
+        .any(|done| done == *record);
+    Ok(done && (!check_output || record.output.exists()))
+}
+
+/// Append `record` to `db`, creating it if needed.
+pub fn mark_done(db: &Path, record: &Record) -> Result<()> {
+    let mut line = serde_json::to_string(record)?;
+    line.push('\n');
+    append(db, &line).with_context(|| format!("Failed to update state database {:?}", db))
+}
+
+fn append(db: &Path, line: &str) -> io::Result<()> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .read(true)
+        .append(true)
+        .open(db)?;
+    // Held until the file is closed, so two runs can't both see the same
+    // partial line and both finish it off
+    file.lock()?;
+    // Finish off a partial line, so it doesn't swallow this record too
+    let mut last = [b'\n'];
+    if file.metadata()?.len() > 0 {
+        file.seek(SeekFrom::End(-1))?;
+        file.read_exact(&mut last)?;
+    }
+    let line = if last[0] == b'\n' {
+        line.to_string()
+    } else {
+        format!("\n{}", line)
+    };
+    file.write_all(line.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempPath;
+    use crate::Cli;
+    use clap::Parser;
+
+    fn args(input: &Path, output: &Path, extra: &[&str]) -> Args {
+        let argv = [
+            "shear",
+            "-i",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "--fps-num",
+            "24",
+            "--fps-den",
+            "1",
+            "--total-frames",
+            "0",
+        ];
+        let cli = Cli::try_parse_from(argv.iter().chain(extra)).unwrap();
+        cli.args.unwrap()
+    }
+
+    #[test]
+    fn test_options_checksum() {
+        let (input, output) = (Path::new("in.y4m"), Path::new("scenes.txt"));
+        let plain = options_checksum(&args(input, output, &[]));
+        // Reporting options don't count; detection options do
+        let reporting = args(
+            input,
+            output,
+            &["--progress", "--summary", "--state-db", "db", "--skip-done"],
+        );
+        assert_eq!(options_checksum(&reporting), plain);
+        // Nor does how many commands run at once, only whether they run
+        let exec = |jobs: &str| {
+            let extra = ["--exec-template", "true", "--exec-jobs", jobs];
+            options_checksum(&args(input, output, &extra))
+        };
+        assert_eq!(exec("1"), exec("8"));
+        let printed = args(input, output, &["--exec-template", "true"]);
+        assert_ne!(options_checksum(&printed), exec("1"));
+        let detection = args(input, output, &["--max-scene-secs", "5"]);
+        assert_ne!(options_checksum(&detection), plain);
+    }
+
+    #[test]
+    fn test_skip_done() {
+        let input = TempPath::new("state-db-in.y4m");
+        let output = TempPath::new("state-db-out.txt");
+        let db = TempPath::new("state-db");
+        fs::write(&input, b"source video").unwrap();
+        let record = Record::new(&args(&input, &output, &[])).unwrap();
+        assert!(!is_done(&db, &record, true).unwrap());
+
+        mark_done(&db, &record).unwrap();
+        // Not done while the output is missing
+        assert!(!is_done(&db, &record, true).unwrap());
+        assert!(is_done(&db, &record, false).unwrap());
+        fs::write(&output, b"0\n").unwrap();
+        assert!(is_done(&db, &record, true).unwrap());
+
+        // A partial line from an interrupted append is skipped
+        OpenOptions::new()
+            .append(true)
+            .open(&db)
+            .unwrap()
+            .write_all(b"{\"input\":")
+            .unwrap();
+        assert!(is_done(&db, &record, true).unwrap());
+        let other_output = TempPath::new("state-db-other.txt");
+        let other = Record {
+            output: other_output.to_path_buf(),
+            ..record.clone()
+        };
+        mark_done(&db, &other).unwrap();
+        assert!(is_done(&db, &other, false).unwrap());
+
+        // A changed input or different options need another run
+        let rerun = Record::new(&args(&input, &output, &["--min-chunk-secs", "2"])).unwrap();
+        assert!(!is_done(&db, &rerun, true).unwrap());
+        fs::write(&input, b"other video").unwrap();
+        let changed = Record::new(&args(&input, &output, &[])).unwrap();
+        assert!(!is_done(&db, &changed, true).unwrap());
+    }
+}
@@ -0,0 +1,161 @@
+//! End-of-run summary table for `--summary`.
+
+use shear::timecode;
+use std::io::IsTerminal;
+use std::path::Path;
+
+const BOLD: &str = "\x1b[1m";
+const DIM: &str = "\x1b[2m";
+const YELLOW: &str = "\x1b[33m";
+const RESET: &str = "\x1b[0m";
+
+/// What a run produced.
+#[derive(Debug, Clone)]
+pub struct Summary<'a> {
+    /// Detected scene starts, including frame 0. Boundaries added for
+    /// black segments or excluded ranges don't count.
+    pub scenes: usize,
+    /// Boundaries added by splitting long scenes
+    pub forced_splits: usize,
+    /// Chunk lengths in frames
+    pub chunk_lens: Vec<usize>,
+    pub total_frames: usize,
+    pub fps_num: u32,
+    pub fps_den: u32,
+    pub output: &'a Path,
+}
+
+impl<'a> Summary<'a> {
+    pub fn new(
+        scenes: usize,
+        scene_starts: &[usize],
+        final_scenes: &[usize],
+        total_frames: usize,
+        fps_num: u32,
+        fps_den: u32,
+        output: &'a Path,
+    ) -> Self {
+        let forced_splits = final_scenes
+            .iter()
+            .filter(|start| !scene_starts.contains(start))
+            .count();
+        let chunk_lens = final_scenes
+            .iter()
+            .enumerate()
+            .map(|(i, &start)| final_scenes.get(i + 1).unwrap_or(&total_frames) - start)
+            .collect();
+        Summary {
+            scenes,
+            forced_splits,
+            chunk_lens,
+            total_frames,
+            fps_num,
+            fps_den,
+            output,
+        }
+    }
+
+    /// Chunk length as frames and seconds
+    fn length(&self, frames: f64) -> String {
+        let secs = frames * self.fps_den as f64 / self.fps_num.max(1) as f64;
+        format!("{:.0} frames ({:.3}s)", frames, secs)
+    }
+
+    /// The table, with ANSI colors when `color` is set.
+    pub fn render(&self, color: bool) -> String {
+        let paint = |style: &str, text: &str| {
+            if color {
+                format!("{}{}{}", style, text, RESET)
+            } else {
+                text.to_string()
+            }
+        };
+
+        let shortest = self.chunk_lens.iter().min().copied().unwrap_or(0);
+        let longest = self.chunk_lens.iter().max().copied().unwrap_or(0);
+        let average =
+            self.chunk_lens.iter().sum::<usize>() as f64 / self.chunk_lens.len().max(1) as f64;
+        let forced = self.forced_splits.to_string();
+        let rows = [
+            ("Scenes found", self.scenes.to_string()),
+            (
+                "Forced splits",
+                if self.forced_splits > 0 {
+                    paint(YELLOW, &forced)
+                } else {
+                    forced
+                },
+            ),
+            ("Chunks", self.chunk_lens.len().to_string()),
+            ("Shortest chunk", self.length(shortest as f64)),
+            ("Longest chunk", self.length(longest as f64)),
+            ("Average chunk", self.length(average)),
+            (
+                "Duration",
+                format!(
+                    "{} ({} frames)",
+                    timecode::format_millis(self.total_frames, self.fps_num, self.fps_den),
+                    self.total_frames
+                ),
+            ),
+            ("Output", self.output.display().to_string()),
+        ];
+
+        let mut text = paint(BOLD, "Summary");
+        for (label, value) in rows {
+            text.push('\n');
+            text.push_str(&paint(DIM, &format!("  {:<16}", label)));
+            text.push_str(&value);
+        }
+        text
+    }
+}
+
+/// Colors are used when stderr is a terminal and `NO_COLOR` is unset.
+pub fn use_color() -> bool {
+    std::io::stderr().is_terminal() && std::env::var_os("NO_COLOR").is_none()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary() {
+        let summary = Summary::new(
+            2,
+            &[0, 100],
+            &[0, 50, 100],
+            250,
+            25,
+            1,
+            Path::new("scenes.txt"),
+        );
+        assert_eq!(summary.forced_splits, 1);
+        assert_eq!(summary.chunk_lens, vec![50, 50, 150]);
+
+        let plain = summary.render(false);
+        assert!(plain.starts_with("Summary\n  Scenes found    2\n"));
+        assert!(plain.contains("  Shortest chunk  50 frames (2.000s)\n"));
+        assert!(plain.contains("  Average chunk   83 frames (3.333s)\n"));
+        assert!(plain.contains("  Duration        00:00:10.000 (250 frames)\n"));
+        assert!(plain.ends_with("  Output          scenes.txt"));
+        assert!(!plain.contains('\x1b'));
+
+        let colored = summary.render(true);
+        assert!(colored.contains("\x1b[33m1\x1b[0m"));
+
+        // A black midpoint at 60 is a boundary but neither a found scene
+        // nor a forced split
+        let summary = Summary::new(
+            2,
+            &[0, 60, 100],
+            &[0, 60, 100],
+            250,
+            25,
+            1,
+            Path::new("scenes.txt"),
+        );
+        assert_eq!((summary.scenes, summary.forced_splits), (2, 0));
+    }
+}
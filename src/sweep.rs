@@ -0,0 +1,164 @@
+//! Threshold sweep: how many scenes a range of thresholds would produce,
+//! all derived from the scores of a single detection pass.
+
+use crate::detect;
+use av_scenechange::ScenecutResult;
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+/// Most thresholds one sweep may evaluate
+const MAX_THRESHOLDS: usize = 10_000;
+
+/// Thresholds from `start` to `end` inclusive, `step` apart.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SweepRange {
+    pub start: f64,
+    pub end: f64,
+    pub step: f64,
+}
+
+impl FromStr for SweepRange {
+    type Err = String;
+
+    /// Parse `start:end:step`, e.g. `20:40:5`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.split(':').collect();
+        let [start, end, step] = parts.as_slice() else {
+            return Err("expected start:end:step, e.g. 20:40:5".to_string());
+        };
+        let parse = |v: &str| {
+            v.trim()
+                .parse::<f64>()
+                .map_err(|_| format!("invalid threshold {:?}", v))
+        };
+        let range = SweepRange {
+            start: parse(start)?,
+            end: parse(end)?,
+            step: parse(step)?,
+        };
+        if ![range.start, range.end, range.step]
+            .iter()
+            .all(|v| v.is_finite())
+        {
+            return Err("start, end and step must be finite numbers".to_string());
+        }
+        if range.step <= 0.0 {
+            return Err("step must be greater than zero".to_string());
+        }
+        if range.start > range.end {
+            return Err("start must not be greater than end".to_string());
+        }
+        if (range.end - range.start) / range.step >= MAX_THRESHOLDS as f64 {
+            return Err(format!(
+                "at most {} thresholds can be swept at once",
+                MAX_THRESHOLDS
+            ));
+        }
+        Ok(range)
+    }
+}
+
+impl SweepRange {
+    pub fn thresholds(&self) -> Vec<f64> {
+        let count = ((self.end - self.start) / self.step + 1e-9).floor() as usize + 1;
+        (0..count)
+            .map(|i| self.start + i as f64 * self.step)
+            .collect()
+    }
+}
+
+/// Detection outcome at one threshold.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SweepRow {
+    pub threshold: f64,
+    /// Detected scenes, before any splitting of long scenes
+    pub scenes: usize,
+    pub avg_scene_frames: f64,
+}
+
+pub fn sweep(
+    scores: &BTreeMap<usize, ScenecutResult>,
+    total_frames: usize,
+    range: &SweepRange,
+) -> Vec<SweepRow> {
+    range
+        .thresholds()
+        .into_iter()
+        .map(|threshold| {
+            let cuts = detect::cuts_at_threshold(scores, threshold);
+            // Frame 0 always starts a scene
+            let scenes = 1 + cuts.iter().filter(|&&frame| frame > 0).count();
+            SweepRow {
+                threshold,
+                scenes,
+                avg_scene_frames: total_frames as f64 / scenes as f64,
+            }
+        })
+        .collect()
+}
+
+/// Print the sweep as a table on stdout.
+pub fn print_sweep(rows: &[SweepRow], fps: f64) {
+    println!(
+        "{:>9}  {:>6}  {:>10}  {:>8}",
+        "threshold", "scenes", "avg frames", "avg secs"
+    );
+    for row in rows {
+        let marker = if (row.threshold - detect::DEFAULT_THRESHOLD).abs() < 1e-9 {
+            "  (default)"
+        } else {
+            ""
+        };
+        println!(
+            "{:>9}  {:>6}  {:>10.1}  {:>8.2}{}",
+            row.threshold,
+            row.scenes,
+            row.avg_scene_frames,
+            row.avg_scene_frames / fps,
+            marker
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn score(strength: f64) -> ScenecutResult {
+        ScenecutResult {
+            inter_cost: 0.0,
+            imp_block_cost: 0.0,
+            backward_adjusted_cost: 0.0,
+            forward_adjusted_cost: strength * 10.0,
+            threshold: 10.0,
+        }
+    }
+
+    #[test]
+    fn test_parse_sweep_range() {
+        let range: SweepRange = "20:40:5".parse().unwrap();
+        assert_eq!(range.thresholds(), vec![20.0, 25.0, 30.0, 35.0, 40.0]);
+
+        assert!("20:40".parse::<SweepRange>().is_err());
+        assert!("20:40:0".parse::<SweepRange>().is_err());
+        assert!("40:20:5".parse::<SweepRange>().is_err());
+        assert!("0:inf:1".parse::<SweepRange>().is_err());
+        assert!("NaN:40:5".parse::<SweepRange>().is_err());
+        assert!("0:40:NaN".parse::<SweepRange>().is_err());
+        assert!("0:1e15:1".parse::<SweepRange>().is_err());
+        let widest: SweepRange = "0:9999:1".parse().unwrap();
+        assert_eq!(widest.thresholds().len(), 10_000);
+    }
+
+    #[test]
+    fn test_sweep_counts() {
+        // Strength 1.0 is a cut at the default threshold of 30
+        let scores = BTreeMap::from([(10, score(0.5)), (20, score(1.0)), (30, score(2.0))]);
+        let range: SweepRange = "15:75:15".parse().unwrap();
+        let rows = sweep(&scores, 40, &range);
+
+        let scenes: Vec<usize> = rows.iter().map(|r| r.scenes).collect();
+        assert_eq!(scenes, vec![4, 3, 2, 2, 1]);
+        assert!((rows[1].avg_scene_frames - 40.0 / 3.0).abs() < 1e-9);
+    }
+}
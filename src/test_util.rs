@@ -0,0 +1,41 @@
+//! Helpers shared by the unit tests of the library and the binary, which
+//! both declare this module.
+
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+
+/// A path in the temp directory, unique to this process and `name`, that is
+/// removed on drop, file or directory, so a failed test leaves nothing
+/// behind.
+pub struct TempPath(PathBuf);
+
+impl TempPath {
+    pub fn new(name: &str) -> Self {
+        Self(std::env::temp_dir().join(format!("shear-{}-{}", std::process::id(), name)))
+    }
+}
+
+impl Deref for TempPath {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for TempPath {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempPath {
+    fn drop(&mut self) {
+        // Not every test creates every path it names
+        if self.0.is_dir() {
+            let _ = std::fs::remove_dir_all(&self.0);
+        } else {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+}
@@ -0,0 +1,104 @@
+//! Conversion between frame numbers and timestamps.
+
+/// Nanoseconds from the start of the video to the start of `frame`.
+pub fn frame_to_nanos(frame: usize, fps_num: u32, fps_den: u32) -> u128 {
+    let num = fps_num.max(1) as u128;
+    (frame as u128 * fps_den as u128 * 1_000_000_000 + num / 2) / num
+}
+
+/// Format `frame`'s start time as `HH:MM:SS.mmm`.
+pub fn format_millis(frame: usize, fps_num: u32, fps_den: u32) -> String {
+    let millis = (frame_to_nanos(frame, fps_num, fps_den) + 500_000) / 1_000_000;
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        millis % 1000
+    )
+}
+
+/// Format `frame`'s start time as `HH:MM:SS.nnnnnnnnn`.
+pub fn format_nanos(frame: usize, fps_num: u32, fps_den: u32) -> String {
+    let nanos = frame_to_nanos(frame, fps_num, fps_den);
+    format!(
+        "{:02}:{:02}:{:02}.{:09}",
+        nanos / 3_600_000_000_000,
+        nanos / 60_000_000_000 % 60,
+        nanos / 1_000_000_000 % 60,
+        nanos % 1_000_000_000
+    )
+}
+
+/// Format `frame`'s start time as plain seconds with microsecond
+/// precision, rounded down so a seek never lands on the frame before.
+pub fn format_seconds(frame: usize, fps_num: u32, fps_den: u32) -> String {
+    let micros = frame as u128 * fps_den as u128 * 1_000_000 / fps_num.max(1) as u128;
+    format!("{}.{:06}", micros / 1_000_000, micros % 1_000_000)
+}
+
+/// Parse a timestamp given as `SS[.fff]`, `MM:SS[.fff]` or
+/// `HH:MM:SS[.fff]` into seconds.
+pub fn parse_seconds(s: &str) -> Option<f64> {
+    let mut seconds = 0.0;
+    let fields: Vec<&str> = s.trim().split(':').collect();
+    if fields.len() > 3 {
+        return None;
+    }
+    for (i, field) in fields.iter().enumerate() {
+        let last = i == fields.len() - 1;
+        let value: f64 = if last {
+            field.parse().ok()?
+        } else {
+            field.parse::<u32>().ok()? as f64
+        };
+        if !value.is_finite() || value < 0.0 {
+            return None;
+        }
+        seconds = seconds * 60.0 + value;
+    }
+    Some(seconds)
+}
+
+/// The frame displayed at `seconds`, rounded to the nearest frame start.
+pub fn seconds_to_frame(seconds: f64, fps_num: u32, fps_den: u32) -> usize {
+    (seconds * fps_num as f64 / fps_den.max(1) as f64).round() as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_millis() {
+        assert_eq!(format_millis(0, 24000, 1001), "00:00:00.000");
+        // 1439 frames at 23.976 fps = 60.018 seconds
+        assert_eq!(format_millis(1439, 24000, 1001), "00:01:00.018");
+        assert_eq!(format_millis(90000 * 25, 25, 1), "25:00:00.000");
+    }
+
+    #[test]
+    fn test_format_nanos() {
+        assert_eq!(format_nanos(1, 24000, 1001), "00:00:00.041708333");
+        assert_eq!(format_nanos(30, 30, 1), "00:00:01.000000000");
+    }
+
+    #[test]
+    fn test_format_seconds() {
+        assert_eq!(format_seconds(0, 24000, 1001), "0.000000");
+        assert_eq!(format_seconds(1439, 24000, 1001), "60.018291");
+        // 998000.9995 microseconds: rounding to the nearest nanosecond
+        // first would reach the next microsecond
+        assert_eq!(format_seconds(1997, 2001, 1), "0.998000");
+    }
+
+    #[test]
+    fn test_parse_seconds() {
+        assert_eq!(parse_seconds("12.5"), Some(12.5));
+        assert_eq!(parse_seconds("01:30"), Some(90.0));
+        assert_eq!(parse_seconds("01:00:00.018"), Some(3600.018));
+        assert_eq!(parse_seconds("1:2:3:4"), None);
+        assert_eq!(parse_seconds("-1"), None);
+        assert_eq!(seconds_to_frame(60.018, 24000, 1001), 1439);
+    }
+}
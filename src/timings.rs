@@ -0,0 +1,98 @@
+//! Wall time breakdown for `--timings`.
+
+use std::fmt;
+use std::time::Duration;
+
+/// Time spent in each phase of one run.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Timings {
+    /// Opening the input and reading its stream parameters
+    pub probe: Duration,
+    /// Decoding and pre-filtering frames
+    pub decode: Duration,
+    /// Analyzing frames, in parallel with decoding
+    pub detection: Duration,
+    /// Wall time of the decode and detection pass
+    pub pass: Duration,
+    /// Splitting, snapping and statistics after the pass
+    pub post_processing: Duration,
+    /// Writing the scene file and other outputs
+    pub output: Duration,
+    /// Frames decoded
+    pub frames: usize,
+}
+
+impl Timings {
+    pub fn total(&self) -> Duration {
+        self.probe + self.pass + self.post_processing + self.output
+    }
+
+    /// Decoded frames per second of decode time
+    pub fn decode_fps(&self) -> f64 {
+        let secs = self.decode.as_secs_f64();
+        if secs > 0.0 {
+            self.frames as f64 / secs
+        } else {
+            0.0
+        }
+    }
+
+    /// Which side of the pipelined pass limits its speed
+    fn bottleneck(&self) -> &'static str {
+        if self.decode >= self.detection {
+            "decoding (faster decoding, such as hardware decode, would help)"
+        } else {
+            "detection (faster decoding wouldn't help; analyzing fewer pixels would)"
+        }
+    }
+}
+
+impl fmt::Display for Timings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let secs = |d: Duration| format!("{:>9.3}s", d.as_secs_f64());
+        writeln!(f, "Timings:")?;
+        writeln!(f, "  probe           {}", secs(self.probe))?;
+        writeln!(
+            f,
+            "  decode          {} ({:.1} fps)",
+            secs(self.decode),
+            self.decode_fps()
+        )?;
+        writeln!(f, "  detection       {}", secs(self.detection))?;
+        writeln!(f, "  post-processing {}", secs(self.post_processing))?;
+        writeln!(f, "  output          {}", secs(self.output))?;
+        writeln!(f, "  total           {}", secs(self.total()))?;
+        write!(
+            f,
+            "Decode and detection run in parallel ({:.3}s together); the pass is limited by {}",
+            self.pass.as_secs_f64(),
+            self.bottleneck()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timings() {
+        let timings = Timings {
+            probe: Duration::from_millis(100),
+            decode: Duration::from_secs(2),
+            detection: Duration::from_secs(3),
+            pass: Duration::from_secs(3),
+            post_processing: Duration::from_millis(50),
+            output: Duration::from_millis(50),
+            frames: 480,
+        };
+        assert_eq!(timings.total(), Duration::from_millis(3200));
+        assert_eq!(timings.decode_fps(), 240.0);
+
+        let text = timings.to_string();
+        assert!(text.contains("  decode              2.000s (240.0 fps)"));
+        assert!(text.ends_with(
+            "limited by detection (faster decoding wouldn't help; analyzing fewer pixels would)"
+        ));
+    }
+}
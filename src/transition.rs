@@ -0,0 +1,163 @@
+//! Transition type of each boundary, from per-frame statistics.
+//!
+//! A hard cut changes the picture in one frame, so the luma difference at
+//! the boundary stands far above its neighbours'. Dissolves spread the
+//! change over many frames with no dominant spike, and fades run the
+//! picture down to, or up from, black.
+
+use crate::detect::FrameStats;
+use serde::Serialize;
+
+/// Mean luma (8-bit units) at or below which a frame counts as black;
+/// limited-range black is 16
+const NEAR_BLACK_LUMA: f64 = 24.0;
+
+/// Frames a fade is looked for over, on its side of the boundary
+const FADE_WINDOW: usize = 8;
+
+/// Mean luma change across `FADE_WINDOW` frames needed for a fade
+const FADE_MIN_CHANGE: f64 = 20.0;
+
+/// Frames each side of the boundary compared with its luma difference
+const DISSOLVE_WINDOW: usize = 4;
+
+/// How many times the neighbours' mean luma difference a hard cut's must be
+const HARD_CUT_RATIO: f64 = 3.0;
+
+/// How one chunk follows the previous one.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Transition {
+    HardCut,
+    FadeIn,
+    FadeOut,
+    Dissolve,
+    /// Added by splitting a long scene, not a change in the picture
+    ForcedSplit,
+}
+
+/// Classify the detected scene change at `boundary`.
+pub fn classify(stats: &[FrameStats], boundary: usize) -> Transition {
+    let luma = |frame: usize| stats.get(frame).map(|s| s.mean_luma);
+    let dark = |frame: usize| luma(frame).is_some_and(|l| l <= NEAR_BLACK_LUMA);
+    let darkens = |from: usize, to: usize| {
+        luma(from)
+            .zip(luma(to))
+            .is_some_and(|(from, to)| from - to >= FADE_MIN_CHANGE)
+    };
+
+    // Picture runs down to black just before the boundary
+    if boundary > 0
+        && dark(boundary - 1)
+        && darkens(boundary.saturating_sub(FADE_WINDOW), boundary - 1)
+    {
+        return Transition::FadeOut;
+    }
+    // New scene rises out of black
+    if dark(boundary) && darkens(boundary + FADE_WINDOW - 1, boundary) {
+        return Transition::FadeIn;
+    }
+
+    // Frame 0 and excluded frames have no difference to compare
+    let diff = |frame: usize| {
+        stats
+            .get(frame)
+            .map(|s| s.luma_diff)
+            .filter(|&d| d < f64::MAX)
+    };
+    let Some(peak) = diff(boundary) else {
+        return Transition::HardCut;
+    };
+    let neighbours: Vec<f64> = (boundary.saturating_sub(DISSOLVE_WINDOW)
+        ..=boundary + DISSOLVE_WINDOW)
+        .filter(|&frame| frame != boundary)
+        .filter_map(diff)
+        .collect();
+    let average = neighbours.iter().sum::<f64>() / neighbours.len().max(1) as f64;
+    if average > 0.0 && peak < HARD_CUT_RATIO * average {
+        Transition::Dissolve
+    } else {
+        Transition::HardCut
+    }
+}
+
+/// Transition into each chunk of `final_scenes`: None for the first,
+/// `ForcedSplit` for boundaries that aren't in `scene_starts`, and
+/// `classify` for the rest.
+pub fn transitions(
+    final_scenes: &[usize],
+    scene_starts: &[usize],
+    stats: &[FrameStats],
+) -> Vec<Option<Transition>> {
+    final_scenes
+        .iter()
+        .map(|&start| {
+            if start == 0 {
+                None
+            } else if !scene_starts.contains(&start) {
+                Some(Transition::ForcedSplit)
+            } else {
+                Some(classify(stats, start))
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats(lumas: &[f64]) -> Vec<FrameStats> {
+        lumas
+            .iter()
+            .enumerate()
+            .map(|(i, &mean_luma)| FrameStats {
+                luma_diff: if i == 0 {
+                    f64::MAX
+                } else {
+                    (mean_luma - lumas[i - 1]).abs()
+                },
+                mean_luma,
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_classify() {
+        // Steady picture with a jump at 10
+        let mut lumas = vec![100.0; 10];
+        lumas.extend([180.0; 10]);
+        assert_eq!(classify(&stats(&lumas), 10), Transition::HardCut);
+
+        // Even steps from 100 to 180 over frames 5..15
+        let lumas: Vec<f64> = (0..20)
+            .map(|i| 100.0 + 8.0 * (i as f64 - 5.0).clamp(0.0, 10.0))
+            .collect();
+        assert_eq!(classify(&stats(&lumas), 10), Transition::Dissolve);
+
+        // Down to black, then a cut to a bright scene
+        let mut lumas: Vec<f64> = (0..10).map(|i| 120.0 - 12.0 * i as f64).collect();
+        lumas.extend([150.0; 10]);
+        assert_eq!(classify(&stats(&lumas), 10), Transition::FadeOut);
+
+        // A bright scene, then one rising from black
+        let mut lumas = vec![150.0; 10];
+        lumas.extend((0..10).map(|i| 16.0 + 12.0 * i as f64));
+        assert_eq!(classify(&stats(&lumas), 10), Transition::FadeIn);
+    }
+
+    #[test]
+    fn test_transitions() {
+        let mut lumas = vec![100.0; 10];
+        lumas.extend([180.0; 20]);
+        assert_eq!(
+            transitions(&[0, 10, 20], &[0, 10], &stats(&lumas)),
+            vec![
+                None,
+                Some(Transition::HardCut),
+                Some(Transition::ForcedSplit)
+            ]
+        );
+    }
+}
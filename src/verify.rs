@@ -0,0 +1,114 @@
+//! `shear verify`: check every chunk start in a scene file can be seeked to
+//! and decoded, before an encoding farm relies on it.
+
+use anyhow::{bail, Result};
+use clap::Args;
+use shear::ffmpeg::{self, SeekProbe};
+use shear::output;
+use std::path::PathBuf;
+
+#[derive(Args, Debug)]
+pub struct VerifyArgs {
+    /// Scene file to verify (text or JSON)
+    #[arg(short, long)]
+    pub scenes: PathBuf,
+
+    /// Video the scene file was made from
+    #[arg(short, long)]
+    pub input: PathBuf,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Status {
+    Ok,
+    /// The seek landed after the boundary
+    Overshoot,
+    /// No frame at or after the boundary could be decoded
+    Unreachable,
+    /// The boundary was reached, but through undecodable packets
+    DecodeErrors(usize),
+}
+
+fn classify(boundary: usize, probe: &SeekProbe) -> Status {
+    match probe.landed {
+        None => Status::Unreachable,
+        Some(landed) if landed > boundary => Status::Overshoot,
+        Some(_) if !probe.reached => Status::Unreachable,
+        Some(_) if probe.decode_errors > 0 => Status::DecodeErrors(probe.decode_errors),
+        Some(_) => Status::Ok,
+    }
+}
+
+/// Seek to each boundary and print the result; fails if any boundary
+/// can't be decoded cleanly.
+pub fn run(args: &VerifyArgs) -> Result<()> {
+    let boundaries = output::read_scene_starts(&args.scenes)?;
+    let mut seeker = ffmpeg::Seeker::open(&args.input)?;
+
+    println!("{:>8}  {:>8}  {:>6}  status", "boundary", "landed", "drift");
+    let mut failures = 0;
+    for &boundary in &boundaries {
+        let probe = seeker.probe(boundary)?;
+        let status = classify(boundary, &probe);
+        let (landed, drift) = match probe.landed {
+            Some(landed) => (
+                landed.to_string(),
+                (landed as i64 - boundary as i64).to_string(),
+            ),
+            None => ("-".to_string(), "-".to_string()),
+        };
+        if status != Status::Ok {
+            failures += 1;
+        }
+        let status = match status {
+            Status::Ok => "ok".to_string(),
+            Status::Overshoot => "FAIL: seek landed past boundary".to_string(),
+            Status::Unreachable => "FAIL: boundary not reachable".to_string(),
+            Status::DecodeErrors(n) => format!("FAIL: {} decode errors", n),
+        };
+        println!("{:>8}  {:>8}  {:>6}  {}", boundary, landed, drift, status);
+    }
+
+    if failures > 0 {
+        bail!(
+            "{} of {} boundaries failed verification",
+            failures,
+            boundaries.len()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn probe(landed: Option<usize>, reached: bool, decode_errors: usize) -> SeekProbe {
+        SeekProbe {
+            landed,
+            reached,
+            decode_errors,
+        }
+    }
+
+    #[test]
+    fn test_classify() {
+        // Landing on an earlier keyframe and decoding forward is normal
+        assert_eq!(classify(100, &probe(Some(96), true, 0)), Status::Ok);
+        assert_eq!(classify(100, &probe(Some(100), true, 0)), Status::Ok);
+        assert_eq!(
+            classify(100, &probe(Some(110), false, 0)),
+            Status::Overshoot
+        );
+        // Decoding skipped from before the boundary to after it
+        assert_eq!(
+            classify(100, &probe(Some(90), false, 0)),
+            Status::Unreachable
+        );
+        assert_eq!(classify(100, &probe(None, false, 0)), Status::Unreachable);
+        assert_eq!(
+            classify(100, &probe(Some(96), true, 2)),
+            Status::DecodeErrors(2)
+        );
+    }
+}
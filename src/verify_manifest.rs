@@ -0,0 +1,34 @@
+//! `shear verify-manifest`: confirm a JSON scene file or manifest written
+//! with `--checksum` matches a source before a farm encodes against it.
+
+use anyhow::{bail, Result};
+use clap::Args;
+use shear::checksum;
+use std::path::PathBuf;
+
+#[derive(Args, Debug)]
+pub struct VerifyManifestArgs {
+    /// JSON scene file or manifest written with --checksum
+    #[arg(short, long)]
+    pub scenes: PathBuf,
+
+    /// Video the scene file should have been made from
+    #[arg(short, long)]
+    pub input: PathBuf,
+}
+
+/// Print whether the input and boundaries match; fails if either doesn't.
+pub fn run(args: &VerifyManifestArgs) -> Result<()> {
+    let result = checksum::verify(&args.scenes, &args.input)?;
+    let status = |ok: bool| if ok { "ok" } else { "MISMATCH" };
+    println!("input       {}", status(result.input));
+    println!("boundaries  {}", status(result.boundaries));
+
+    if !result.input {
+        bail!("{:?} was not made from {:?}", args.scenes, args.input);
+    }
+    if !result.boundaries {
+        bail!("{:?} has been edited since it was written", args.scenes);
+    }
+    Ok(())
+}
@@ -0,0 +1,289 @@
+//! Zone rules for `--zone`: per-chunk encoder setting overrides chosen
+//! from each chunk's statistics, written into the manifest.
+//!
+//! A rule reads `METRIC OP VALUE => SETTING ADJUSTMENT`, e.g.
+//! `complexity>0.8 => crf-4` or `luma<16 => crf+2`. `+N` and `-N` adjust
+//! the encoder's own setting; `=N` replaces it. Rules are applied in the
+//! order given, so a later adjustment builds on an earlier one.
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// Chunk statistic a rule tests.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Metric {
+    /// Mean frame cost relative to the whole video (1.0 = average)
+    Complexity,
+    /// Mean luma in 8-bit units
+    Luma,
+    /// Mean inter cost relative to intra cost
+    Motion,
+    /// Length in seconds
+    Duration,
+    /// Length in frames
+    Frames,
+}
+
+impl Metric {
+    const NAMES: [(&'static str, Metric); 5] = [
+        ("complexity", Metric::Complexity),
+        ("luma", Metric::Luma),
+        ("motion", Metric::Motion),
+        ("duration", Metric::Duration),
+        ("frames", Metric::Frames),
+    ];
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl Comparison {
+    fn holds(self, left: f64, right: f64) -> bool {
+        match self {
+            Comparison::Less => left < right,
+            Comparison::LessOrEqual => left <= right,
+            Comparison::Greater => left > right,
+            Comparison::GreaterOrEqual => left >= right,
+        }
+    }
+}
+
+/// Override of one encoder setting for a chunk.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Override {
+    /// Add to the encoder's setting for the whole video
+    Delta(f64),
+    /// Use this value instead
+    Value(f64),
+}
+
+impl Override {
+    /// This override followed by `next`.
+    fn then(self, next: Override) -> Override {
+        match (self, next) {
+            (Override::Delta(a), Override::Delta(b)) => Override::Delta(a + b),
+            (Override::Value(a), Override::Delta(b)) => Override::Value(a + b),
+            (_, Override::Value(b)) => Override::Value(b),
+        }
+    }
+}
+
+/// One `--zone` rule.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ZoneRule {
+    pub metric: Metric,
+    pub comparison: Comparison,
+    pub threshold: f64,
+    /// Encoder setting name, e.g. `crf`
+    pub setting: String,
+    pub adjustment: Override,
+}
+
+impl fmt::Display for ZoneRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let metric = Metric::NAMES
+            .iter()
+            .find(|(_, metric)| *metric == self.metric)
+            .map_or("?", |(name, _)| name);
+        let comparison = match self.comparison {
+            Comparison::Less => "<",
+            Comparison::LessOrEqual => "<=",
+            Comparison::Greater => ">",
+            Comparison::GreaterOrEqual => ">=",
+        };
+        let adjustment = match self.adjustment {
+            Override::Delta(delta) if delta >= 0.0 => format!("+{}", delta),
+            Override::Delta(delta) => delta.to_string(),
+            Override::Value(value) => format!("={}", value),
+        };
+        write!(
+            f,
+            "{}{}{} => {}{}",
+            metric, comparison, self.threshold, self.setting, adjustment
+        )
+    }
+}
+
+impl FromStr for ZoneRule {
+    type Err = String;
+
+    /// Parse `METRIC OP VALUE => SETTING ADJUSTMENT`, e.g.
+    /// `complexity>0.8 => crf-4`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((condition, action)) = s.split_once("=>") else {
+            return Err("expected METRIC OP VALUE => SETTING ADJUSTMENT, e.g. \
+                        'complexity>0.8 => crf-4'"
+                .to_string());
+        };
+
+        let condition = condition.trim();
+        let name_end = condition
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(condition.len());
+        let (name, rest) = condition.split_at(name_end);
+        let Some(&(_, metric)) = Metric::NAMES.iter().find(|(n, _)| *n == name) else {
+            let names: Vec<&str> = Metric::NAMES.iter().map(|(n, _)| *n).collect();
+            return Err(format!(
+                "unknown metric {:?}, expected one of {}",
+                name,
+                names.join(", ")
+            ));
+        };
+        let rest = rest.trim_start();
+        let (comparison, value) = [
+            ("<=", Comparison::LessOrEqual),
+            (">=", Comparison::GreaterOrEqual),
+            ("<", Comparison::Less),
+            (">", Comparison::Greater),
+        ]
+        .into_iter()
+        .find_map(|(op, comparison)| rest.strip_prefix(op).map(|value| (comparison, value)))
+        .ok_or_else(|| format!("expected <, <=, > or >= after {:?}", name))?;
+        let threshold = parse_number(value)?;
+
+        let action = action.trim();
+        let setting_end = action
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .ok_or_else(|| format!("expected +N, -N or =N after {:?}", action))?;
+        let (setting, adjustment) = action.split_at(setting_end);
+        if setting.is_empty() {
+            return Err("missing encoder setting name before the adjustment".to_string());
+        }
+        let adjustment = adjustment.trim_start();
+        let value = adjustment.get(1..).unwrap_or_default();
+        let adjustment = match adjustment.chars().next() {
+            Some('+') => Override::Delta(parse_number(value)?),
+            Some('-') => Override::Delta(-parse_number(value)?),
+            Some('=') => Override::Value(parse_number(value)?),
+            _ => return Err(format!("expected +N, -N or =N after {:?}", setting)),
+        };
+
+        Ok(ZoneRule {
+            metric,
+            comparison,
+            threshold,
+            setting: setting.to_string(),
+            adjustment,
+        })
+    }
+}
+
+fn parse_number(s: &str) -> Result<f64, String> {
+    s.trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|value| value.is_finite())
+        .ok_or_else(|| format!("invalid number {:?}", s.trim()))
+}
+
+/// Statistics of one chunk that rules are tested against. Unavailable
+/// ones are None, and rules testing them never match.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ChunkMetrics {
+    pub complexity: Option<f64>,
+    pub luma: Option<f64>,
+    pub motion: Option<f64>,
+    pub duration: f64,
+    pub frames: usize,
+}
+
+impl ChunkMetrics {
+    fn get(&self, metric: Metric) -> Option<f64> {
+        match metric {
+            Metric::Complexity => self.complexity,
+            Metric::Luma => self.luma,
+            Metric::Motion => self.motion,
+            Metric::Duration => Some(self.duration),
+            Metric::Frames => Some(self.frames as f64),
+        }
+    }
+}
+
+/// Overrides from the rules that match `metrics`, by setting name, or None
+/// if no rule matches.
+pub fn evaluate(rules: &[ZoneRule], metrics: &ChunkMetrics) -> Option<BTreeMap<String, Override>> {
+    let mut overrides: BTreeMap<String, Override> = BTreeMap::new();
+    for rule in rules {
+        let matches = metrics
+            .get(rule.metric)
+            .is_some_and(|value| rule.comparison.holds(value, rule.threshold));
+        if matches {
+            overrides
+                .entry(rule.setting.clone())
+                .and_modify(|current| *current = current.then(rule.adjustment))
+                .or_insert(rule.adjustment);
+        }
+    }
+    (!overrides.is_empty()).then_some(overrides)
+}
+
+/// True if any rule tests `metric`.
+pub fn uses(rules: &[ZoneRule], metric: Metric) -> bool {
+    rules.iter().any(|rule| rule.metric == metric)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_zone_rule() {
+        let rule: ZoneRule = "complexity>0.8 => crf-4".parse().unwrap();
+        assert_eq!(rule.metric, Metric::Complexity);
+        assert_eq!(rule.comparison, Comparison::Greater);
+        assert_eq!(rule.threshold, 0.8);
+        assert_eq!(rule.setting, "crf");
+        assert_eq!(rule.adjustment, Override::Delta(-4.0));
+        assert_eq!(rule.to_string(), "complexity>0.8 => crf-4");
+
+        let rule: ZoneRule = "luma <= 16=>crf+2".parse().unwrap();
+        assert_eq!(rule.comparison, Comparison::LessOrEqual);
+        assert_eq!(rule.adjustment, Override::Delta(2.0));
+        let rule: ZoneRule = "duration<1 => film_grain=0".parse().unwrap();
+        assert_eq!(rule.setting, "film_grain");
+        assert_eq!(rule.adjustment, Override::Value(0.0));
+
+        assert!("complexity>0.8".parse::<ZoneRule>().is_err());
+        assert!("grain>1 => crf-4".parse::<ZoneRule>().is_err());
+        assert!("luma=16 => crf+2".parse::<ZoneRule>().is_err());
+        assert!("luma<x => crf+2".parse::<ZoneRule>().is_err());
+        assert!("luma<16 => crf".parse::<ZoneRule>().is_err());
+        assert!("luma<16 => +2".parse::<ZoneRule>().is_err());
+        assert!("luma<16 => crf ".parse::<ZoneRule>().is_err());
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let rules: Vec<ZoneRule> = [
+            "complexity>0.8 => crf-4",
+            "luma<16 => crf+2",
+            "luma<16 => preset=6",
+        ]
+        .iter()
+        .map(|rule| rule.parse().unwrap())
+        .collect();
+        let mut metrics = ChunkMetrics {
+            complexity: Some(1.2),
+            luma: Some(12.0),
+            ..Default::default()
+        };
+        let overrides = evaluate(&rules, &metrics).unwrap();
+        assert_eq!(overrides["crf"], Override::Delta(-2.0));
+        assert_eq!(overrides["preset"], Override::Value(6.0));
+
+        // Unavailable metrics never match
+        metrics.luma = None;
+        metrics.complexity = Some(0.5);
+        assert_eq!(evaluate(&rules, &metrics), None);
+        assert!(uses(&rules, Metric::Luma));
+        assert!(!uses(&rules, Metric::Motion));
+    }
+}